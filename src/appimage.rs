@@ -0,0 +1,627 @@
+use crate::archive::{Compression, decompress};
+use crate::tool::{path_to_str, write_to_file};
+use crate::types::OutputFile;
+use anyhow::{Context, Result, bail};
+use guess_target::{Arch, Os, Target};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const SQUASHFS_MAGIC: &[u8; 4] = b"hsqs";
+/// Uncompressed size of a squashfs metadata block.
+const METADATA_SIZE: u64 = 8192;
+/// Fragment index of a file whose tail is stored in its last data block.
+const NO_FRAGMENT: u32 = 0xffff_ffff;
+/// Set in the size of a data or fragment block stored uncompressed.
+const UNCOMPRESSED_BLOCK: u32 = 1 << 24;
+/// Largest desktop entry or icon read from an image.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+const MAX_SYMLINK_DEPTH: usize = 8;
+
+/// Name tokens that end the tool name in an AppImage file name.
+const ARCH_TOKENS: [&str; 10] = [
+    "x86", "amd64", "x64", "arm64", "aarch64", "armhf", "armv7l", "i386", "i686", "linux",
+];
+
+pub(crate) fn is_appimage(s: &str) -> bool {
+    s.to_ascii_lowercase().ends_with(".appimage")
+}
+
+/// File name without its `.AppImage` extension, in any case.
+pub(crate) fn appimage_stem(filename: &str) -> &str {
+    if is_appimage(filename) {
+        &filename[..filename.len() - ".appimage".len()]
+    } else {
+        filename
+    }
+}
+
+/// Whether an AppImage is built for `target`. AppImages rarely name their
+/// architecture unless they ship several, so a name without one is x86_64.
+pub(crate) fn appimage_matches(stem: &str, target: &Target) -> bool {
+    if target.os() != Os::Linux {
+        return false;
+    }
+    let stem = stem.to_ascii_lowercase().replace("x86_64", "amd64");
+    for token in stem.split(['_', '-', '.']) {
+        match token {
+            "amd64" | "x64" => return target.arch() == Arch::X86_64,
+            "arm64" | "aarch64" => return target.arch() == Arch::Aarch64,
+            "armhf" | "armv7l" | "i386" | "i686" | "x86" => return false,
+            _ => {}
+        }
+    }
+    target.arch() == Arch::X86_64
+}
+
+/// Tool name from a file name like `Obsidian-1.5.3` or `nvim-linux-x86_64`:
+/// everything before the version or architecture, lowercased.
+pub(crate) fn appimage_name(stem: &str) -> String {
+    let stem = stem.to_ascii_lowercase();
+    let name: Vec<&str> = stem
+        .split(['-', '_'])
+        .take_while(|token| {
+            !ARCH_TOKENS.contains(token)
+                && !token
+                    .trim_start_matches('v')
+                    .starts_with(|c: char| c.is_ascii_digit())
+        })
+        .collect();
+    if name.is_empty() {
+        stem
+    } else {
+        name.join("-")
+    }
+}
+
+/// Install the desktop entry and icon embedded in the AppImage at `path` as
+/// `applications/ei-<name>.desktop` and `icons/ei-<name>.<ext>` in the user's
+/// data directory, pointing the entry at the installed AppImage.
+pub(crate) fn install_desktop_files(path: &str, name: &str) -> Result<Vec<OutputFile>> {
+    let exec = std::path::absolute(path).unwrap_or(path.into());
+    let (entry, icon) = read_desktop_files(&exec)
+        .with_context(|| format!("Failed to read the desktop entry of {path}"))?;
+    let data_dir = dirs::data_dir().context("Failed to get data_dir")?;
+
+    let mut files = vec![];
+    let icon_path = match icon {
+        Some((data, ext)) => {
+            let icon_path = path_to_str(&data_dir.join("icons").join(format!("ei-{name}.{ext}")));
+            files.push(write_desktop_file(".DirIcon", &icon_path, &data)?);
+            Some(icon_path)
+        }
+        None => None,
+    };
+    let entry = rewrite_desktop_entry(&entry, &path_to_str(&exec), icon_path.as_deref());
+    let entry_path = path_to_str(
+        &data_dir
+            .join("applications")
+            .join(format!("ei-{name}.desktop")),
+    );
+    files.push(write_desktop_file(
+        &format!("{name}.desktop"),
+        &entry_path,
+        entry.as_bytes(),
+    )?);
+    Ok(files)
+}
+
+fn write_desktop_file(origin_path: &str, install_path: &str, data: &[u8]) -> Result<OutputFile> {
+    let mode = Some(0o644);
+    write_to_file(install_path, &mut &data[..], &mode)?;
+    Ok(OutputFile {
+        mode,
+        size: data.len() as u64,
+        origin_path: origin_path.to_string(),
+        is_dir: false,
+        install_path: install_path.to_string(),
+        link: None,
+    })
+}
+
+/// Icon data and the file extension matching its contents.
+type Icon = (Vec<u8>, &'static str);
+
+/// The `.desktop` file at the root of the image, and its icon.
+fn read_desktop_files(path: &Path) -> Result<(String, Option<Icon>)> {
+    let mut fs = SquashFs::open(path)?;
+    let root = fs.lookup("")?;
+    let Inode::Dir {
+        block,
+        offset,
+        size,
+    } = root
+    else {
+        bail!("corrupt squashfs root directory");
+    };
+    let name = fs
+        .read_dir(block, offset, size)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .find(|name| name.ends_with(".desktop"))
+        .context("no .desktop file in the AppImage")?;
+    let inode = fs.lookup(&name)?;
+    let entry = String::from_utf8(fs.read_file(inode)?).context("invalid .desktop file")?;
+
+    let mut candidates = vec![".DirIcon".to_string()];
+    if let Some(icon) = desktop_value(&entry, "Icon")
+        && !icon.contains('/')
+    {
+        candidates.push(format!("{icon}.png"));
+        candidates.push(format!("{icon}.svg"));
+    }
+    let icon = candidates.iter().find_map(|name| {
+        let data = fs.lookup(name).and_then(|inode| fs.read_file(inode)).ok()?;
+        icon_ext(&data).map(|ext| (data, ext))
+    });
+    Ok((entry, icon))
+}
+
+fn icon_ext(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if String::from_utf8_lossy(&data[..data.len().min(1024)]).contains("<svg") {
+        Some("svg")
+    } else {
+        None
+    }
+}
+
+/// Value of `key` in the `[Desktop Entry]` group.
+fn desktop_value<'a>(entry: &'a str, key: &str) -> Option<&'a str> {
+    let mut in_entry = false;
+    for line in entry.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+        } else if in_entry
+            && let Some((k, v)) = line.split_once('=')
+            && k.trim() == key
+        {
+            return Some(v.trim());
+        }
+    }
+    None
+}
+
+/// Point `Exec=` and `TryExec=` at the installed AppImage, keeping the
+/// arguments, and `Icon=` at the installed icon.
+fn rewrite_desktop_entry(entry: &str, exec: &str, icon: Option<&str>) -> String {
+    let quoted = if exec.contains(' ') {
+        format!("\"{exec}\"")
+    } else {
+        exec.to_string()
+    };
+    let mut out = String::new();
+    for line in entry.lines() {
+        if let Some(cmd) = line.strip_prefix("Exec=") {
+            match cmd.trim_start().split_once(' ') {
+                Some((_, args)) => out.push_str(&format!("Exec={quoted} {args}")),
+                None => out.push_str(&format!("Exec={quoted}")),
+            }
+        } else if line.starts_with("TryExec=") {
+            out.push_str(&format!("TryExec={exec}"));
+        } else if let Some(icon) = icon
+            && line.starts_with("Icon=")
+        {
+            out.push_str(&format!("Icon={icon}"));
+        } else {
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Little-endian integer of up to 8 bytes.
+fn le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, b| acc << 8 | *b as u64)
+}
+
+/// Offset of the filesystem image in a type 2 AppImage: the end of the ELF
+/// runtime, which is where its section header table ends.
+fn elf_end(header: &[u8; 64]) -> Result<u64> {
+    if &header[..4] != b"\x7fELF" {
+        bail!("not an AppImage: missing ELF header");
+    }
+    let little = header[5] == 1;
+    let field = |at: usize, len: usize| {
+        let bytes = &header[at..at + len];
+        if little {
+            le(bytes)
+        } else {
+            bytes.iter().fold(0, |acc, b| acc << 8 | *b as u64)
+        }
+    };
+    let (offset, entry_size, count) = match header[4] {
+        1 => (field(0x20, 4), field(0x2e, 2), field(0x30, 2)),
+        2 => (field(0x28, 8), field(0x3a, 2), field(0x3c, 2)),
+        class => bail!("unknown ELF class {class}"),
+    };
+    Ok(offset + entry_size * count)
+}
+
+enum Inode {
+    Dir {
+        block: u64,
+        offset: usize,
+        size: u64,
+    },
+    File {
+        start: u64,
+        size: u64,
+        blocks: Vec<u32>,
+        fragment: u32,
+        fragment_offset: usize,
+    },
+    Symlink(String),
+    Other,
+}
+
+/// Read-only access to the squashfs 4.0 image of an AppImage, enough to read
+/// a few small files by path.
+struct SquashFs {
+    file: File,
+    /// Offset of the image in the file; table offsets are relative to it.
+    base: u64,
+    block_size: u64,
+    compression: Compression,
+    root: u64,
+    inode_table: u64,
+    dir_table: u64,
+    fragment_table: u64,
+}
+
+impl SquashFs {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut header = [0; 64];
+        file.read_exact(&mut header)
+            .context("not an AppImage: file too short")?;
+        let base = elf_end(&header)?;
+        let mut sb = [0; 96];
+        file.seek(SeekFrom::Start(base))?;
+        file.read_exact(&mut sb)
+            .context("not an AppImage: missing squashfs image")?;
+        if &sb[..4] != SQUASHFS_MAGIC {
+            bail!("not an AppImage: missing squashfs image");
+        }
+        if le(&sb[28..30]) != 4 {
+            bail!("unsupported squashfs version {}", le(&sb[28..30]));
+        }
+        let block_size = le(&sb[12..16]);
+        if !(4096..=1 << 20).contains(&block_size) {
+            bail!("invalid squashfs block size {block_size}");
+        }
+        let compression = match le(&sb[20..22]) {
+            1 => Compression::Zlib,
+            4 => Compression::Xz,
+            6 => Compression::Zstd,
+            id => bail!("unsupported squashfs compressor {id}"),
+        };
+        Ok(SquashFs {
+            file,
+            base,
+            block_size,
+            compression,
+            root: le(&sb[32..40]),
+            inode_table: le(&sb[64..72]),
+            dir_table: le(&sb[72..80]),
+            fragment_table: le(&sb[80..88]),
+        })
+    }
+
+    fn read_at(&mut self, pos: u64, len: u64) -> Result<Vec<u8>> {
+        let mut buf = vec![0; len as usize];
+        self.file.seek(SeekFrom::Start(self.base + pos))?;
+        self.file
+            .read_exact(&mut buf)
+            .context("truncated squashfs image")?;
+        Ok(buf)
+    }
+
+    fn inflate(&self, data: &[u8], limit: u64) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        decompress(data, self.compression)?
+            .take(limit)
+            .read_to_end(&mut out)
+            .context("corrupt squashfs block")?;
+        Ok(out)
+    }
+
+    /// `len` bytes of the metadata blocks starting at `table + block`, from
+    /// `offset` into their uncompressed contents.
+    fn metadata(&mut self, table: u64, block: u64, offset: usize, len: usize) -> Result<Vec<u8>> {
+        let mut pos = table + block;
+        let mut buf = vec![];
+        while buf.len() < offset + len {
+            let header = le(&self.read_at(pos, 2)?);
+            let size = header & 0x7fff;
+            let data = self.read_at(pos + 2, size)?;
+            let data = if header & 0x8000 != 0 {
+                data
+            } else {
+                self.inflate(&data, METADATA_SIZE)?
+            };
+            if data.is_empty() {
+                bail!("corrupt squashfs metadata");
+            }
+            buf.extend(data);
+            pos += 2 + size;
+        }
+        Ok(buf[offset..offset + len].to_vec())
+    }
+
+    fn inode(&mut self, reference: u64) -> Result<Inode> {
+        let (table, block, offset) = (self.inode_table, reference >> 16, reference as u16 as usize);
+        let header = self.metadata(table, block, offset, 16)?;
+        let kind = le(&header[..2]);
+        let body =
+            |fs: &mut Self, at: usize, len: usize| fs.metadata(table, block, offset + 16 + at, len);
+        Ok(match kind {
+            1 => {
+                let b = body(self, 0, 16)?;
+                Inode::Dir {
+                    block: le(&b[0..4]),
+                    size: le(&b[8..10]),
+                    offset: le(&b[10..12]) as usize,
+                }
+            }
+            8 => {
+                let b = body(self, 0, 24)?;
+                Inode::Dir {
+                    size: le(&b[4..8]),
+                    block: le(&b[8..12]),
+                    offset: le(&b[18..20]) as usize,
+                }
+            }
+            2 | 9 => {
+                let fixed = if kind == 2 { 16 } else { 40 };
+                let b = body(self, 0, fixed)?;
+                let (start, size, fragment, fragment_offset) = if kind == 2 {
+                    (le(&b[0..4]), le(&b[12..16]), le(&b[4..8]), le(&b[8..12]))
+                } else {
+                    (le(&b[0..8]), le(&b[8..16]), le(&b[28..32]), le(&b[32..36]))
+                };
+                if size > MAX_FILE_SIZE {
+                    bail!("file of {size} bytes is too large");
+                }
+                let fragment = fragment as u32;
+                let count = if fragment == NO_FRAGMENT {
+                    size.div_ceil(self.block_size)
+                } else {
+                    size / self.block_size
+                };
+                let list = body(self, fixed, count as usize * 4)?;
+                Inode::File {
+                    start,
+                    size,
+                    blocks: list.chunks(4).map(|c| le(c) as u32).collect(),
+                    fragment,
+                    fragment_offset: fragment_offset as usize,
+                }
+            }
+            3 | 10 => {
+                let b = body(self, 0, 8)?;
+                let len = le(&b[4..8]) as usize;
+                if len > 4096 {
+                    bail!("corrupt squashfs symlink");
+                }
+                let target = body(self, 8, len)?;
+                Inode::Symlink(String::from_utf8_lossy(&target).into_owned())
+            }
+            _ => Inode::Other,
+        })
+    }
+
+    /// Names and inode references of a directory's entries.
+    fn read_dir(&mut self, block: u64, offset: usize, size: u64) -> Result<Vec<(String, u64)>> {
+        // The listed size counts 3 bytes for the implicit `.` and `..`.
+        let len = size.saturating_sub(3) as usize;
+        let data = self.metadata(self.dir_table, block, offset, len)?;
+        let mut entries = vec![];
+        let mut at = 0;
+        while at + 12 <= data.len() {
+            let count = le(&data[at..at + 4]) + 1;
+            let start = le(&data[at + 4..at + 8]);
+            at += 12;
+            for _ in 0..count {
+                let entry = data.get(at..at + 8).context("corrupt squashfs directory")?;
+                let name_len = le(&entry[6..8]) as usize + 1;
+                let name = data
+                    .get(at + 8..at + 8 + name_len)
+                    .context("corrupt squashfs directory")?;
+                entries.push((
+                    String::from_utf8_lossy(name).into_owned(),
+                    start << 16 | le(&entry[0..2]),
+                ));
+                at += 8 + name_len;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The inode at `path`, following symlinks on the way.
+    fn lookup(&mut self, path: &str) -> Result<Inode> {
+        let mut parts: Vec<String> = path
+            .split('/')
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect();
+        let mut depth = 0;
+        loop {
+            let mut inode = self.inode(self.root)?;
+            let mut redirect = None;
+            for (i, name) in parts.iter().enumerate() {
+                let Inode::Dir {
+                    block,
+                    offset,
+                    size,
+                } = inode
+                else {
+                    bail!("{path} not found");
+                };
+                let (_, reference) = self
+                    .read_dir(block, offset, size)?
+                    .into_iter()
+                    .find(|(entry, _)| entry == name)
+                    .with_context(|| format!("{path} not found"))?;
+                inode = self.inode(reference)?;
+                if let Inode::Symlink(target) = &inode {
+                    redirect = Some(resolve_link(&parts[..i], target, &parts[i + 1..]));
+                    break;
+                }
+            }
+            match redirect {
+                Some(next) => {
+                    depth += 1;
+                    if depth > MAX_SYMLINK_DEPTH {
+                        bail!("too many levels of symlinks in {path}");
+                    }
+                    parts = next;
+                }
+                None => return Ok(inode),
+            }
+        }
+    }
+
+    fn read_file(&mut self, inode: Inode) -> Result<Vec<u8>> {
+        let Inode::File {
+            start,
+            size,
+            blocks,
+            fragment,
+            fragment_offset,
+        } = inode
+        else {
+            bail!("not a regular file");
+        };
+        let mut out = vec![];
+        let mut pos = start;
+        for block in blocks {
+            let len = (block & !UNCOMPRESSED_BLOCK) as u64;
+            if len == 0 {
+                // A sparse block.
+                out.resize(out.len() + self.block_size as usize, 0);
+                continue;
+            }
+            let data = self.read_at(pos, len)?;
+            pos += len;
+            if block & UNCOMPRESSED_BLOCK != 0 {
+                out.extend(data);
+            } else {
+                out.extend(self.inflate(&data, self.block_size)?);
+            }
+        }
+        if fragment != NO_FRAGMENT {
+            let index = fragment as u64;
+            let table = le(&self.read_at(self.fragment_table + index / 512 * 8, 8)?);
+            let entry = self.metadata(table, 0, (index % 512) as usize * 16, 16)?;
+            let frag_size = le(&entry[8..12]) as u32;
+            let data = self.read_at(le(&entry[0..8]), (frag_size & !UNCOMPRESSED_BLOCK) as u64)?;
+            let data = if frag_size & UNCOMPRESSED_BLOCK != 0 {
+                data
+            } else {
+                self.inflate(&data, self.block_size)?
+            };
+            let tail = (size % self.block_size) as usize;
+            out.extend(
+                data.get(fragment_offset..fragment_offset + tail)
+                    .context("corrupt squashfs fragment")?,
+            );
+        }
+        out.truncate(size as usize);
+        Ok(out)
+    }
+}
+
+/// Path components after following a symlink at `dir/<link>` to `target`.
+fn resolve_link(dir: &[String], target: &str, rest: &[String]) -> Vec<String> {
+    let mut parts = if target.starts_with('/') {
+        vec![]
+    } else {
+        dir.to_vec()
+    };
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part.to_string()),
+        }
+    }
+    parts.extend(rest.iter().cloned());
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_appimage_name() {
+        for (stem, name) in [
+            ("Obsidian-1.5.3", "obsidian"),
+            ("nvim-linux-x86_64", "nvim"),
+            ("Cursor-0.42.3-x86_64", "cursor"),
+            ("kdenlive-24.02.1-x86_64", "kdenlive"),
+            ("Heroic-v2.15.2", "heroic"),
+            ("balena_etcher", "balena-etcher"),
+            ("x86_64", "x86_64"),
+        ] {
+            assert_eq!(appimage_name(stem), name, "{stem}");
+        }
+        assert_eq!(appimage_stem("Obsidian-1.5.3.AppImage"), "Obsidian-1.5.3");
+        assert_eq!(appimage_stem("tool.appimage"), "tool");
+    }
+
+    #[test]
+    fn test_rewrite_desktop_entry() {
+        let entry = "[Desktop Entry]\nName=Tool\nExec=AppRun --no-sandbox %U\nTryExec=tool\nIcon=tool\n\n[Desktop Action new]\nExec=AppRun --new\n";
+        let out = rewrite_desktop_entry(
+            entry,
+            "/home/u/.ei/tool",
+            Some("/home/u/.local/share/icons/ei-tool.png"),
+        );
+        assert_eq!(
+            out,
+            "[Desktop Entry]\nName=Tool\nExec=/home/u/.ei/tool --no-sandbox %U\nTryExec=/home/u/.ei/tool\nIcon=/home/u/.local/share/icons/ei-tool.png\n\n[Desktop Action new]\nExec=/home/u/.ei/tool --new\n"
+        );
+        assert_eq!(desktop_value(entry, "Icon"), Some("tool"));
+        assert_eq!(desktop_value(entry, "Missing"), None);
+        let out = rewrite_desktop_entry("Exec=tool\nIcon=tool\n", "/opt/my apps/tool", None);
+        assert_eq!(out, "Exec=\"/opt/my apps/tool\"\nIcon=tool\n");
+    }
+
+    #[test]
+    fn test_elf_end() {
+        let mut header = [0; 64];
+        header[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        header[0x28..0x30].copy_from_slice(&0x1000u64.to_le_bytes());
+        header[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        header[0x3c..0x3e].copy_from_slice(&30u16.to_le_bytes());
+        assert_eq!(elf_end(&header).unwrap(), 0x1000 + 64 * 30);
+        header[4] = 1;
+        header[0x20..0x24].copy_from_slice(&0x800u32.to_le_bytes());
+        header[0x2e..0x30].copy_from_slice(&40u16.to_le_bytes());
+        header[0x30..0x32].copy_from_slice(&10u16.to_le_bytes());
+        assert_eq!(elf_end(&header).unwrap(), 0x800 + 400);
+        assert!(elf_end(&[0; 64]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        let parts = |s: &str| s.split('/').map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            resolve_link(&[], "usr/share/icons/tool.png", &[]),
+            parts("usr/share/icons/tool.png")
+        );
+        assert_eq!(
+            resolve_link(&parts("usr/bin"), "../lib/tool", &parts("x")),
+            parts("usr/lib/tool/x")
+        );
+        assert_eq!(resolve_link(&parts("a"), "/b", &[]), parts("b"));
+    }
+}
@@ -0,0 +1,638 @@
+use crate::package::read_rpm_header;
+use crate::types::Link;
+use anyhow::{Context, Result};
+use easy_archive::Fmt;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+use tracing::trace;
+
+/// An archive member as described by its header.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Entry {
+    pub(crate) path: String,
+    pub(crate) size: u64,
+    pub(crate) mode: Option<u32>,
+    pub(crate) is_dir: bool,
+    pub(crate) link: Option<Link>,
+}
+
+/// How the entries of a file are stored.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Container {
+    Archive(Fmt),
+    /// The cpio payload of an RPM package, read from the `.rpm` itself.
+    Rpm,
+}
+
+impl From<Fmt> for Container {
+    fn from(fmt: Fmt) -> Self {
+        Container::Archive(fmt)
+    }
+}
+
+/// Maps an entry to where it is installed, or drops it, before any check
+/// sees it. See `package::system_entry`.
+pub(crate) type Remap = fn(Entry) -> Option<Entry>;
+
+/// `./bin/ei` and `bin\ei` both become `bin/ei`; directories lose their
+/// trailing slash.
+fn clean_entry_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut path = path.as_str();
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path.trim_end_matches('/').to_string()
+}
+
+/// Why `path` can't be used below the extraction directory, if it can't.
+fn unsafe_reason(path: &str) -> Option<&'static str> {
+    let first = path.split('/').next().unwrap_or_default();
+    if path.starts_with('/') {
+        Some("absolute path")
+    } else if first.len() >= 2
+        && first.as_bytes()[1] == b':'
+        && first.as_bytes()[0].is_ascii_alphabetic()
+    {
+        Some("Windows drive prefix")
+    } else {
+        None
+    }
+}
+
+/// Reject a cleaned entry path that could be written outside the directory
+/// the archive is extracted to, naming the entry in the error.
+pub(crate) fn check_entry_path(path: &str) -> Result<()> {
+    let reason = match unsafe_reason(path) {
+        Some(reason) => reason,
+        None if path.split('/').any(|c| c == "..") => "parent directory component",
+        None => return Ok(()),
+    };
+    anyhow::bail!("Refusing to extract archive entry {path:?}: {reason}")
+}
+
+/// Reject a symlink at `path` whose `target` resolves outside the directory
+/// `path` is relative to. `..` is only allowed before the first name, since
+/// going up from a name that is itself a link leaves the checked path.
+pub(crate) fn check_symlink(path: &str, target: &str) -> Result<()> {
+    let target = target.replace('\\', "/");
+    if let Some(reason) = unsafe_reason(&target) {
+        anyhow::bail!("Refusing to extract archive entry {path:?}: link to {reason} {target:?}");
+    }
+    let mut depth = path.split('/').count() - 1;
+    let mut descended = false;
+    for c in target.split('/') {
+        match c {
+            "" | "." => {}
+            ".." if depth == 0 || descended => anyhow::bail!(
+                "Refusing to extract archive entry {path:?}: link to {target:?} points outside the install directory"
+            ),
+            ".." => depth -= 1,
+            _ => {
+                depth += 1;
+                descended = true;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reject entries below a symlink of the same archive, which would be
+/// written wherever that link points.
+pub(crate) fn check_link_parents(entries: &[Entry]) -> Result<()> {
+    let links: HashSet<&str> = entries
+        .iter()
+        .filter(|e| matches!(e.link, Some(Link::Symlink(_))))
+        .map(|e| e.path.as_str())
+        .collect();
+    for entry in entries {
+        let mut parent = entry.path.as_str();
+        while let Some((p, _)) = parent.rsplit_once('/') {
+            if links.contains(p) {
+                anyhow::bail!(
+                    "Refusing to extract archive entry {:?}: it is inside the symlink {p:?}",
+                    entry.path
+                );
+            }
+            parent = p;
+        }
+    }
+    Ok(())
+}
+
+/// `check_entry_path` for an entry and whatever it links to.
+fn check_entry(entry: &Entry) -> Result<()> {
+    check_entry_path(&entry.path)?;
+    match &entry.link {
+        Some(Link::Symlink(target)) => check_symlink(&entry.path, target),
+        Some(Link::Hardlink(target)) => check_entry_path(target)
+            .with_context(|| format!("hard link {:?} is unsafe", entry.path)),
+        None => Ok(()),
+    }
+}
+
+/// Call `f` with every entry of the archive at `path` and a reader over its
+/// content. Entries are decompressed as they are read, so only one small
+/// buffer is held at a time, whatever the size of the archive.
+pub(crate) fn for_each_entry(
+    path: &Path,
+    container: impl Into<Container>,
+    f: impl FnMut(&Entry, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    for_each_mapped_entry(path, container, None, f)
+}
+
+/// `for_each_entry` over the entries `remap` keeps, as it maps them.
+pub(crate) fn for_each_mapped_entry(
+    path: &Path,
+    container: impl Into<Container>,
+    remap: Option<Remap>,
+    mut f: impl FnMut(&Entry, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut f = |entry: &Entry, reader: &mut dyn Read| {
+        // FIXME: remove __MACOSX
+        if entry.path.is_empty() || entry.path.starts_with("__MACOSX") {
+            return Ok(());
+        }
+        let mapped;
+        let entry = match remap {
+            Some(remap) => match remap(entry.clone()) {
+                Some(e) => {
+                    mapped = e;
+                    &mapped
+                }
+                None => return Ok(()),
+            },
+            None => entry,
+        };
+        check_entry(entry)?;
+        f(entry, reader)
+    };
+    let fmt = match container.into() {
+        Container::Archive(fmt) => fmt,
+        Container::Rpm => {
+            let mut file = BufReader::new(file);
+            let header = read_rpm_header(&mut file)?;
+            return cpio_entries(decompress(file, header.compression()?)?, &mut f);
+        }
+    };
+    match fmt {
+        #[cfg(feature = "tar")]
+        Fmt::Tar => tar_entries(file, &mut f),
+        #[cfg(feature = "tar-gz")]
+        Fmt::TarGz => tar_entries(decompress(file, Compression::Gzip)?, &mut f),
+        #[cfg(feature = "tar-xz")]
+        Fmt::TarXz => tar_entries(decompress(file, Compression::Xz)?, &mut f),
+        #[cfg(feature = "tar-bz")]
+        Fmt::TarBz => tar_entries(decompress(file, Compression::Bzip2)?, &mut f),
+        #[cfg(feature = "tar-zstd")]
+        Fmt::TarZstd => tar_entries(decompress(file, Compression::Zstd)?, &mut f),
+        #[cfg(feature = "zip")]
+        Fmt::Zip => zip_entries(file, &mut f),
+        // 7z decodes solid blocks as a whole; easy-archive unpacks it in memory.
+        _ => decoded_entries(file, fmt, &mut f),
+    }
+}
+
+/// Compression of a single stream: a tarball, an RPM payload, a squashfs
+/// block, a compressed executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Compression {
+    None,
+    Gzip,
+    /// zlib framing of deflate, without the gzip header.
+    Zlib,
+    Xz,
+    Bzip2,
+    Zstd,
+}
+
+/// Extensions of files that are a single compressed stream.
+pub(crate) const COMPRESSED_EXTS: [&str; 4] = [".gz", ".xz", ".zst", ".bz2"];
+
+impl Compression {
+    /// Compression of a single compressed file like `tool.gz`, and the name
+    /// of the file it decompresses to. Compressed tarballs are archives.
+    pub(crate) fn guess_file(name: &str) -> Option<(Self, &str)> {
+        if Fmt::guess(name).is_some() {
+            return None;
+        }
+        let compression = [
+            Compression::Gzip,
+            Compression::Xz,
+            Compression::Zstd,
+            Compression::Bzip2,
+        ];
+        COMPRESSED_EXTS
+            .iter()
+            .zip(compression)
+            .find_map(|(ext, compression)| {
+                name.strip_suffix(ext)
+                    .filter(|stem| !stem.is_empty())
+                    .map(|stem| (compression, stem))
+            })
+    }
+}
+
+/// Decompress `reader` as it is read. Concatenated streams are read through
+/// to the end.
+pub(crate) fn decompress<'a>(
+    reader: impl Read + 'a,
+    compression: Compression,
+) -> Result<Box<dyn Read + 'a>> {
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        #[cfg(feature = "tar-gz")]
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(BufReader::new(reader))),
+        #[cfg(feature = "tar-gz")]
+        Compression::Zlib => Box::new(flate2::read::ZlibDecoder::new(reader)),
+        #[cfg(feature = "tar-xz")]
+        Compression::Xz => Box::new(liblzma::read::XzDecoder::new_multi_decoder(BufReader::new(
+            reader,
+        ))),
+        #[cfg(feature = "tar-bz")]
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(BufReader::new(reader))),
+        #[cfg(feature = "tar-zstd")]
+        Compression::Zstd => Box::new(
+            zstd::stream::read::Decoder::new(reader).context("Failed to read zstd stream")?,
+        ),
+        #[allow(unreachable_patterns)]
+        _ => anyhow::bail!("ei was built without {compression:?} support"),
+    })
+}
+
+/// Headers of every entry, without extracting anything.
+pub(crate) fn list(
+    path: &Path,
+    container: impl Into<Container>,
+    remap: Option<Remap>,
+) -> Result<Vec<Entry>> {
+    let mut entries = vec![];
+    for_each_mapped_entry(path, container, remap, |entry, _| {
+        entries.push(entry.clone());
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+/// Write the entry at `entry_path` to `dst`, e.g. an archive nested in
+/// another one.
+pub(crate) fn extract_entry(
+    path: &Path,
+    container: impl Into<Container>,
+    entry_path: &str,
+    dst: &Path,
+) -> Result<()> {
+    let mut found = false;
+    for_each_entry(path, container, |entry, reader| {
+        if entry.path == entry_path {
+            let mut file =
+                File::create(dst).with_context(|| format!("Failed to create {}", dst.display()))?;
+            std::io::copy(reader, &mut file)
+                .with_context(|| format!("Failed to extract {entry_path}"))?;
+            found = true;
+        }
+        Ok(())
+    })?;
+    if !found {
+        anyhow::bail!("{entry_path} not found in {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tar")]
+fn tar_entries(
+    reader: impl Read,
+    f: &mut impl FnMut(&Entry, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().context("Failed to read tar archive")? {
+        let mut entry = entry.context("Failed to read tar entry")?;
+        let kind = entry.header().entry_type();
+        let path = clean_entry_path(&entry.path()?.to_string_lossy());
+        let link = if kind.is_symlink() || kind.is_hard_link() {
+            let target = entry
+                .link_name()?
+                .with_context(|| format!("link {path:?} has no target"))?
+                .to_string_lossy()
+                .to_string();
+            Some(if kind.is_symlink() {
+                Link::Symlink(target)
+            } else {
+                Link::Hardlink(clean_entry_path(&target))
+            })
+        } else if kind.is_file() || kind.is_dir() {
+            None
+        } else {
+            trace!("skip tar entry {path:?} of type {kind:?}");
+            continue;
+        };
+        let header = Entry {
+            path,
+            size: entry.size(),
+            mode: entry.header().mode().ok(),
+            is_dir: kind.is_dir(),
+            link,
+        };
+        f(&header, &mut entry)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "zip")]
+fn zip_entries(file: File, f: &mut impl FnMut(&Entry, &mut dyn Read) -> Result<()>) -> Result<()> {
+    let mut archive =
+        zip::ZipArchive::new(BufReader::new(file)).context("Failed to read zip archive")?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).context("Failed to read zip entry")?;
+        let path = clean_entry_path(file.name());
+        // A zip symlink stores its target as the entry's content.
+        let link = if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)
+                .with_context(|| format!("Failed to read link {path:?}"))?;
+            Some(Link::Symlink(target))
+        } else {
+            None
+        };
+        let header = Entry {
+            path,
+            size: if link.is_some() { 0 } else { file.size() },
+            mode: file.unix_mode(),
+            is_dir: file.is_dir(),
+            link,
+        };
+        f(&header, &mut file)?;
+    }
+    Ok(())
+}
+
+const CPIO_HEADER_LEN: usize = 110;
+/// Upper bound for an entry name or link target, against corrupt input.
+const CPIO_NAME_MAX: u64 = 64 << 10;
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Bytes of padding after `len` bytes to the next multiple of four.
+fn pad4(len: u64) -> u64 {
+    (4 - len % 4) % 4
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> Result<()> {
+    let skipped = std::io::copy(&mut reader.take(len), &mut std::io::sink())?;
+    if skipped < len {
+        anyhow::bail!("Unexpected end of cpio archive");
+    }
+    Ok(())
+}
+
+/// Entries of a cpio archive in the "new ASCII" format (`070701`, or
+/// `070702` with checksums) that RPM payloads use. Hard links share an
+/// inode and only the last of them carries the content, so the others are
+/// passed on after it, as links to it.
+fn cpio_entries(
+    mut reader: impl Read,
+    f: &mut impl FnMut(&Entry, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let mut pending: HashMap<u32, Vec<(String, u32)>> = HashMap::new();
+    let mut header = [0u8; CPIO_HEADER_LEN];
+    loop {
+        reader
+            .read_exact(&mut header)
+            .context("Failed to read cpio header")?;
+        if !matches!(&header[..6], b"070701" | b"070702") {
+            anyhow::bail!("Not a cpio archive in the new ASCII format");
+        }
+        // c_ino, c_mode, c_uid, c_gid, c_nlink, c_mtime, c_filesize, ...,
+        // c_namesize: eight hex digits each.
+        let field = |i: usize| -> Result<u32> {
+            let hex = std::str::from_utf8(&header[6 + i * 8..14 + i * 8])?;
+            u32::from_str_radix(hex, 16).context("Invalid cpio header")
+        };
+        let (ino, mode, nlink) = (field(0)?, field(1)?, field(4)?);
+        let size = field(6)? as u64;
+        let name_len = field(11)? as u64;
+        if name_len > CPIO_NAME_MAX {
+            anyhow::bail!("cpio entry name too long");
+        }
+        let mut name = vec![0; name_len as usize];
+        reader
+            .read_exact(&mut name)
+            .context("Failed to read cpio entry name")?;
+        skip(&mut reader, pad4(CPIO_HEADER_LEN as u64 + name_len))?;
+        let name = String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(&name)).to_string();
+        if name == "TRAILER!!!" {
+            return Ok(());
+        }
+
+        let path = clean_entry_path(&name);
+        let kind = mode & S_IFMT;
+        let mut data = (&mut reader).take(size);
+        if kind == S_IFREG && nlink > 1 && size == 0 {
+            pending.entry(ino).or_default().push((path, mode & 0o7777));
+        } else if matches!(kind, S_IFREG | S_IFDIR | S_IFLNK) {
+            let link = if kind == S_IFLNK {
+                if size > CPIO_NAME_MAX {
+                    anyhow::bail!("{path}: cpio link target too long");
+                }
+                let mut target = String::new();
+                data.read_to_string(&mut target)
+                    .with_context(|| format!("Failed to read link {path:?}"))?;
+                Some(Link::Symlink(target))
+            } else {
+                None
+            };
+            let entry = Entry {
+                path,
+                size: if link.is_some() { 0 } else { size },
+                mode: Some(mode & 0o7777),
+                is_dir: kind == S_IFDIR,
+                link,
+            };
+            f(&entry, &mut data)?;
+            for (path, mode) in pending.remove(&ino).unwrap_or_default() {
+                let link = Entry {
+                    path,
+                    size: 0,
+                    mode: Some(mode),
+                    is_dir: false,
+                    link: Some(Link::Hardlink(entry.path.clone())),
+                };
+                f(&link, &mut std::io::empty())?;
+            }
+        } else {
+            trace!("skip cpio entry {path:?} of mode {mode:o}");
+        }
+        let rest = data.limit();
+        skip(&mut reader, rest + pad4(size))?;
+    }
+}
+
+fn decoded_entries(
+    mut file: File,
+    fmt: Fmt,
+    f: &mut impl FnMut(&Entry, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+    for file in fmt.decode(bytes)? {
+        let header = Entry {
+            path: clean_entry_path(&file.path),
+            size: file.buffer.len() as u64,
+            mode: file.mode,
+            is_dir: file.is_dir,
+            link: None,
+        };
+        f(&header, &mut file.buffer.as_slice())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Compression, Entry, check_entry_path, check_link_parents, check_symlink, clean_entry_path,
+    };
+    use crate::types::Link;
+
+    #[test]
+    fn test_clean_entry_path() {
+        assert_eq!(clean_entry_path("./bin/ei"), "bin/ei");
+        assert_eq!(clean_entry_path("bin\\ei.exe"), "bin/ei.exe");
+        assert_eq!(clean_entry_path("ei-x86_64/"), "ei-x86_64");
+        assert_eq!(clean_entry_path("./"), "");
+    }
+
+    #[test]
+    fn test_guess_compressed_file() {
+        assert_eq!(
+            Compression::guess_file("rust-analyzer-x86_64-unknown-linux-gnu.gz"),
+            Some((Compression::Gzip, "rust-analyzer-x86_64-unknown-linux-gnu"))
+        );
+        assert_eq!(
+            Compression::guess_file("tool-x86_64-pc-windows-msvc.exe.zst"),
+            Some((Compression::Zstd, "tool-x86_64-pc-windows-msvc.exe"))
+        );
+        assert_eq!(
+            Compression::guess_file("tool.bz2"),
+            Some((Compression::Bzip2, "tool"))
+        );
+        assert_eq!(Compression::guess_file("tool.tar.xz"), None);
+        assert_eq!(Compression::guess_file("tool.zip"), None);
+        assert_eq!(Compression::guess_file(".gz"), None);
+    }
+
+    #[test]
+    fn test_check_entry_path() {
+        assert!(check_entry_path("ei/bin/ei").is_ok());
+        assert!(check_entry_path("ei/..hidden/x").is_ok());
+        for path in [
+            "../../.bashrc",
+            "ei/../../x",
+            "/etc/x",
+            "//server/share/x",
+            "C:/Windows/x",
+            "c:x",
+        ] {
+            let err = check_entry_path(&clean_entry_path(path)).unwrap_err();
+            assert!(err.to_string().contains(&clean_entry_path(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn test_check_symlink() {
+        assert!(check_symlink("node/bin/npm", "../lib/node_modules/npm/bin/npm-cli.js").is_ok());
+        assert!(check_symlink("lib/libfoo.so", "libfoo.so.1").is_ok());
+        assert!(check_symlink("bin/x", "../../.bashrc").is_err());
+        assert!(check_symlink("x", "../x").is_err());
+        assert!(check_symlink("bin/sh", "/bin/sh").is_err());
+        assert!(check_symlink("bin/x", "C:\\Windows\\x").is_err());
+        assert!(check_symlink("a/x", "b/../../y").is_err());
+    }
+
+    #[test]
+    fn test_check_link_parents() {
+        let entry = |path: &str, link: Option<&str>| Entry {
+            path: path.to_string(),
+            link: link.map(|t| Link::Symlink(t.to_string())),
+            ..Default::default()
+        };
+        let ok = [
+            entry("bin/npm", Some("../lib/npm.js")),
+            entry("lib/npm.js", None),
+        ];
+        assert!(check_link_parents(&ok).is_ok());
+        let escape = [entry("d", Some(".")), entry("d/x", Some("../../etc"))];
+        assert!(check_link_parents(&escape).is_err());
+    }
+
+    #[test]
+    fn test_cpio_name_too_long() {
+        // A header claiming a 4 GiB entry name.
+        let header = format!(
+            "070701{}{:08x}{}",
+            "0".repeat(8 * 11),
+            u32::MAX,
+            "0".repeat(8)
+        );
+        let err = super::cpio_entries(header.as_bytes(), &mut |_, _| Ok(())).unwrap_err();
+        assert_eq!(err.to_string(), "cpio entry name too long");
+    }
+
+    #[cfg(feature = "tar-gz")]
+    #[test]
+    fn test_tar_gz_entries() {
+        use super::{extract_entry, list};
+        use easy_archive::Fmt;
+
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            vec![],
+            flate2::Compression::default(),
+        ));
+        for (path, content) in [("ei/bin/ei", &b"binary"[..]), ("ei/README.md", b"# ei")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content).unwrap();
+        }
+        for (kind, path, target) in [
+            (tar::EntryType::Symlink, "ei/bin/ei-link", "ei"),
+            (tar::EntryType::Link, "ei/bin/ei-hard", "ei/bin/ei"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_size(0);
+            builder.append_link(&mut header, path, target).unwrap();
+        }
+        let bytes = builder.into_inner().unwrap().finish().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("ei-archive-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ei.tar.gz");
+        std::fs::write(&path, bytes).unwrap();
+
+        let entries = list(&path, Fmt::TarGz, None).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].path, "ei/bin/ei");
+        assert_eq!(entries[0].size, 6);
+        assert_eq!(entries[0].mode, Some(0o755));
+        assert_eq!(entries[2].link, Some(Link::Symlink("ei".to_string())));
+        assert_eq!(
+            entries[3].link,
+            Some(Link::Hardlink("ei/bin/ei".to_string()))
+        );
+
+        let dst = dir.join("ei");
+        extract_entry(&path, Fmt::TarGz, "ei/bin/ei", &dst).unwrap();
+        assert_eq!(std::fs::read(&dst).unwrap(), b"binary");
+        assert!(extract_entry(&path, Fmt::TarGz, "missing", &dst).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
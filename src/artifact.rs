@@ -10,6 +10,11 @@ pub(crate) struct GhArtifact {
     /// the releases listing endpoint).
     #[serde(default)]
     pub(crate) url: Option<String>,
+    /// Content digest computed by GitHub (e.g. `sha256:<hex>`). Absent for
+    /// assets uploaded before GitHub started recording digests, and for
+    /// assets scraped from HTML.
+    #[serde(default)]
+    pub(crate) digest: Option<String>,
 }
 
 // Manually implement PartialEq and Hash to ensure it will always produce the
@@ -35,4 +40,37 @@ impl Hash for GhArtifact {
 #[derive(Debug, Default, Deserialize)]
 pub(crate) struct GhArtifacts {
     pub(crate) assets: HashSet<GhArtifact>,
+    /// Release tag the assets belong to (`tag_name` in the releases API).
+    #[serde(default)]
+    pub(crate) tag_name: Option<String>,
+}
+
+/// A release asset selected for installation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ArtifactUrl {
+    /// Tool name inferred from the asset filename.
+    pub(crate) name: String,
+    pub(crate) url: String,
+    /// Checksum asset published alongside it (`<file>.sha256`,
+    /// `checksums.txt`, `SHA256SUMS`, ...).
+    pub(crate) checksum_url: Option<String>,
+    /// Digest reported by the GitHub API for the asset (`sha256:<hex>`).
+    pub(crate) digest: Option<String>,
+    /// Detached signature published alongside it (`<file>.minisig`, `<file>.sig`).
+    pub(crate) signature_url: Option<String>,
+    /// Expected SHA-256 recorded in a lockfile.
+    pub(crate) sha256: Option<String>,
+}
+
+impl ArtifactUrl {
+    pub(crate) fn new(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            checksum_url: None,
+            digest: None,
+            signature_url: None,
+            sha256: None,
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use crate::install::install;
+use crate::types::Output;
+use crate::{InstallConfig, record_install};
+use anyhow::{Context, Result};
+use tokio::task::JoinSet;
+
+/// Sources listed one per line. Blank lines and `#` comments, whole-line
+/// or after whitespace, are ignored.
+pub(crate) fn parse_source_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| match line.find(" #").or_else(|| line.find("\t#")) {
+            Some(i) => &line[..i],
+            None => line,
+        })
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Read a source list from `path`, or from stdin when it is `-`.
+pub(crate) fn read_source_list(path: &str) -> Result<Vec<String>> {
+    let content = if path == "-" {
+        std::io::read_to_string(std::io::stdin()).context("Failed to read sources from stdin")?
+    } else {
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?
+    };
+    Ok(parse_source_list(&content))
+}
+
+/// Install several sources concurrently. Downloads share one limit across
+/// all of them; receipts are written once every install has finished.
+pub(crate) async fn install_batch(sources: &[(String, InstallConfig)]) -> Result<()> {
+    let mut tasks = JoinSet::new();
+    for (i, (source, config)) in sources.iter().cloned().enumerate() {
+        tasks.spawn(async move { (i, install(&source, &config).await) });
+    }
+
+    let mut results: Vec<Option<Result<Output>>> = sources.iter().map(|_| None).collect();
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok((i, res)) => results[i] = Some(res),
+            Err(e) => eprintln!("Warning: {e}"),
+        }
+    }
+
+    let mut succeeded = vec![];
+    let mut failed = vec![];
+    for ((source, config), res) in sources.iter().zip(results) {
+        match res.unwrap_or_else(|| Err(anyhow::anyhow!("install aborted"))) {
+            Ok(output) => {
+                record_install(source, config, &output);
+                succeeded.push(source.as_str());
+            }
+            Err(e) => failed.push((source.as_str(), e)),
+        }
+    }
+
+    let quiet = sources.iter().all(|(_, config)| config.quiet);
+    if !quiet {
+        println!();
+        println!("Installed {} of {} sources", succeeded.len(), sources.len());
+        for source in &succeeded {
+            println!("  ok      {source}");
+        }
+        for (source, e) in &failed {
+            println!("  failed  {source}: {e:#}");
+        }
+    }
+    if !failed.is_empty() {
+        let names: Vec<&str> = failed.iter().map(|(s, _)| *s).collect();
+        anyhow::bail!("Failed to install: {}", names.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_source_list;
+
+    #[test]
+    fn test_parse_source_list() {
+        let content = "# tools\nhttps://github.com/ip7z/7zip/releases/tag/25.01\n\n  ahaoboy/ansi2  \nnushell/nushell # shell\n\t# indented comment\n";
+        assert_eq!(
+            parse_source_list(content),
+            [
+                "https://github.com/ip7z/7zip/releases/tag/25.01",
+                "ahaoboy/ansi2",
+                "nushell/nushell"
+            ]
+        );
+    }
+}
@@ -0,0 +1,593 @@
+use crate::config::PersistentConfig;
+use crate::env::{cache_dir, get_cache_dir};
+use crate::file_lock::FileLock;
+use crate::receipt::format_timestamp;
+use crate::tool::print_table;
+use crate::verify::{sha256_file, sha256_hex};
+use anyhow::{Context, Result};
+use easy_archive::human_size;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::trace;
+
+pub const DEFAULT_CACHE_INDEX_NAME: &str = "index.json";
+const BLOBS_DIR: &str = "blobs";
+pub(crate) const PARTIAL_DIR: &str = "partial";
+
+/// Cap on the cached content when `cache_size` isn't configured: 2 GiB.
+pub const DEFAULT_CACHE_SIZE: u64 = 2 << 30;
+
+/// A cache hit refreshes `last_used` at most this often, in seconds, so
+/// reading the cache doesn't rewrite the index every time.
+const TOUCH_INTERVAL: u64 = 3600;
+
+/// Set by `--offline`: every download must be served from the cache.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Serializes read-modify-write cycles of the index within this process.
+/// Other processes are kept out by a file lock next to the index.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Content this process read from or stored in the cache. Installs may
+/// still be verifying or extracting it, so eviction leaves it alone.
+static IN_USE: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn mark_in_use(sha256: &str) {
+    IN_USE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(sha256.to_string());
+}
+
+fn in_use() -> BTreeSet<String> {
+    IN_USE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub(crate) fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub(crate) fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// The error for a download `--offline` can't serve.
+pub(crate) fn offline_miss(url: &str) -> anyhow::Error {
+    anyhow::anyhow!("{url} is not in the download cache (running with --offline)")
+}
+
+/// Downloaded URLs and the content they resolved to. The content itself is
+/// stored once per SHA-256 under `blobs/`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+struct CacheIndex {
+    #[serde(default)]
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CacheEntry {
+    sha256: String,
+    size: u64,
+    /// Seconds since the Unix epoch.
+    cached_at: u64,
+    last_used: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn blob_path(dir: &Path, sha256: &str) -> PathBuf {
+    dir.join(BLOBS_DIR).join(sha256)
+}
+
+impl CacheIndex {
+    fn load(dir: &Path) -> Self {
+        std::fs::read_to_string(dir.join(DEFAULT_CACHE_INDEX_NAME))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(DEFAULT_CACHE_INDEX_NAME);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Change the index on disk under the process-wide lock and, unless
+    /// `read_only`, the lock shared with other ei processes. It is only
+    /// written back when `f` changed it, and never when `read_only`, as in
+    /// a dry run.
+    fn update<T>(read_only: bool, f: impl FnOnce(&mut Self, &Path) -> T) -> Result<T> {
+        let dir = if read_only {
+            cache_dir()?
+        } else {
+            get_cache_dir()?
+        };
+        let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // The index is replaced by a rename, so reading it needs no lock.
+        let _lock = if read_only {
+            None
+        } else {
+            Some(FileLock::for_file(
+                &dir.join(DEFAULT_CACHE_INDEX_NAME),
+                true,
+            )?)
+        };
+        let before = Self::load(&dir);
+        let mut index = before.clone();
+        let value = f(&mut index, &dir);
+        if !read_only && index != before {
+            index.save(&dir)?;
+        }
+        Ok(value)
+    }
+}
+
+/// Path of a blob, dropping it if its content no longer matches its name.
+fn checked_blob(dir: &Path, sha256: &str, read_only: bool) -> Option<PathBuf> {
+    let path = blob_path(dir, sha256);
+    if sha256_file(&path).ok()? != sha256 {
+        trace!("cache blob {} is corrupt, removing it", path.display());
+        if !read_only {
+            let _ = std::fs::remove_file(&path);
+        }
+        return None;
+    }
+    Some(path)
+}
+
+/// Release assets under a tag don't change, so cached copies can be used
+/// without asking the server. Anything else (`releases/latest`, API
+/// responses, other hosts) is only served from the cache when offline.
+pub(crate) fn is_immutable(url: &str) -> bool {
+    url.contains("/releases/download/") && !url.contains("/releases/latest/")
+}
+
+/// Path of the cached content of `url`, if any. A `read_only` lookup leaves
+/// the cache as it is.
+pub(crate) fn get_path(url: &str, read_only: bool) -> Option<PathBuf> {
+    CacheIndex::update(read_only, |index, dir| {
+        let entry = index.entries.get_mut(url)?;
+        let path = checked_blob(dir, &entry.sha256, read_only);
+        match path {
+            Some(_) => {
+                mark_in_use(&entry.sha256);
+                let t = now();
+                if t.saturating_sub(entry.last_used) >= TOUCH_INTERVAL {
+                    entry.last_used = t;
+                }
+            }
+            None => {
+                index.entries.remove(url);
+            }
+        }
+        path
+    })
+    .ok()
+    .flatten()
+    .inspect(|_| trace!("cache hit for {url}"))
+}
+
+/// Cached content of `url`, if any.
+pub(crate) fn get(url: &str, read_only: bool) -> Option<Vec<u8>> {
+    std::fs::read(get_path(url, read_only)?).ok()
+}
+
+/// Path of cached content with the given SHA-256, under whichever URL it
+/// was downloaded from. Records `url` as another source of it.
+pub(crate) fn get_path_by_sha256(url: &str, sha256: &str, read_only: bool) -> Option<PathBuf> {
+    let dir = cache_dir().ok()?;
+    let sha256 = sha256.to_ascii_lowercase();
+    let path = checked_blob(&dir, &sha256, read_only)?;
+    trace!("cache hit for {url} by sha256");
+    mark_in_use(&sha256);
+    if read_only {
+        return Some(path);
+    }
+    let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    if let Err(e) = CacheIndex::update(false, |index, _| index.insert(url, &sha256, size)) {
+        trace!("failed to index {url}: {e:?}");
+    }
+    Some(path)
+}
+
+impl CacheIndex {
+    fn insert(&mut self, url: &str, sha256: &str, size: u64) {
+        let t = now();
+        self.entries.insert(
+            url.to_string(),
+            CacheEntry {
+                sha256: sha256.to_string(),
+                size,
+                cached_at: t,
+                last_used: t,
+            },
+        );
+    }
+}
+
+impl CacheIndex {
+    /// Delete the least recently used content until what the index refers
+    /// to fits in `max_size` bytes (0 for no limit). Content in `keep`,
+    /// which this process is using, is never deleted. Returns how many were
+    /// deleted.
+    fn evict(&mut self, dir: &Path, max_size: u64, keep: &BTreeSet<String>) -> u64 {
+        // Content shared by several URLs counts once, as recently as its
+        // latest use.
+        let mut blobs: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for e in self.entries.values() {
+            let (used, _) = blobs.entry(&e.sha256).or_insert((0, e.size));
+            *used = (*used).max(e.last_used);
+        }
+        let mut total: u64 = blobs.values().map(|(_, size)| size).sum();
+        if max_size == 0 || total <= max_size {
+            return 0;
+        }
+
+        let mut lru: Vec<(u64, &str, u64)> = blobs
+            .into_iter()
+            .filter(|(sha256, _)| !keep.contains(*sha256))
+            .map(|(sha256, (used, size))| (used, sha256, size))
+            .collect();
+        lru.sort_unstable();
+        let mut dropped = HashSet::new();
+        for (_, sha256, size) in lru {
+            if total <= max_size {
+                break;
+            }
+            trace!("cache is over {max_size} bytes, evicting {sha256}");
+            let _ = std::fs::remove_file(blob_path(dir, sha256));
+            total -= size;
+            dropped.insert(sha256.to_string());
+        }
+        self.entries.retain(|_, e| !dropped.contains(&e.sha256));
+        dropped.len() as u64
+    }
+}
+
+/// The configured cap on cached content, in bytes; 0 means no limit.
+fn max_size() -> u64 {
+    PersistentConfig::read()
+        .cache_size
+        .unwrap_or(DEFAULT_CACHE_SIZE)
+}
+
+/// Whether GitHub API responses are cached for `--offline`. They go stale
+/// as soon as a new release is published, so this is off by default.
+pub(crate) fn caches_api_responses() -> bool {
+    PersistentConfig::read().cache_api.unwrap_or(false)
+}
+
+fn put_entry(url: &str, bytes: &[u8]) -> Result<()> {
+    let sha256 = sha256_hex(bytes);
+    let max_size = max_size();
+    CacheIndex::update(false, |index, dir| -> Result<()> {
+        let path = blob_path(dir, &sha256);
+        if !path.exists() {
+            std::fs::create_dir_all(dir.join(BLOBS_DIR))?;
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, bytes)?;
+            std::fs::rename(&tmp, &path)?;
+        }
+        index.insert(url, &sha256, bytes.len() as u64);
+        mark_in_use(&sha256);
+        index.evict(dir, max_size, &in_use());
+        Ok(())
+    })?
+}
+
+/// Store downloaded content. Failing to cache never fails the download.
+pub(crate) fn put(url: &str, bytes: &[u8]) {
+    if let Err(e) = put_entry(url, bytes) {
+        trace!("failed to cache {url}: {e:?}");
+    }
+}
+
+/// Where to unpack `filename` out of the download of `url`, e.g. an archive
+/// nested in another one. It sits next to partial downloads; the caller
+/// removes it when done.
+pub(crate) fn scratch_path(url: &str, filename: &str) -> Result<PathBuf> {
+    let dir = get_cache_dir()?.join(PARTIAL_DIR);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir.join(format!(
+        "{}-{}-{filename}",
+        std::process::id(),
+        &sha256_hex(url.as_bytes())[..16],
+    )))
+}
+
+/// Move a finished download into the cache, returning where it now lives.
+/// `src` must be on the same filesystem, i.e. under the cache directory.
+pub(crate) fn put_file(url: &str, src: &Path) -> Result<PathBuf> {
+    let sha256 = sha256_file(src)?;
+    let size = std::fs::metadata(src)?.len();
+    let max_size = max_size();
+    CacheIndex::update(false, |index, dir| -> Result<PathBuf> {
+        let path = blob_path(dir, &sha256);
+        std::fs::create_dir_all(dir.join(BLOBS_DIR))?;
+        if path.exists() {
+            std::fs::remove_file(src)?;
+        } else {
+            std::fs::rename(src, &path)
+                .with_context(|| format!("Failed to move download to {}", path.display()))?;
+        }
+        index.insert(url, &sha256, size);
+        mark_in_use(&sha256);
+        index.evict(dir, max_size, &in_use());
+        Ok(path)
+    })?
+}
+
+fn dir_size(dir: &Path) -> (u64, usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (0, 0);
+    };
+    entries
+        .flatten()
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .fold((0, 0), |(size, n), m| (size + m.len(), n + 1))
+}
+
+/// `ei cache list`
+pub(crate) fn list() -> Result<()> {
+    let dir = get_cache_dir()?;
+    let index = CacheIndex::load(&dir);
+    if index.entries.is_empty() {
+        println!("Download cache is empty");
+        return Ok(());
+    }
+    let rows: Vec<[String; 4]> = index
+        .entries
+        .iter()
+        .map(|(url, e)| {
+            [
+                e.sha256[..12].to_string(),
+                human_size(e.size as usize),
+                format_timestamp(e.last_used),
+                url.clone(),
+            ]
+        })
+        .collect();
+    print_table(["SHA256", "SIZE", "LAST USED", "URL"], &rows);
+    Ok(())
+}
+
+/// `ei cache size`
+pub(crate) fn size() -> Result<()> {
+    let dir = get_cache_dir()?;
+    let (blobs, n) = dir_size(&dir.join(BLOBS_DIR));
+    let (partial, parts) = dir_size(&dir.join(PARTIAL_DIR));
+    println!(
+        "{}: {} in {n} files",
+        dir.display(),
+        human_size(blobs as usize)
+    );
+    if parts > 0 {
+        println!(
+            "partial downloads: {} in {parts} files",
+            human_size(partial as usize)
+        );
+    }
+    Ok(())
+}
+
+/// `ei cache clean`: remove every cached and partial download.
+pub(crate) fn clean(quiet: bool) -> Result<()> {
+    let dir = get_cache_dir()?;
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _lock = FileLock::for_file(&dir.join(DEFAULT_CACHE_INDEX_NAME), quiet)?;
+    let (blobs, _) = dir_size(&dir.join(BLOBS_DIR));
+    let (partial, _) = dir_size(&dir.join(PARTIAL_DIR));
+    for sub in [BLOBS_DIR, PARTIAL_DIR] {
+        let path = dir.join(sub);
+        if path.exists() {
+            std::fs::remove_dir_all(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    let index = dir.join(DEFAULT_CACHE_INDEX_NAME);
+    if index.exists() {
+        std::fs::remove_file(&index)
+            .with_context(|| format!("Failed to remove {}", index.display()))?;
+    }
+    if !quiet {
+        println!("Removed {}", human_size((blobs + partial) as usize));
+    }
+    Ok(())
+}
+
+/// `ei cache prune --older-than`: forget URLs not used within `max_age`
+/// seconds, and delete content and partial downloads nothing refers to.
+pub(crate) fn prune(max_age: u64, quiet: bool) -> Result<()> {
+    let cutoff = now().saturating_sub(max_age);
+    let (entries, freed) = CacheIndex::update(false, |index, dir| {
+        let before = index.entries.len();
+        index.entries.retain(|_, e| e.last_used >= cutoff);
+        let live: HashSet<&str> = index.entries.values().map(|e| e.sha256.as_str()).collect();
+
+        let mut freed = 0;
+        let blobs = std::fs::read_dir(dir.join(BLOBS_DIR)).into_iter().flatten();
+        let parts = std::fs::read_dir(dir.join(PARTIAL_DIR))
+            .into_iter()
+            .flatten();
+        for entry in blobs.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !live.contains(name.as_str())
+                && let Ok(meta) = entry.metadata()
+                && std::fs::remove_file(entry.path()).is_ok()
+            {
+                freed += meta.len();
+            }
+        }
+        for entry in parts.flatten() {
+            let modified = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            if modified < cutoff
+                && let Ok(meta) = entry.metadata()
+                && std::fs::remove_file(entry.path()).is_ok()
+            {
+                freed += meta.len();
+            }
+        }
+        (before - index.entries.len(), freed)
+    })?;
+    if !quiet {
+        println!(
+            "Pruned {entries} entries, freed {}",
+            human_size(freed as usize)
+        );
+    }
+    Ok(())
+}
+
+/// Parse a size such as `500M`, `2G` or `1024K` into bytes. A bare number
+/// is in bytes.
+pub(crate) fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n
+        .parse()
+        .map_err(|_| format!("invalid size {s:?}, expected e.g. 500M or 2G"))?;
+    let unit = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("invalid unit in {s:?}, expected K, M or G")),
+    };
+    Ok(n * unit)
+}
+
+/// Parse an age such as `30d`, `12h`, `45m`, `90s` or `2w` into seconds.
+pub(crate) fn parse_age(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n
+        .parse()
+        .map_err(|_| format!("invalid age {s:?}, expected e.g. 30d or 12h"))?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "" | "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(format!("invalid unit in {s:?}, expected s, m, h, d or w")),
+    };
+    Ok(n * unit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CacheEntry, CacheIndex, is_immutable, parse_age, parse_size};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90s"), Ok(90));
+        assert_eq!(parse_age("45m"), Ok(45 * 60));
+        assert_eq!(parse_age("12h"), Ok(12 * 3600));
+        assert_eq!(parse_age("30d"), Ok(30 * 86400));
+        assert_eq!(parse_age("30"), Ok(30 * 86400));
+        assert_eq!(parse_age("2w"), Ok(14 * 86400));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3y").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("64kb"), Ok(64 << 10));
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("1T").is_err());
+    }
+
+    #[test]
+    fn test_evict() {
+        let dir = std::env::temp_dir().join(format!("ei-cache-test-{}", std::process::id()));
+        let mut index = CacheIndex::default();
+        for (url, sha256, last_used) in [
+            ("https://a/old.zip", "old", 1),
+            ("https://a/mid.zip", "mid", 2),
+            ("https://b/mid.zip", "mid", 5),
+            ("https://a/new.zip", "new", 3),
+        ] {
+            let entry = CacheEntry {
+                sha256: sha256.to_string(),
+                size: 10,
+                cached_at: 0,
+                last_used,
+            };
+            index.entries.insert(url.to_string(), entry);
+        }
+
+        let keep = BTreeSet::from(["new".to_string()]);
+        assert_eq!(index.evict(&dir, 0, &keep), 0);
+        assert_eq!(index.evict(&dir, 30, &keep), 0);
+        // `mid` was used last through its second URL, so `old` goes first,
+        // then `mid`; `new` is kept even though the cap is still exceeded.
+        assert_eq!(index.evict(&dir, 20, &keep), 1);
+        assert!(!index.entries.contains_key("https://a/old.zip"));
+        assert_eq!(index.evict(&dir, 5, &keep), 1);
+        assert_eq!(
+            index.entries.keys().collect::<Vec<_>>(),
+            ["https://a/new.zip"]
+        );
+
+        // Content another install of this process is still using stays too.
+        let mut index = CacheIndex::default();
+        for (url, sha256, last_used) in [
+            ("https://a/old.zip", "old", 1),
+            ("https://a/mid.zip", "mid", 2),
+            ("https://a/new.zip", "new", 3),
+        ] {
+            let entry = CacheEntry {
+                sha256: sha256.to_string(),
+                size: 10,
+                cached_at: 0,
+                last_used,
+            };
+            index.entries.insert(url.to_string(), entry);
+        }
+        let keep = BTreeSet::from(["old".to_string(), "new".to_string()]);
+        assert_eq!(index.evict(&dir, 20, &keep), 1);
+        assert_eq!(
+            index.entries.keys().collect::<Vec<_>>(),
+            ["https://a/new.zip", "https://a/old.zip"]
+        );
+    }
+
+    #[test]
+    fn test_is_immutable() {
+        assert!(is_immutable(
+            "https://github.com/ahaoboy/mujs-build/releases/download/v0.0.1/mujs.tar.gz"
+        ));
+        assert!(!is_immutable(
+            "https://github.com/denoland/deno/releases/latest/download/deno.zip"
+        ));
+        assert!(!is_immutable(
+            "https://api.github.com/repos/ahaoboy/mujs-build/releases/latest"
+        ));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::InstallConfig;
-use crate::artifact::{GhArtifact, GhArtifacts};
+use crate::artifact::{ArtifactUrl, GhArtifact, GhArtifacts};
 use crate::download::download_json;
 use crate::tool::get_artifact_url;
 use anyhow::{Context, Result};
@@ -38,6 +38,8 @@ struct ActionsArtifact {
     name: String,
     archive_download_url: String,
     expired: bool,
+    #[serde(default)]
+    digest: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -68,8 +70,7 @@ impl CiRun {
         owner: &str,
         repo: &str,
         run_str: &str,
-        retry: usize,
-        timeout: u64,
+        config: &InstallConfig,
     ) -> Result<u64> {
         // Direct run ID
         if let Ok(id) = run_str.parse::<u64>() {
@@ -80,7 +81,7 @@ impl CiRun {
         let url = format!(
             "https://api.github.com/repos/{owner}/{repo}/actions/workflows/{run_str}/runs?per_page=1&status=completed&conclusion=success"
         );
-        let runs: WorkflowRuns = download_json(&url, retry, timeout)
+        let runs: WorkflowRuns = download_json(&url, config)
             .await
             .context("Failed to fetch workflow runs. GitHub Actions API requires authentication — set GITHUB_TOKEN or run `gh auth login`.")?;
 
@@ -97,18 +98,18 @@ impl CiRun {
     pub(crate) async fn get_artifact_url(
         &self,
         config: &InstallConfig,
-    ) -> Result<Vec<(String, String)>> {
-        let artifacts = self.get_artifacts(config.retry, config.timeout).await?;
+    ) -> Result<Vec<ArtifactUrl>> {
+        let artifacts = self.get_artifacts(config).await?;
         get_artifact_url(artifacts, config)
     }
 
-    pub(crate) async fn get_artifacts(&self, retry: usize, timeout: u64) -> Result<GhArtifacts> {
+    pub(crate) async fn get_artifacts(&self, config: &InstallConfig) -> Result<GhArtifacts> {
         let url = format!(
             "https://api.github.com/repos/{}/{}/actions/runs/{}/artifacts",
             self.owner, self.repo, self.run_id
         );
 
-        let response: ActionsArtifacts = download_json(&url, retry, timeout)
+        let response: ActionsArtifacts = download_json(&url, config)
             .await
             .context("Failed to fetch CI artifacts. The GitHub Actions API requires authentication — set GITHUB_TOKEN or run `gh auth login`.")?;
 
@@ -121,9 +122,13 @@ impl CiRun {
                 name: format!("{}.zip", a.name),
                 browser_download_url: a.archive_download_url,
                 url: None,
+                digest: a.digest,
             });
         }
-        Ok(GhArtifacts { assets })
+        Ok(GhArtifacts {
+            assets,
+            tag_name: None,
+        })
     }
 }
 
@@ -163,7 +168,7 @@ impl TryFrom<&str> for CiRun {
 }
 
 /// Parse a CI workflow URL and resolve to the latest completed run.
-pub(crate) async fn resolve_ci_workflow(url: &str, retry: usize, timeout: u64) -> Result<CiRun> {
+pub(crate) async fn resolve_ci_workflow(url: &str, config: &InstallConfig) -> Result<CiRun> {
     let cap = RE_CI_WORKFLOW
         .captures(url)
         .context("Not a valid CI workflow URL")?;
@@ -172,7 +177,7 @@ pub(crate) async fn resolve_ci_workflow(url: &str, retry: usize, timeout: u64) -
     let repo = cap["repo"].to_string();
     let workflow = cap["workflow"].to_string();
 
-    let run_id = CiRun::resolve(&owner, &repo, &workflow, retry, timeout).await?;
+    let run_id = CiRun::resolve(&owner, &repo, &workflow, config).await?;
 
     Ok(CiRun {
         owner,
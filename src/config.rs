@@ -1,7 +1,10 @@
+use crate::file_lock::FileLock;
+use crate::types::Repo;
 use anyhow::{Context, Result};
 use github_proxy::Proxy;
 use guess_target::Target;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -20,6 +23,143 @@ pub struct PersistentConfig {
     pub upx: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub strip: Option<bool>,
+    /// Cap on the download cache in bytes, 0 for no limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_size: Option<u64>,
+    /// Cache GitHub API responses so `--offline` can resolve releases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_api: Option<bool>,
+    /// Per-package presets, keyed by install source (`owner/repo`, URL or path)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub packages: BTreeMap<String, PackageConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct PackageConfig {
+    /// Default `--name` filters
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub name: Vec<String>,
+    /// Default `--alias`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Default `--regex`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Default installation directory, overriding the global `dir`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Default target platform, overriding the global `target`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Target>,
+    /// Default `--fuzzy`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuzzy: Option<bool>,
+    /// Expected SHA-256 of the downloaded asset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Trusted minisign or SSH public keys (inline or as file paths)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pubkeys: Vec<String>,
+    /// Asset to pick when several variants of a tool tie, as a filename
+    /// with the release version written as `{version}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+}
+
+/// Keys of a package preset, as used by `ei config package`.
+pub const PACKAGE_KEYS: [&str; 9] = [
+    "name", "alias", "regex", "dir", "target", "fuzzy", "sha256", "pubkeys", "asset",
+];
+
+impl PackageConfig {
+    /// This preset, with keys it leaves unset taken from `other`.
+    fn or(self, other: PackageConfig) -> PackageConfig {
+        PackageConfig {
+            name: if self.name.is_empty() {
+                other.name
+            } else {
+                self.name
+            },
+            alias: self.alias.or(other.alias),
+            regex: self.regex.or(other.regex),
+            dir: self.dir.or(other.dir),
+            target: self.target.or(other.target),
+            fuzzy: self.fuzzy.or(other.fuzzy),
+            sha256: self.sha256.or(other.sha256),
+            pubkeys: if self.pubkeys.is_empty() {
+                other.pubkeys
+            } else {
+                self.pubkeys
+            },
+            asset: self.asset.or(other.asset),
+        }
+    }
+
+    /// The value of `key`, as `ei config package` shows it.
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let list = |v: &[String]| (!v.is_empty()).then(|| v.join(","));
+        Ok(match key {
+            "name" => list(&self.name),
+            "alias" => self.alias.clone(),
+            "regex" => self.regex.clone(),
+            "dir" => self.dir.clone(),
+            "target" => self.target.map(|t| t.to_str().to_string()),
+            "fuzzy" => self.fuzzy.map(|f| f.to_string()),
+            "sha256" => self.sha256.clone(),
+            "pubkeys" => list(&self.pubkeys),
+            "asset" => self.asset.clone(),
+            _ => anyhow::bail!("Unknown package key: {key}"),
+        })
+    }
+
+    /// Set `key` from its command line form, or unset it with `None`.
+    /// Lists (`name`, `pubkeys`) are comma-separated.
+    pub fn set(&mut self, key: &str, value: Option<&str>) -> Result<()> {
+        let list = |v: Option<&str>| -> Vec<String> {
+            v.map(|v| v.split(',').map(|i| i.trim().to_string()).collect())
+                .unwrap_or_default()
+        };
+        match key {
+            "name" => self.name = list(value),
+            "alias" => self.alias = value.map(str::to_string),
+            "regex" => {
+                if let Some(v) = value {
+                    regex::Regex::new(v).context("invalid regex")?;
+                }
+                self.regex = value.map(str::to_string)
+            }
+            "dir" => self.dir = value.map(crate::tool::expand_path),
+            "target" => {
+                self.target = value
+                    .map(|v| {
+                        v.parse()
+                            .map_err(|_| anyhow::anyhow!("invalid target: {v}"))
+                    })
+                    .transpose()?
+            }
+            "fuzzy" => {
+                self.fuzzy = value
+                    .map(|v| v.parse().with_context(|| format!("invalid bool: {v}")))
+                    .transpose()?
+            }
+            "sha256" => {
+                self.sha256 = value
+                    .map(crate::verify::parse_sha256)
+                    .transpose()
+                    .map_err(|e| anyhow::anyhow!("invalid sha256: {e}"))?
+            }
+            "pubkeys" => {
+                let keys = list(value);
+                for k in &keys {
+                    crate::signature::parse_pubkey(k).map_err(|e| anyhow::anyhow!(e))?;
+                }
+                self.pubkeys = keys
+            }
+            "asset" => self.asset = value.map(str::to_string),
+            _ => anyhow::bail!("Unknown package key: {key}"),
+        }
+        Ok(())
+    }
 }
 
 pub const DEFAULT_CONFIG_NAME: &str = "ei_config.json";
@@ -47,26 +187,48 @@ fn read_config(config_path: &PathBuf) -> Result<PersistentConfig> {
 }
 
 impl PersistentConfig {
+    /// Held across load and save so concurrent ei runs don't drop each
+    /// other's settings.
+    pub(crate) fn lock(quiet: bool) -> Result<FileLock> {
+        FileLock::for_file(&get_config_path()?, quiet)
+    }
+
+    /// The saved configuration. Without one, the default configuration is
+    /// saved for the user to edit.
     pub fn load() -> Self {
+        Self::find().unwrap_or_else(|| {
+            let default_config = Self::default();
+            let _ = default_config.save_quiet(true);
+            default_config
+        })
+    }
+
+    /// Like `load`, but never writes: for dry runs.
+    pub(crate) fn read() -> Self {
+        Self::find().unwrap_or_default()
+    }
+
+    fn find() -> Option<Self> {
         for get_path in [get_config_path, get_default_config_path] {
             if let Ok(config_path) = get_path()
                 && config_path.exists()
                 && let Ok(c) = read_config(&config_path)
             {
-                return c;
+                return Some(c);
             };
         }
-
-        let default_config = Self::default();
-        let _ = default_config.save_quiet(true);
-        default_config
+        None
     }
 
     pub fn save_quiet(&self, quiet: bool) -> Result<()> {
         let config_path = get_config_path()?;
         let content =
             serde_json::to_string_pretty(self).context("Failed to serialize configuration")?;
-        std::fs::write(&config_path, content).context("Failed to write configuration file")?;
+        // Another ei process may be reading or writing it: replace it whole.
+        let _lock = FileLock::for_file(&config_path, quiet)?;
+        let tmp = config_path.with_extension("json.tmp");
+        std::fs::write(&tmp, content).context("Failed to write configuration file")?;
+        std::fs::rename(&tmp, &config_path).context("Failed to write configuration file")?;
         if !quiet {
             println!("Configuration saved to: {}", config_path.display());
         }
@@ -97,7 +259,117 @@ impl PersistentConfig {
     pub fn set_strip(&mut self, strip: bool) {
         self.strip = Some(strip);
     }
+    pub fn set_cache_size(&mut self, cache_size: u64) {
+        self.cache_size = Some(cache_size);
+    }
+    pub fn set_cache_api(&mut self, cache_api: bool) {
+        self.cache_api = Some(cache_api);
+    }
+    /// Preset for an install source: the entry for the exact source, with
+    /// keys it leaves unset taken from its GitHub repository (`owner/repo`).
+    pub fn package(&self, source: &str) -> PackageConfig {
+        let exact = self.packages.get(source).cloned().unwrap_or_default();
+        let repo = Repo::try_from(source)
+            .ok()
+            .map(|r| format!("{}/{}", r.owner, r.name))
+            .filter(|key| key != source)
+            .and_then(|key| self.packages.get(&key));
+        match repo {
+            Some(repo) => exact.or(repo.clone()),
+            None => exact,
+        }
+    }
+    pub fn set_package_sha256(&mut self, source: &str, sha256: String) {
+        self.packages.entry(source.to_string()).or_default().sha256 = Some(sha256);
+    }
+    pub fn set_package_asset(&mut self, source: &str, asset: String) {
+        self.packages.entry(source.to_string()).or_default().asset = Some(asset);
+    }
     pub fn display(&self) {
         println!("{}", serde_json::to_string_pretty(self).unwrap_or_default())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{PackageConfig, PersistentConfig};
+    use crate::{Args, InstallConfig};
+
+    #[test]
+    fn test_package_keys() {
+        let mut preset = PackageConfig::default();
+        preset.set("name", Some("mpv, mpv-dev")).unwrap();
+        preset.set("fuzzy", Some("true")).unwrap();
+        assert_eq!(preset.name, ["mpv", "mpv-dev"]);
+        assert_eq!(preset.get("name").unwrap().as_deref(), Some("mpv,mpv-dev"));
+        assert_eq!(preset.get("fuzzy").unwrap().as_deref(), Some("true"));
+        assert!(preset.set("fuzzy", Some("yes")).is_err());
+        assert!(preset.set("sha256", Some("abc")).is_err());
+        assert!(preset.set("regex", Some("(")).is_err());
+        assert!(preset.get("proxy").is_err());
+
+        preset.set("name", None).unwrap();
+        preset.set("fuzzy", None).unwrap();
+        assert_eq!(preset, PackageConfig::default());
+    }
+
+    #[test]
+    fn test_with_preset() {
+        let mut presets = PersistentConfig {
+            dir: Some("/global".to_string()),
+            ..Default::default()
+        };
+        presets.packages.insert(
+            "mpv-player/mpv".to_string(),
+            PackageConfig {
+                dir: Some("/mpv".to_string()),
+                regex: Some("msvc".to_string()),
+                alias: Some("mpv-dev".to_string()),
+                fuzzy: Some(true),
+                ..Default::default()
+            },
+        );
+        presets.packages.insert(
+            "mpv-player/mpv@v0.40.0".to_string(),
+            PackageConfig {
+                alias: Some("mpv-040".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let preset = presets.package("mpv-player/mpv@v0.40.0");
+        assert_eq!(preset.alias.as_deref(), Some("mpv-040"));
+        assert_eq!(preset.regex.as_deref(), Some("msvc"));
+
+        let args = Args::default();
+        let config = InstallConfig::from_args(&args, &presets, Some("mpv-player/mpv@v0.40.0"));
+        assert_eq!(config.dir.as_deref(), Some("/mpv"));
+        assert_eq!(config.regex.as_deref(), Some("msvc"));
+        assert_eq!(config.alias.as_deref(), Some("mpv-040"));
+        assert!(config.fuzzy);
+
+        // The command line wins, even when it repeats the global setting.
+        let cli = Args {
+            dir: Some("/global".to_string()),
+            alias: Some("mpv".to_string()),
+            fuzzy: Some(false),
+            ..Default::default()
+        };
+        let config = InstallConfig::from_args(&cli, &presets, Some("mpv-player/mpv"));
+        assert_eq!(config.dir.as_deref(), Some("/global"));
+        assert_eq!(config.alias.as_deref(), Some("mpv"));
+        assert!(!config.fuzzy);
+
+        let config = InstallConfig::from_args(&args, &presets, Some("BurntSushi/ripgrep"));
+        assert_eq!(config.dir.as_deref(), Some("/global"));
+        assert_eq!(config.regex, None);
+
+        let config = InstallConfig {
+            alias: Some("mpv".to_string()),
+            ..Default::default()
+        }
+        .with_preset(presets.package("mpv-player/mpv"));
+        assert_eq!(config.dir.as_deref(), Some("/mpv"));
+        assert_eq!(config.alias.as_deref(), Some("mpv"));
+    }
+}
@@ -1,12 +1,17 @@
-use crate::artifact::GhArtifacts;
+use crate::InstallConfig;
+use crate::artifact::{ArtifactUrl, GhArtifacts};
+use crate::cache;
+use crate::partial::PartialDownload;
+use crate::signature::verify_artifact_signature;
 use crate::tool::parse_and_validate_url;
+use crate::verify::{check_file_digest, parse_digest, verify_artifact};
 use crate::{manfiest::DistManifest, tool::is_url};
 use anyhow::{Context, Result};
-use easy_archive::{File, Fmt};
 use regex::Regex;
 use reqwest::Client;
 use reqwest::header::{HeaderMap, HeaderValue};
 use serde::de::DeserializeOwned;
+use std::path::PathBuf;
 use std::sync::{LazyLock, OnceLock};
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
@@ -383,11 +388,16 @@ async fn download_private_release_asset(
     Ok(Some(response))
 }
 
+/// Fetch and parse a JSON API response. A dry run leaves the cache as it is.
 pub(crate) async fn download_json<T: DeserializeOwned>(
     url: &str,
-    retry: usize,
-    timeout: u64,
+    config: &InstallConfig,
 ) -> Result<T> {
+    let (retry, timeout) = (config.retry, config.timeout);
+    if cache::is_offline() {
+        let bytes = cache::get(url, config.dry_run).ok_or_else(|| cache::offline_miss(url))?;
+        return serde_json::from_slice(&bytes).context("json parse failed");
+    }
     let parsed = parse_and_validate_url(url)?;
     let timeout_dur = Duration::from_secs(timeout);
 
@@ -420,34 +430,111 @@ pub(crate) async fn download_json<T: DeserializeOwned>(
                     response.status()
                 ));
             }
-            response.json::<T>().await.context("json parse failed")
+            let bytes = response.bytes().await.context("bytes failed")?;
+            let value = serde_json::from_slice(&bytes).context("json parse failed")?;
+            // Kept for --offline, which has no other way to resolve releases.
+            if !config.dry_run && cache::caches_api_responses() {
+                cache::put(url, &bytes);
+            }
+            Ok(value)
         },
         &format!("download_json({})", url),
     )
     .await
 }
 
-pub(crate) async fn get_bytes(url: &str, retry: usize, timeout: u64) -> Result<Vec<u8>> {
-    let bin = if is_url(url) {
-        download_binary(url, retry, timeout).await?
-    } else {
-        std::fs::read(url).context("read file failed")?.to_vec()
-    };
-    Ok(bin)
+/// The content of a local path or URL. A dry run reads a cached copy
+/// when there is one and otherwise downloads into memory, so nothing is
+/// written to the cache.
+pub(crate) async fn get_bytes(url: &str, config: &InstallConfig) -> Result<Vec<u8>> {
+    if !is_url(url) {
+        return std::fs::read(url).with_context(|| format!("Failed to read {url}"));
+    }
+    if config.dry_run {
+        if let Some(path) = cached_file(url, true)? {
+            return std::fs::read(&path)
+                .with_context(|| format!("Failed to read {}", path.display()));
+        }
+        let response = download(url, config.retry, config.timeout).await?;
+        let bytes = response.bytes().await.context("download interrupted")?;
+        return Ok(bytes.to_vec());
+    }
+    let path = get_file(url, config.retry, config.timeout).await?;
+    std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
 }
 
-pub(crate) fn extract_bytes(bytes: Vec<u8>, fmt: Fmt) -> Result<Vec<File>> {
-    let files = fmt
-        .decode(bytes)?
-        .into_iter()
-        // FIXME: remove __MACOSX
-        .filter(|i| !i.path.starts_with("__MACOSX"))
-        .collect();
+/// The cached download of `url` when it may be used without asking the
+/// server, failing when `--offline` and it isn't cached.
+fn cached_file(url: &str, read_only: bool) -> Result<Option<PathBuf>> {
+    let offline = cache::is_offline();
+    if (offline || cache::is_immutable(url))
+        && let Some(path) = cache::get_path(url, read_only)
+    {
+        return Ok(Some(path));
+    }
+    if offline {
+        return Err(cache::offline_miss(url));
+    }
+    Ok(None)
+}
 
-    Ok(files)
+/// A local path, or the cached download of a URL.
+pub(crate) async fn get_file(url: &str, retry: usize, timeout: u64) -> Result<PathBuf> {
+    if !is_url(url) {
+        return Ok(PathBuf::from(url));
+    }
+    match cached_file(url, false)? {
+        Some(path) => Ok(path),
+        None => download_file(url, retry, timeout).await,
+    }
+}
+
+/// Fetch an artifact to disk and check it against the `--sha256` or lockfile pin, the GitHub
+/// digest, published checksums and trusted signatures before anything is
+/// extracted from it.
+pub(crate) async fn get_artifact_file(
+    art: &ArtifactUrl,
+    config: &InstallConfig,
+) -> Result<PathBuf> {
+    // Any cached copy with the expected content will do, whatever URL or
+    // proxy it was downloaded through.
+    let known = [&config.sha256, &art.sha256]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .chain(art.digest.as_deref().and_then(parse_digest))
+        .find(|hex| hex.len() == 64);
+    let cached = known
+        .filter(|_| is_url(&art.url))
+        .and_then(|hex| cache::get_path_by_sha256(&art.url, hex, false));
+    let path = match cached {
+        Some(path) => path,
+        None => get_file(&art.url, config.retry, config.timeout).await?,
+    };
+    for expected in [&config.sha256, &art.sha256].into_iter().flatten() {
+        check_file_digest(&path, expected)
+            .with_context(|| format!("{} does not match the pinned sha256", art.url))?;
+    }
+    verify_artifact(&path, art, config).await?;
+    verify_artifact_signature(&path, art, config).await?;
+    Ok(path)
 }
 
 pub(crate) async fn download(url: &str, retry: usize, timeout: u64) -> Result<reqwest::Response> {
+    download_with_headers(url, retry, timeout, &HeaderMap::new()).await
+}
+
+/// `download` with extra request headers such as `Range`. A `206 Partial
+/// Content` answer is passed through for the caller to check.
+pub(crate) async fn download_with_headers(
+    url: &str,
+    retry: usize,
+    timeout: u64,
+    extra: &HeaderMap,
+) -> Result<reqwest::Response> {
+    if cache::is_offline() {
+        return Err(cache::offline_miss(url));
+    }
     let parsed = parse_and_validate_url(url)?;
     let timeout_dur = Duration::from_secs(timeout);
 
@@ -456,7 +543,8 @@ pub(crate) async fn download(url: &str, retry: usize, timeout: u64) -> Result<re
         || async {
             trace!("download {}", url);
             let client = create_client();
-            let headers = get_headers(&parsed).await?;
+            let mut headers = get_headers(&parsed).await?;
+            headers.extend(extra.clone());
             let response = match client
                 .get(parsed.clone())
                 .timeout(timeout_dur)
@@ -476,7 +564,10 @@ pub(crate) async fn download(url: &str, retry: usize, timeout: u64) -> Result<re
                     return Err(e).context("send failed");
                 }
             };
-            if response.status() != reqwest::StatusCode::OK {
+            if !matches!(
+                response.status(),
+                reqwest::StatusCode::OK | reqwest::StatusCode::PARTIAL_CONTENT
+            ) {
                 let status = response.status();
                 // Private release assets: browser_download_url rejects API
                 // tokens, so retry through the GitHub asset API instead.
@@ -501,35 +592,33 @@ pub(crate) async fn download(url: &str, retry: usize, timeout: u64) -> Result<re
 
 pub(crate) async fn download_dist_manfiest(
     url: &str,
-    retry: usize,
-    timeout: u64,
+    config: &InstallConfig,
 ) -> Result<DistManifest> {
-    let url_clone = url.to_string();
-    retry_request(
-        retry,
-        || async {
-            trace!("download_dist_manfiest {}", url_clone);
-            let response = download(&url_clone, 0, timeout).await?;
-            response.json().await.context("json parse failed")
-        },
-        &format!("download_dist_manfiest({})", url),
-    )
-    .await
+    trace!("download_dist_manfiest {}", url);
+    let bytes = get_bytes(url, config).await?;
+    serde_json::from_slice(&bytes).context("json parse failed")
 }
 
-pub(crate) async fn download_binary(url: &str, retry: usize, timeout: u64) -> Result<Vec<u8>> {
-    let url_clone = url.to_string();
+/// Download a file through a `.part` file in the cache directory, so a
+/// failed attempt resumes where it stopped instead of starting over. The
+/// finished file is moved into the cache.
+pub(crate) async fn download_file(url: &str, retry: usize, timeout: u64) -> Result<PathBuf> {
+    let part = PartialDownload::new(url)?;
+    let _lock = part.lock().await?;
     retry_request(
         retry,
-        || async {
-            trace!("download_binary {}", url_clone);
-            let response = download(&url_clone, 0, timeout).await?;
-            let bytes = response.bytes().await.context("bytes failed")?;
-            Ok(bytes.to_vec())
-        },
-        &format!("download_binary({})", url),
+        || part.fetch(timeout),
+        &format!("download_file({})", url),
     )
-    .await
+    .await?;
+    let path = part.finish();
+    match cache::put_file(url, &path) {
+        Ok(blob) => Ok(blob),
+        Err(e) => {
+            trace!("failed to cache {url}: {e:?}");
+            Ok(path)
+        }
+    }
 }
 
 pub(crate) fn read_dist_manfiest(url: &str) -> Result<DistManifest> {
@@ -542,14 +631,15 @@ pub(crate) fn read_dist_manfiest(url: &str) -> Result<DistManifest> {
 mod test {
     use easy_archive::Fmt;
 
-    use crate::download::{extract_bytes, get_bytes};
+    use crate::archive;
+    use crate::download::get_file;
 
     #[tokio::test]
     async fn test_download() {
         let url = "https://github.com/ahaoboy/mujs-build/releases/download/v0.0.1/mujs-x86_64-unknown-linux-gnu.tar.gz";
-        let bytes = get_bytes(url, 3, 30).await.expect("donwload error");
+        let path = get_file(url, 3, 30).await.expect("donwload error");
         let fmt = Fmt::guess(url).expect("fmt error");
-        let files = extract_bytes(bytes, fmt).expect("extract_bytes failed");
+        let files = archive::list(&path, fmt, None).expect("archive list failed");
         assert!(files.iter().any(|i| i.path == "mujs"));
         assert!(files.iter().any(|i| i.path == "mujs-pp"));
         assert!(files.iter().any(|i| i.path == "libmujs.a"));
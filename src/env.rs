@@ -5,10 +5,14 @@ use crud_path::{add_github_path, is_github};
 
 use crate::config::DEFAULT_CONFIG_DIR;
 
-pub(crate) fn add_to_path(dir: &str, quiet: bool) {
+pub const DEFAULT_CACHE_DIR: &str = "cache";
+
+/// Add `dir` to PATH. Returns the shell crud-path added it to, or None when
+/// it was already present or could not be added.
+pub(crate) fn add_to_path(dir: &str, quiet: bool) -> Option<String> {
     let dir = dir.trim_end_matches('/');
     if crud_path::has_path(dir) {
-        return;
+        return None;
     }
 
     if is_github() {
@@ -22,17 +26,224 @@ pub(crate) fn add_to_path(dir: &str, quiet: bool) {
         if !quiet {
             println!("Successfully added {dir} to {sh}'s $PATH");
         }
-    } else if !quiet {
-        println!("You need to add {dir} to your $PATH");
+        Some(sh.to_string())
+    } else {
+        if !quiet {
+            println!("You need to add {dir} to your $PATH");
+        }
+        None
     }
 }
 
-pub(crate) fn get_install_dir() -> Result<PathBuf> {
+/// Startup files, relative to the home directory, that crud-path appends
+/// PATH entries to for `shell`; every known one when the shell is unknown.
+#[cfg(not(windows))]
+fn shell_rc_files(shell: Option<&str>) -> &'static [&'static str] {
+    const BASH: &[&str] = &[".bashrc", ".bash_profile", ".profile"];
+    const ZSH: &[&str] = &[".zshrc", ".zprofile"];
+    const FISH: &[&str] = &[".config/fish/config.fish"];
+    const NU: &[&str] = &[".config/nushell/env.nu"];
+    const ALL: &[&str] = &[
+        ".bashrc",
+        ".bash_profile",
+        ".profile",
+        ".zshrc",
+        ".zprofile",
+        ".config/fish/config.fish",
+        ".config/nushell/env.nu",
+    ];
+    let shell = shell.map(str::to_ascii_lowercase).unwrap_or_default();
+    if shell.contains("fish") {
+        FISH
+    } else if shell.contains("zsh") {
+        ZSH
+    } else if shell.contains("bash") {
+        BASH
+    } else if shell.starts_with("nu") {
+        NU
+    } else {
+        ALL
+    }
+}
+
+/// The ways a startup file may spell `dir`: as is, and relative to `$HOME`.
+#[cfg(not(windows))]
+fn dir_spellings(dir: &str, home: Option<&std::path::Path>) -> Vec<String> {
+    let mut spellings = vec![dir.to_string()];
+    if let Some(rest) = home.and_then(|h| dir.strip_prefix(h.to_str()?.trim_end_matches('/')))
+        && (rest.is_empty() || rest.starts_with('/'))
+    {
+        spellings.extend(["$HOME", "${HOME}", "~"].map(|h| format!("{h}{rest}")));
+    }
+    spellings
+}
+
+/// Remove `dir`, spelled any of `spellings`, from a PATH assignment in a
+/// startup file. Returns None when `line` doesn't add `dir`, and an empty
+/// string when nothing else is left on it.
+#[cfg(not(windows))]
+fn remove_path_entry(line: &str, spellings: &[String]) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('#') || !(trimmed.contains("PATH") || trimmed.contains("fish_add_path"))
+    {
+        return None;
+    }
+    let is_sep = |c: Option<char>| matches!(c, None | Some('"' | '\'' | ':' | ';' | ' ' | '='));
+    let (start, end) = spellings.iter().find_map(|dir| {
+        line.match_indices(dir.as_str()).find_map(|(i, _)| {
+            let end = line.len() - line[i + dir.len()..].trim_start_matches('/').len();
+            (is_sep(line[..i].chars().next_back()) && is_sep(line[end..].chars().next()))
+                .then_some((i, end))
+        })
+    })?;
+    // Take one neighbouring list separator along with the entry.
+    let (before, after) = (&line[..start], &line[end..]);
+    let new_line = if let Some(before) = before.strip_suffix(':') {
+        format!("{before}{after}")
+    } else if let Some(after) = after.strip_prefix(':') {
+        format!("{before}{after}")
+    } else if before.ends_with(' ') && (after.is_empty() || after.starts_with(' ')) {
+        format!("{}{after}", &before[..before.len() - 1])
+    } else {
+        format!("{before}{after}")
+    };
+    // `export PATH="$PATH"`, `fish_add_path` and the like add nothing.
+    if !new_line.contains(['/', '~']) {
+        return Some(String::new());
+    }
+    Some(new_line)
+}
+
+/// Remove `dir` from the user's PATH, undoing `add_to_path` for `shell`.
+pub(crate) fn remove_from_path(dir: &str, shell: Option<&str>, quiet: bool) -> Result<()> {
+    let dir = dir.trim_end_matches('/');
+
+    #[cfg(windows)]
+    {
+        let _ = shell;
+        let script = format!(
+            "$p = [Environment]::GetEnvironmentVariable('Path', 'User'); \
+             $n = ($p -split ';' | Where-Object {{ $_ -and (($_ -replace '\\\\', '/').TrimEnd('/') -ne '{}') }}) -join ';'; \
+             [Environment]::SetEnvironmentVariable('Path', $n, 'User')",
+            dir.replace('\\', "/").replace('\'', "''")
+        );
+        let status = std::process::Command::new("powershell")
+            .args(["-NoProfile", "-c", &script])
+            .status()
+            .context("Failed to run powershell")?;
+        anyhow::ensure!(status.success(), "Failed to remove {dir} from user Path");
+        if !quiet {
+            println!("Successfully removed {dir} from $PATH");
+        }
+    }
+
+    #[cfg(not(windows))]
+    {
+        let home = dirs::home_dir().context("Failed to get home_dir")?;
+        let spellings = dir_spellings(dir, Some(&home));
+        for rc in shell_rc_files(shell) {
+            let rc = home.join(rc);
+            let Ok(content) = std::fs::read_to_string(&rc) else {
+                continue;
+            };
+            let mut changed = false;
+            let kept: Vec<String> = content
+                .lines()
+                .filter_map(|line| match remove_path_entry(line, &spellings) {
+                    Some(new_line) => {
+                        changed = true;
+                        (!new_line.is_empty()).then_some(new_line)
+                    }
+                    None => Some(line.to_string()),
+                })
+                .collect();
+            if !changed {
+                continue;
+            }
+            let mut new_content = kept.join("\n");
+            if content.ends_with('\n') {
+                new_content.push('\n');
+            }
+            std::fs::write(&rc, new_content)
+                .with_context(|| format!("Failed to write {}", rc.display()))?;
+            if !quiet {
+                println!("Successfully removed {dir} from {}", rc.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Download cache and partial downloads, `~/.ei/cache`, without creating it.
+pub(crate) fn cache_dir() -> Result<PathBuf> {
+    Ok(install_dir()?.join(DEFAULT_CACHE_DIR))
+}
+
+pub(crate) fn get_cache_dir() -> Result<PathBuf> {
+    let dir = cache_dir()?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// `~/.ei`, without creating it.
+pub(crate) fn install_dir() -> Result<PathBuf> {
     let mut home = dirs::home_dir().context("Failed to get home_dir")?;
     home.push(DEFAULT_CONFIG_DIR);
+    Ok(home)
+}
 
+pub(crate) fn get_install_dir() -> Result<PathBuf> {
+    let home = install_dir()?;
     if !home.exists() {
         std::fs::create_dir_all(&home).context("Failed to create_dir home_dir")?;
     }
     Ok(home)
 }
+
+#[cfg(all(test, not(windows)))]
+mod test {
+    use super::{dir_spellings, remove_path_entry};
+    use std::path::Path;
+
+    #[test]
+    fn test_remove_path_entry() {
+        let dir = dir_spellings("/home/u/.ei", Some(Path::new("/home/u")));
+        let remove = |line: &str| remove_path_entry(line, &dir);
+        assert_eq!(
+            remove(r#"export PATH="$PATH:/home/u/.ei""#).as_deref(),
+            Some("")
+        );
+        assert_eq!(
+            remove(r#"export PATH="/home/u/.ei:$PATH""#).as_deref(),
+            Some("")
+        );
+        assert_eq!(
+            remove("set -gx PATH /home/u/.ei $PATH").as_deref(),
+            Some("")
+        );
+        assert_eq!(remove("fish_add_path /home/u/.ei/").as_deref(), Some(""));
+        assert_eq!(remove(r#"export PATH="$PATH:~/.ei""#).as_deref(), Some(""));
+        assert_eq!(remove(r#"export PATH="$PATH:/home/u/.ei/mpv""#), None);
+        assert_eq!(remove(r#"# export PATH="$PATH:/home/u/.ei""#), None);
+        assert_eq!(remove("alias ei=/home/u/.ei/ei"), None);
+    }
+
+    #[test]
+    fn test_remove_shared_path_entry() {
+        let dir = dir_spellings("/home/u/.ei", Some(Path::new("/home/u")));
+        let remove = |line: &str| remove_path_entry(line, &dir);
+        assert_eq!(
+            remove(r#"export PATH="$HOME/.ei:/opt/other/bin:$PATH""#).as_deref(),
+            Some(r#"export PATH="/opt/other/bin:$PATH""#)
+        );
+        assert_eq!(
+            remove(r#"export PATH="/opt/other/bin:/home/u/.ei:$PATH""#).as_deref(),
+            Some(r#"export PATH="/opt/other/bin:$PATH""#)
+        );
+        assert_eq!(
+            remove("fish_add_path /home/u/.ei /opt/bin").as_deref(),
+            Some("fish_add_path /opt/bin")
+        );
+    }
+}
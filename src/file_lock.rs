@@ -0,0 +1,222 @@
+use crate::env::get_install_dir;
+use crate::verify::sha256_hex;
+use anyhow::{Context, Result};
+use fs4::{FileExt, TryLockError};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, LazyLock, Mutex};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tracing::trace;
+
+pub const DEFAULT_LOCK_TIMEOUT: u64 = 300;
+const LOCKS_DIR: &str = "locks";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set by `--lock-timeout`: seconds to wait for another ei process.
+static LOCK_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_LOCK_TIMEOUT);
+
+/// Lock files held by this process, with the thread holding each and how
+/// many times it took it. Tasks of the same process wait for each other
+/// here, since an OS file lock can't tell them apart. Locks are only held
+/// by synchronous code, so a thread taking a lock it already holds is
+/// nested inside that code and goes through.
+static HELD: LazyLock<(Mutex<Held>, Condvar)> = LazyLock::new(Default::default);
+
+type Held = HashMap<PathBuf, (ThreadId, usize)>;
+
+pub(crate) fn set_lock_timeout(secs: u64) {
+    LOCK_TIMEOUT.store(secs, Ordering::Relaxed);
+}
+
+/// Run `f`, which blocks waiting for a lock or for the user, without
+/// stalling the other tasks of the runtime: on a multi-thread runtime they
+/// move to another worker.
+pub(crate) fn wait<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(h) if h.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+/// An exclusive advisory lock, released on drop. Other ei processes taking
+/// the same lock wait for it; nothing else is kept out.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl FileLock {
+    /// Lock `path`, creating it if needed. Waits up to `--lock-timeout`
+    /// seconds when another task or process holds it. Taking a lock this
+    /// thread already holds returns at once.
+    pub(crate) fn acquire(path: &Path, what: &str, quiet: bool) -> Result<Self> {
+        let timeout = LOCK_TIMEOUT.load(Ordering::Relaxed);
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        let timed_out = || {
+            anyhow::anyhow!(
+                "Timed out after {timeout}s waiting for another ei process {what} (lock: {}); use --lock-timeout to wait longer",
+                path.display()
+            )
+        };
+        {
+            let me = std::thread::current().id();
+            let (held, released) = &*HELD;
+            let mut held = held.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                match held.get_mut(path) {
+                    Some((owner, depth)) if *owner == me => {
+                        *depth += 1;
+                        return Ok(Self {
+                            path: path.to_path_buf(),
+                            file: None,
+                        });
+                    }
+                    Some(_) => {
+                        let left = deadline.saturating_duration_since(Instant::now());
+                        if left.is_zero() {
+                            return Err(timed_out());
+                        }
+                        held = wait(|| {
+                            released
+                                .wait_timeout(held, left)
+                                .unwrap_or_else(|e| e.into_inner())
+                                .0
+                        });
+                    }
+                    None => break,
+                }
+            }
+            held.insert(path.to_path_buf(), (me, 1));
+        }
+        // From here on, dropping `lock` hands the path to the next task.
+        let mut lock = Self {
+            path: path.to_path_buf(),
+            file: None,
+        };
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+        let try_lock = || match FileExt::try_lock(&file) {
+            Ok(()) => Ok(true),
+            Err(TryLockError::WouldBlock) => Ok(false),
+            Err(TryLockError::Error(e)) => {
+                Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+            }
+        };
+        if !try_lock()? {
+            if !quiet {
+                eprintln!("Waiting for another ei process {what}...");
+            }
+            wait(|| -> Result<()> {
+                while !try_lock()? {
+                    if Instant::now() >= deadline {
+                        return Err(timed_out());
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                Ok(())
+            })?;
+        }
+        trace!("locked {}", path.display());
+        lock.file = Some(file);
+        Ok(lock)
+    }
+
+    /// Lock guarding everything installed under `root`, which is `~/.ei` or
+    /// a `--dir`. Installs into different roots don't wait for each other.
+    pub(crate) fn install_root(root: &Path, quiet: bool) -> Result<Self> {
+        let root = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let key = &sha256_hex(root.to_string_lossy().as_bytes())[..16];
+        let path = get_install_dir()?
+            .join(LOCKS_DIR)
+            .join(format!("install-{key}.lock"));
+        Self::acquire(&path, &format!("installing to {}", root.display()), quiet)
+    }
+
+    /// Lock guarding read-modify-write cycles of a state file such as the
+    /// config or the receipts, taken on `<file>.lock` next to it.
+    pub(crate) fn for_file(file: &Path, quiet: bool) -> Result<Self> {
+        let mut name = file.file_name().unwrap_or_default().to_os_string();
+        name.push(".lock");
+        let what = format!("writing {}", file.display());
+        Self::acquire(&file.with_file_name(name), &what, quiet)
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let (held, released) = &*HELD;
+        let mut held = held.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, depth)) = held.get_mut(&self.path) {
+            *depth -= 1;
+            if *depth > 0 {
+                return;
+            }
+        }
+        if let Some(file) = self.file.take() {
+            let _ = FileExt::unlock(&file);
+        }
+        held.remove(&self.path);
+        released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DEFAULT_LOCK_TIMEOUT, FileLock, set_lock_timeout};
+
+    #[test]
+    fn test_nested_acquire() {
+        let dir = std::env::temp_dir().join(format!("ei-lock-test-{}", std::process::id()));
+        let path = dir.join("state.json.lock");
+        let outer = FileLock::acquire(&path, "testing", true).unwrap();
+        let inner = FileLock::acquire(&path, "testing", true).unwrap();
+        drop(inner);
+
+        // Still held by `outer`: another thread has to wait for it.
+        let other = std::thread::spawn({
+            let path = path.clone();
+            move || FileLock::acquire(&path, "testing", true).map(drop)
+        });
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!other.is_finished());
+        drop(outer);
+        other.join().unwrap().unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_acquire_timeout() {
+        let dir = std::env::temp_dir().join(format!("ei-lock-timeout-{}", std::process::id()));
+        let path = dir.join("state.json.lock");
+        let held = FileLock::acquire(&path, "testing", true).unwrap();
+
+        // Another task of this process gives up after --lock-timeout too.
+        set_lock_timeout(1);
+        let other = std::thread::spawn({
+            let path = path.clone();
+            move || FileLock::acquire(&path, "testing", true).map(drop)
+        });
+        let err = other.join().unwrap().unwrap_err();
+        set_lock_timeout(DEFAULT_LOCK_TIMEOUT);
+        assert!(err.to_string().contains("Timed out after 1s"), "{err}");
+
+        drop(held);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,134 +1,372 @@
 use crate::InstallConfig;
-use crate::download::{extract_bytes, get_bytes};
-use crate::env::get_install_dir;
-use crate::install::file::install_from_single_file;
+use crate::archive::{self, Compression, Container, Entry, Remap};
+use crate::artifact::ArtifactUrl;
+use crate::cache::scratch_path;
+use crate::download::get_artifact_file;
+use crate::install::INSTALL_SEM;
+use crate::install::file::{install_from_compressed_file, install_from_single_file};
+use crate::package::{self, PackageFmt};
 use crate::tool::{
-    check_disk_space, display_output, expand_path, get_common_prefix_len, get_filename,
-    install_output_files, is_archive_file, name_no_ext, path_to_str,
+    check_disk_space, display_output, get_common_prefix_len, get_filename, get_target_dir,
+    install_output_files, is_archive_file, name_no_ext, path_to_str, write_hardlink, write_symlink,
+    write_to_file,
 };
-use crate::types::{Output, OutputFile, OutputItem};
+use crate::types::{Link, Output, OutputFile, OutputItem};
 use anyhow::{Context, Result};
 use easy_archive::Fmt;
 use guess_target::guess_target;
+use path_clean::PathClean;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use tracing::trace;
 
+/// Links among `files` in the order to create them: a symlink comes after
+/// the link it points to, so the copies made where symlinks aren't
+/// available find their target already written.
+fn links_in_order(files: &[OutputFile]) -> Vec<&OutputFile> {
+    let resolve = |f: &OutputFile, target: &str| {
+        let dir = Path::new(&f.install_path).parent().unwrap_or(Path::new(""));
+        dir.join(target).clean()
+    };
+    let mut pending: Vec<&OutputFile> = files.iter().filter(|f| f.link.is_some()).collect();
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let waiting: HashSet<PathBuf> = pending
+            .iter()
+            .map(|f| Path::new(&f.install_path).clean())
+            .collect();
+        let (ready, rest): (Vec<_>, Vec<_>) = pending.into_iter().partition(|f| match &f.link {
+            Some(Link::Symlink(target)) => !waiting.contains(&resolve(f, target)),
+            _ => true,
+        });
+        if ready.is_empty() {
+            // A cycle of symlinks: nothing to wait for.
+            ordered.extend(rest);
+            break;
+        }
+        ordered.extend(ready);
+        pending = rest;
+    }
+    ordered
+}
+
+/// Stream the archive entries `files` were listed from to their install
+/// paths, then recreate the links between them.
+fn extract_output_files(
+    path: &Path,
+    container: Container,
+    remap: Option<Remap>,
+    files: &[OutputFile],
+    quiet: bool,
+) -> Result<()> {
+    let targets: HashMap<&str, &OutputFile> =
+        files.iter().map(|f| (f.origin_path.as_str(), f)).collect();
+    archive::for_each_mapped_entry(path, container, remap, |entry, reader| {
+        if let Some(file) = targets.get(entry.path.as_str())
+            && file.link.is_none()
+        {
+            write_to_file(&file.install_path, reader, &file.mode)?;
+        }
+        Ok(())
+    })?;
+
+    for file in links_in_order(files) {
+        match &file.link {
+            Some(Link::Hardlink(origin)) => {
+                let target = targets
+                    .get(origin.as_str())
+                    .filter(|t| t.link.is_none())
+                    .with_context(|| {
+                        format!(
+                            "{}: hard link target {origin} is not a file in the archive",
+                            file.origin_path
+                        )
+                    })?;
+                write_hardlink(&target.install_path, &file.install_path)?;
+            }
+            Some(Link::Symlink(target)) => write_symlink(target, &file.install_path, quiet)?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn install_from_download_file(
-    bytes: Vec<u8>,
+    path: &Path,
     fmt: Fmt,
     url: &str,
     name: &str,
     config: &InstallConfig,
+) -> Result<Output> {
+    install_archive(path, fmt.into(), None, url, name, config)
+}
+
+/// Install a downloaded distribution package from the file tree it carries.
+pub(crate) fn install_from_package_file(
+    path: &Path,
+    pkg: PackageFmt,
+    url: &str,
+    name: &str,
+    config: &InstallConfig,
+) -> Result<Output> {
+    let payload = package::extract_payload(path, pkg, url)?;
+    let name = payload.name.as_deref().unwrap_or(name);
+    let mut output = install_archive(
+        &payload.path,
+        payload.container,
+        Some(package::system_entry),
+        url,
+        name,
+        config,
+    )?;
+    for item in output.values_mut() {
+        item.version = payload.version.clone();
+    }
+    Ok(output)
+}
+
+fn install_archive(
+    path: &Path,
+    container: Container,
+    remap: Option<Remap>,
+    url: &str,
+    name: &str,
+    config: &InstallConfig,
 ) -> Result<Output> {
     trace!("install_from_download_file name={}", name);
-    let mut install_dir = get_install_dir()?;
-    let mut v: OutputItem = Default::default();
-    let mut files: Vec<OutputFile> = vec![];
     let mut output = Output::new();
-    let target_dir = config
-        .dir
-        .clone()
-        .unwrap_or_else(|| path_to_str(&install_dir).to_string());
-    {
-        if target_dir.contains("/") || target_dir.contains("\\") {
-            install_dir = expand_path(&target_dir).into();
-        } else {
-            install_dir.push(target_dir);
+    // Fails on entries that would be written outside `install_dir`.
+    let entries = archive::list(path, container, remap)?;
+    archive::check_link_parents(&entries)?;
+    // Handle nested archive: if there's only one file and it's an archive,
+    // extract it recursively and use the inner archive name for platform/name inference
+    if let Some((first, inner_fmt)) = nested_archive(&entries) {
+        let inner_filename = get_filename(&first.path);
+        // Extract tool name from inner archive (e.g., "bloaty" from "bloaty-x86_64-pc-windows-gnu.tar.gz")
+        let inner_name_no_ext = name_no_ext(&inner_filename);
+        let inner_name = guess_target(&inner_name_no_ext)
+            .pop()
+            .map_or(inner_name_no_ext.clone(), |i| i.name);
+        trace!(
+            "detected nested archive: outer={}, inner={}, tool_name={}",
+            name, inner_filename, inner_name
+        );
+        // The inner archive goes to disk too, next to partial downloads.
+        let inner_path = scratch_path(url, &inner_filename)?;
+        archive::extract_entry(path, container, &first.path, &inner_path)?;
+        let res = install_from_download_file(&inner_path, inner_fmt, url, &inner_name, config);
+        let _ = std::fs::remove_file(&inner_path);
+        return res;
+    }
+
+    let (install_dir, files) = archive_output_files(entries, name, config)?;
+    let mut v = OutputItem {
+        install_dir: path_to_str(&install_dir),
+        files,
+        ..Default::default()
+    };
+    if !v.files.is_empty() {
+        check_disk_space(&v.files, &install_dir)?;
+        install_output_files(&mut v.files, &install_dir, config, |files| {
+            extract_output_files(path, container, remap, files, config.quiet)
+        })?;
+        output.insert(url.to_string(), v);
+        if !config.quiet {
+            println!("{}", display_output(&output, config));
         }
+    }
 
-        if let Ok(download_files) = extract_bytes(bytes, fmt) {
-            // Handle nested archive: if there's only one file and it's an archive,
-            // extract it recursively and use the inner archive name for platform/name inference
-            if let &[first] = &download_files.as_slice()
-                && let Some(inner_fmt) = Fmt::guess(&first.path)
-            {
-                let inner_filename = get_filename(&first.path);
-                // Extract tool name from inner archive (e.g., "bloaty" from "bloaty-x86_64-pc-windows-gnu.tar.gz")
-                let inner_name_no_ext = name_no_ext(&inner_filename);
-                let inner_name = guess_target(&inner_name_no_ext)
-                    .pop()
-                    .map_or(inner_name_no_ext.clone(), |i| i.name);
-                trace!(
-                    "detected nested archive: outer={}, inner={}, tool_name={}",
-                    name, inner_filename, inner_name
-                );
-                return install_from_download_file(
-                    first.buffer.clone(),
-                    inner_fmt,
-                    url,
-                    &inner_name,
-                    config,
-                );
-            }
-            let file_list: Vec<_> = download_files.into_iter().filter(|i| !i.is_dir).collect();
-            if file_list.len() > 1 {
-                if let Some(alias) = &config.alias {
-                    install_dir.push(alias);
-                } else if config.dir.is_none() {
-                    install_dir.push(name);
-                }
-            }
-            let install_dir_str = path_to_str(&install_dir);
-            v.install_dir = install_dir_str;
-
-            let prefix_len = get_common_prefix_len(
-                file_list
-                    .iter()
-                    .map(|i| i.path.as_str())
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            );
+    Ok(output)
+}
 
-            for entry in file_list {
-                let size = entry.buffer.len() as u64;
-                let is_dir = entry.is_dir;
-                if is_dir {
-                    continue;
-                }
-                let mut dst = install_dir.clone();
-                dst.push(&entry.path[prefix_len..]);
-                files.push(OutputFile {
-                    install_path: path_to_str(&dst),
-                    mode: entry.mode,
-                    size,
-                    origin_path: entry.path,
-                    is_dir,
-                    buffer: entry.buffer,
-                });
-            }
+/// The only entry of an archive holding nothing but another archive.
+pub(crate) fn nested_archive(entries: &[Entry]) -> Option<(&Entry, Fmt)> {
+    match entries {
+        [first] => Fmt::guess(&first.path).map(|fmt| (first, fmt)),
+        _ => None,
+    }
+}
 
-            v.files = files;
-            if !v.files.is_empty() {
-                check_disk_space(&v.files, &install_dir)?;
-                install_output_files(&mut v.files, config)?;
-                output.insert(url.to_string(), v);
-                if !config.quiet {
-                    println!("{}", display_output(&output, config));
-                }
-            }
+/// Where the files of an archive are installed: in a directory named after
+/// the tool when there are several, without their common prefix.
+pub(crate) fn archive_output_files(
+    entries: Vec<Entry>,
+    name: &str,
+    config: &InstallConfig,
+) -> Result<(PathBuf, Vec<OutputFile>)> {
+    let mut install_dir = get_target_dir(config)?;
+    let file_list: Vec<_> = entries.into_iter().filter(|i| !i.is_dir).collect();
+    if file_list.len() > 1 {
+        if let Some(alias) = &config.alias {
+            install_dir.push(alias);
+        } else if config.dir.is_none() {
+            install_dir.push(name);
         }
     }
 
-    Ok(output)
+    let prefix_len = get_common_prefix_len(
+        file_list
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+
+    let mut files = vec![];
+    for entry in file_list {
+        let relative = &entry.path[prefix_len..];
+        // The common prefix is dropped, so symlinks are checked
+        // against the layout they are installed with.
+        if let Some(Link::Symlink(target)) = &entry.link {
+            archive::check_symlink(relative, target)
+                .with_context(|| format!("{:?} is installed as {relative:?}", entry.path))?;
+        }
+        let mut dst = install_dir.clone();
+        dst.push(relative);
+        files.push(OutputFile {
+            install_path: path_to_str(&dst),
+            mode: entry.mode,
+            size: entry.size,
+            origin_path: entry.path,
+            is_dir: false,
+            link: entry.link,
+        });
+    }
+    Ok((install_dir, files))
 }
 
 pub(crate) async fn install_from_artifact_url(
-    art_url: &str,
-    name: &str,
+    art: &ArtifactUrl,
     config: &InstallConfig,
 ) -> Result<Output> {
+    let art_url = art.url.as_str();
     trace!("install_from_artifact_url {}", art_url);
+    let _permit = INSTALL_SEM.acquire().await.expect("semaphore closed");
     let mut v = Output::new();
     if !config.quiet {
         println!("download {art_url}");
     }
+    if let Some(pkg) = PackageFmt::guess(art_url) {
+        let path = get_artifact_file(art, config).await?;
+        return install_from_package_file(&path, pkg, art_url, &art.name, config);
+    }
+    if let Some((compression, _)) = Compression::guess_file(&get_filename(art_url)) {
+        let path = get_artifact_file(art, config).await?;
+        return install_from_compressed_file(&path, compression, art_url, config);
+    }
     if !is_archive_file(art_url) {
-        let output = install_from_single_file(art_url, name, config).await?;
+        let output = install_from_single_file(art, config).await?;
         return Ok(output);
     }
 
-    let bytes = get_bytes(art_url, config.retry, config.timeout).await?;
+    let path = get_artifact_file(art, config).await?;
     let fmt = Fmt::guess(art_url).context("fmt guess error")?;
-    let output = install_from_download_file(bytes, fmt, art_url, name, config)?;
+    let output = install_from_download_file(&path, fmt, art_url, &art.name, config)?;
     v.extend(output);
     Ok(v)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{extract_output_files, links_in_order};
+    use crate::types::{Link, OutputFile};
+
+    fn output_file(install_path: &str, link: Option<Link>) -> OutputFile {
+        OutputFile {
+            install_path: install_path.to_string(),
+            origin_path: install_path.to_string(),
+            link,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_links_in_order() {
+        let files = [
+            output_file("out/bin/a", Some(Link::Symlink("b".to_string()))),
+            output_file("out/bin/b", Some(Link::Symlink("../lib/c".to_string()))),
+            output_file("out/lib/c", Some(Link::Hardlink("lib/tool".to_string()))),
+            output_file("out/lib/tool", None),
+        ];
+        let order: Vec<_> = links_in_order(&files)
+            .iter()
+            .map(|f| f.install_path.as_str())
+            .collect();
+        assert_eq!(order, ["out/lib/c", "out/bin/b", "out/bin/a"]);
+
+        let cycle = [
+            output_file("x", Some(Link::Symlink("y".to_string()))),
+            output_file("y", Some(Link::Symlink("x".to_string()))),
+        ];
+        assert_eq!(links_in_order(&cycle).len(), 2);
+    }
+
+    #[cfg(feature = "tar")]
+    #[test]
+    fn test_extract_output_files() {
+        use crate::archive::list;
+        use easy_archive::Fmt;
+
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pkg/bin/tool", &b"binary"[..])
+            .unwrap();
+        // The link to a link comes first, so it must wait for it.
+        for (kind, path, target) in [
+            (tar::EntryType::Symlink, "pkg/bin/t2", "t"),
+            (tar::EntryType::Symlink, "pkg/bin/t", "tool"),
+            (tar::EntryType::Link, "pkg/bin/tool2", "pkg/bin/tool"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_size(0);
+            builder.append_link(&mut header, path, target).unwrap();
+        }
+        let bytes = builder.into_inner().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("ei-artifact-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pkg.tar");
+        std::fs::write(&path, bytes).unwrap();
+        let out = dir.join("out");
+        let files: Vec<OutputFile> = list(&path, Fmt::Tar, None)
+            .unwrap()
+            .into_iter()
+            .map(|entry| OutputFile {
+                install_path: out.join(&entry.path).to_string_lossy().to_string(),
+                origin_path: entry.path,
+                mode: entry.mode,
+                size: entry.size,
+                is_dir: entry.is_dir,
+                link: entry.link,
+            })
+            .collect();
+        extract_output_files(&path, Fmt::Tar.into(), None, &files, true).unwrap();
+
+        let bin = out.join("pkg/bin");
+        for name in ["tool", "t", "t2", "tool2"] {
+            assert_eq!(std::fs::read(bin.join(name)).unwrap(), b"binary", "{name}");
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(
+                std::fs::read_link(bin.join("t")).unwrap().to_str(),
+                Some("tool")
+            );
+            assert_eq!(
+                std::fs::read_link(bin.join("t2")).unwrap().to_str(),
+                Some("t")
+            );
+            assert_eq!(
+                std::fs::metadata(bin.join("tool2")).unwrap().ino(),
+                std::fs::metadata(bin.join("tool")).unwrap().ino()
+            );
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
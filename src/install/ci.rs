@@ -1,22 +1,18 @@
 use crate::InstallConfig;
+use crate::artifact::ArtifactUrl;
 use crate::ci::CiRun;
-use crate::download::get_bytes;
+use crate::download::get_artifact_file;
 use crate::install::artifact::install_from_download_file;
-use crate::tool::{filter_artifacts, get_artifact_url, not_found_asset_message};
+use crate::install::{INSTALL_SEM, check_sha256_pin};
+use crate::tool::{get_artifact_url, not_found_asset_message};
 use crate::types::Output;
 use anyhow::{Context, Result};
 use easy_archive::Fmt;
 use tokio::task::JoinSet;
-use tracing::trace;
 
-/// Limits concurrent network downloads to avoid hammering GitHub.
-static DOWNLOAD_SEM: std::sync::LazyLock<tokio::sync::Semaphore> =
-    std::sync::LazyLock::new(|| tokio::sync::Semaphore::new(4));
-
-pub(crate) async fn install_from_ci(ci: &CiRun, config: &InstallConfig) -> Result<Output> {
-    trace!("install_from_ci {}", ci);
-
-    let artifacts = ci.get_artifacts(config.retry, config.timeout).await?;
+/// Pick the artifacts of a CI run to install.
+pub(crate) async fn resolve_ci(ci: &CiRun, config: &InstallConfig) -> Result<Vec<ArtifactUrl>> {
+    let artifacts = ci.get_artifacts(config).await?;
 
     // Collect available names before get_artifact_url consumes the set.
     let available: Vec<String> = artifacts.assets.iter().map(|a| a.name.clone()).collect();
@@ -24,29 +20,35 @@ pub(crate) async fn install_from_ci(ci: &CiRun, config: &InstallConfig) -> Resul
     let artifact_url = get_artifact_url(artifacts, config)?;
     if artifact_url.is_empty() {
         not_found_asset_message(&ci.to_string(), config, Some(&available));
-        return Ok(Output::new());
     }
+    Ok(artifact_url)
+}
 
-    let artifact_url = filter_artifacts(artifact_url, config);
+/// Install the artifacts of a CI run, concurrently when there are several.
+pub(crate) async fn install_ci_artifacts(
+    artifact_url: Vec<ArtifactUrl>,
+    config: &InstallConfig,
+) -> Result<Output> {
+    if artifact_url.is_empty() {
+        return Ok(Output::new());
+    }
+    check_sha256_pin(&artifact_url, config)?;
 
     // CI artifacts are always ZIP archives served by the GitHub API.
     // The download URL is an API endpoint (…/artifacts/{id}/zip) that
     // `Fmt::guess` may not recognise, so we force `Fmt::Zip` explicitly.
     if artifact_url.len() <= 1 {
         let mut v = Output::new();
-        for (name, url) in artifact_url {
-            v.extend(install_ci_artifact(&url, &name, config).await?);
+        for art in artifact_url {
+            v.extend(install_ci_artifact(&art, config).await?);
         }
         return Ok(v);
     }
 
     let mut tasks: JoinSet<Result<Output>> = JoinSet::new();
-    for (name, url) in artifact_url {
+    for art in artifact_url {
         let config = config.clone();
-        tasks.spawn(async move {
-            let _permit = DOWNLOAD_SEM.acquire().await.expect("semaphore closed");
-            install_ci_artifact(&url, &name, &config).await
-        });
+        tasks.spawn(async move { install_ci_artifact(&art, &config).await });
     }
 
     let mut v = Output::new();
@@ -56,12 +58,13 @@ pub(crate) async fn install_from_ci(ci: &CiRun, config: &InstallConfig) -> Resul
     Ok(v)
 }
 
-async fn install_ci_artifact(url: &str, name: &str, config: &InstallConfig) -> Result<Output> {
+async fn install_ci_artifact(art: &ArtifactUrl, config: &InstallConfig) -> Result<Output> {
+    let _permit = INSTALL_SEM.acquire().await.expect("semaphore closed");
     if !config.quiet {
-        println!("download {url}");
+        println!("download {}", art.url);
     }
-    let bytes = get_bytes(url, config.retry, config.timeout)
+    let path = get_artifact_file(art, config)
         .await
         .context("Failed to download CI artifact")?;
-    install_from_download_file(bytes, Fmt::Zip, url, name, config)
+    install_from_download_file(&path, Fmt::Zip, &art.url, &art.name, config)
 }
@@ -1,70 +1,145 @@
 use crate::InstallConfig;
-use crate::download::download_binary;
-use crate::env::get_install_dir;
+use crate::appimage::{appimage_name, appimage_stem, install_desktop_files, is_appimage};
+use crate::archive::{Compression, decompress};
+use crate::artifact::ArtifactUrl;
+use crate::download::get_artifact_file;
+use crate::install::tool_name;
 use crate::tool::{
-    check_disk_space, display_output, ends_with_exe, expand_path, get_bin_name, get_filename,
-    install_output_files, path_to_str,
+    check_disk_space, display_output, ends_with_exe, get_bin_name, get_filename, get_target_dir,
+    install_output_files, name_no_ext, path_to_str, write_to_file,
 };
 use crate::types::{Output, OutputFile, OutputItem};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use guess_target::Os;
+use std::path::Path;
+
+/// File name a single downloaded file is installed as. Callers pass the
+/// full source filename (e.g. `cli.ts`, `run.sh`, `bun`), so known
+/// extensions are preserved; get_bin_name only appends a platform extension
+/// (.exe) to bare names. A directly given AppImage is named after its tool,
+/// without the version.
+pub(crate) fn single_file_name(url: &str, name: &str) -> String {
+    if is_appimage(url) && is_appimage(name) {
+        get_bin_name(&appimage_name(appimage_stem(name)))
+    } else {
+        get_bin_name(name)
+    }
+}
+
+/// The file a compressed executable decompresses to, and the file name it
+/// is installed as: the tool name guessed from it, with its extension.
+pub(crate) fn compressed_file_names(url: &str, config: &InstallConfig) -> (String, String) {
+    let filename = get_filename(url);
+    let inner = Compression::guess_file(&filename)
+        .map_or(filename.as_str(), |(_, inner)| inner)
+        .to_string();
+    let ext = &inner[name_no_ext(&inner).len()..];
+    let name = tool_name(&inner, config);
+    let bin = get_bin_name(&format!("{name}{ext}"));
+    (inner, bin)
+}
 
 pub(crate) async fn install_from_single_file(
-    url: &str,
-    name: &str,
+    art: &ArtifactUrl,
     config: &InstallConfig,
 ) -> Result<Output> {
-    let mut install_dir = get_install_dir()?;
+    let (url, name) = (art.url.as_str(), art.name.as_str());
+    let install_dir = get_target_dir(config)?;
     let mut output = Output::new();
 
-    if let Some(target_dir) = &config.dir {
-        if target_dir.contains("/") || target_dir.contains("\\") {
-            install_dir = expand_path(target_dir).into();
-        } else {
-            install_dir.push(target_dir);
-        }
-    }
-
     let local_target = config.get_local_target();
     if ends_with_exe(url) && local_target.iter().any(|t| t.os() != Os::Windows) {
         return Ok(output);
     }
     let filename = get_filename(url);
-    // Callers pass the full source filename (e.g. `cli.ts`, `run.sh`, `bun`),
-    // so known extensions are preserved. get_bin_name only appends a platform
-    // extension (.exe) to bare names.
-    let bin = if std::fs::exists(url).unwrap_or(false) {
-        Some(std::fs::read(url)?)
-    } else {
-        Some(download_binary(url, config.retry, config.timeout).await?)
+    let appimage = is_appimage(url);
+    let bin = get_artifact_file(art, config).await?;
+    let install_path = path_to_str(&install_dir.join(single_file_name(url, name)));
+    let mut files = vec![OutputFile {
+        mode: appimage.then_some(0o755),
+        size: std::fs::metadata(&bin)
+            .with_context(|| format!("Failed to read {}", bin.display()))?
+            .len(),
+        origin_path: filename,
+        is_dir: false,
+        install_path,
+        link: None,
+    }];
+    check_disk_space(&files, &install_dir)?;
+    install_output_files(&mut files, &install_dir, config, |files| {
+        let mut src = std::fs::File::open(&bin)
+            .with_context(|| format!("Failed to open {}", bin.display()))?;
+        write_to_file(&files[0].install_path, &mut src, &files[0].mode)
+    })?;
+    if appimage && config.desktop {
+        let name = get_filename(&files[0].install_path);
+        match install_desktop_files(&files[0].install_path, &name) {
+            Ok(desktop) => files.extend(desktop),
+            Err(e) if !config.quiet => eprintln!("Warning: {e:?}"),
+            Err(_) => {}
+        }
+    }
+    let item = OutputItem {
+        install_dir: path_to_str(&install_dir),
+        files,
+        ..Default::default()
     };
-    if let Some(bin) = bin {
-        let mut install_path = install_dir.clone();
-        let target_name = get_bin_name(name);
-        install_path.push(target_name);
-        let install_path = path_to_str(&install_path);
-        let mut files = vec![OutputFile {
-            mode: None,
-            size: bin.len() as u64,
-            origin_path: filename,
-            is_dir: false,
-            install_path,
-            buffer: bin,
-        }];
-        check_disk_space(&files, &install_dir)?;
-        install_output_files(&mut files, config)?;
-        let bin_dir_str = path_to_str(&install_dir);
-        let item = OutputItem {
-            install_dir: bin_dir_str.clone(),
-            files,
-        };
 
-        output.insert(url.to_string(), item);
-        if !config.quiet {
-            println!("{}", display_output(&output, config));
-        }
-    } else if !config.quiet {
-        println!("not found/download artifact for {url}")
+    output.insert(url.to_string(), item);
+    if !config.quiet {
+        println!("{}", display_output(&output, config));
+    }
+    Ok(output)
+}
+
+/// Install an executable published as a single compressed stream, like
+/// `rust-analyzer-x86_64-unknown-linux-gnu.gz`, under the tool name guessed
+/// from the decompressed file name.
+pub(crate) fn install_from_compressed_file(
+    path: &Path,
+    compression: Compression,
+    url: &str,
+    config: &InstallConfig,
+) -> Result<Output> {
+    let install_dir = get_target_dir(config)?;
+    let filename = get_filename(url);
+    let (inner, bin) = compressed_file_names(url, config);
+
+    // The decompressed size is only known by decompressing, so the disk
+    // space check goes by the compressed size and the receipt records the
+    // size written.
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .len();
+    let mut files = vec![OutputFile {
+        mode: Some(0o755),
+        size,
+        origin_path: inner,
+        is_dir: false,
+        install_path: path_to_str(&install_dir.join(bin)),
+        link: None,
+    }];
+    check_disk_space(&files, &install_dir)?;
+    install_output_files(&mut files, &install_dir, config, |files| {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut reader = decompress(file, compression)?;
+        write_to_file(&files[0].install_path, &mut reader, &files[0].mode)
+            .with_context(|| format!("Failed to decompress {filename}"))
+    })?;
+    if let Ok(meta) = std::fs::metadata(&files[0].install_path) {
+        files[0].size = meta.len();
+    }
+    let item = OutputItem {
+        install_dir: path_to_str(&install_dir),
+        files,
+        ..Default::default()
+    };
+
+    let mut output = Output::new();
+    output.insert(url.to_string(), item);
+    if !config.quiet {
+        println!("{}", display_output(&output, config));
     }
     Ok(output)
 }
@@ -1,24 +1,16 @@
 use crate::InstallConfig;
-use crate::install::install_artifacts;
+use crate::artifact::ArtifactUrl;
 use crate::manfiest::DistManifest;
 use crate::tool::{filter_artifacts, get_artifact_url_from_manfiest};
-use crate::types::Output;
-use anyhow::Result;
-use tracing::trace;
 
-pub(crate) async fn install_from_manfiest(
-    manfiest: DistManifest,
+pub(crate) fn resolve_manfiest(
+    manfiest: &DistManifest,
     url: &str,
     config: &InstallConfig,
-) -> Result<Output> {
-    trace!("install_from_manfiest {}", url);
-    let art_url_list = get_artifact_url_from_manfiest(url, &manfiest, config);
-    if art_url_list.is_empty() {
-        if !config.quiet {
-            println!("install_from_manfiest {url} failed");
-        }
-        return Ok(Output::new());
+) -> Vec<ArtifactUrl> {
+    let art_url_list = get_artifact_url_from_manfiest(url, manfiest, config);
+    if art_url_list.is_empty() && !config.quiet {
+        println!("install_from_manfiest {url} failed");
     }
-    let art_url_list = filter_artifacts(art_url_list, config);
-    install_artifacts(art_url_list, config).await
+    filter_artifacts(art_url_list, config)
 }
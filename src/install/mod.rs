@@ -3,71 +3,105 @@ mod ci;
 mod file;
 mod manfiest;
 mod nightly;
+mod plan;
 mod repo;
 
 use crate::InstallConfig;
+use crate::artifact::ArtifactUrl;
 use crate::ci::{CiRun, RE_CI_WORKFLOW, resolve_ci_workflow};
-use crate::download::{download_dist_manfiest, get_bytes, read_dist_manfiest};
+use crate::download::{download_dist_manfiest, read_dist_manfiest};
 use crate::install::artifact::install_from_artifact_url;
-use crate::install::ci::install_from_ci;
-use crate::install::file::install_from_single_file;
-use crate::install::manfiest::install_from_manfiest;
-use crate::install::nightly::install_from_nightly;
-use crate::install::repo::install_from_github;
+use crate::install::ci::{install_ci_artifacts, resolve_ci};
+use crate::install::manfiest::resolve_manfiest;
+use crate::install::nightly::resolve_nightly;
+use crate::install::repo::resolve_github;
+use crate::package::is_package_file;
 use crate::tool::{
-    get_filename, is_archive_file, is_dist_manfiest, is_exe_file, is_known_format, is_url,
-    name_no_ext,
+    get_filename, is_archive_file, is_compressed_file, is_dist_manfiest, is_exe_file,
+    is_known_format, is_url, name_no_ext,
 };
 use crate::types::{Nightly, Output, Repo};
 use anyhow::Result;
-use artifact::install_from_download_file;
-use easy_archive::Fmt;
 use guess_target::guess_target;
 use std::sync::LazyLock;
+use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 use tracing::trace;
 
-/// Limits concurrent network downloads to avoid hammering GitHub and
+pub(crate) use plan::plan_sources;
+
+/// Limits concurrent artifact installs, download and extraction, across
+/// every source installed by this process, to avoid hammering GitHub and
 /// tripping rate limits.
-static DOWNLOAD_SEM: LazyLock<tokio::sync::Semaphore> =
-    LazyLock::new(|| tokio::sync::Semaphore::new(4));
+pub(crate) static INSTALL_SEM: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(4));
 
-pub(crate) async fn install(url: &str, config: &InstallConfig) -> Result<Output> {
-    trace!("install {}", url);
-    let repo = Repo::try_from(url);
+/// The kind of source a URL or path is installed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SourceKind {
+    Manifest,
+    Repo,
+    Nightly,
+    Ci,
+    Url,
+    File,
+}
+
+impl std::fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SourceKind::Manifest => "dist manifest",
+            SourceKind::Repo => "GitHub release",
+            SourceKind::Nightly => "nightly build",
+            SourceKind::Ci => "GitHub Actions artifacts",
+            SourceKind::Url => "direct URL",
+            SourceKind::File => "local file",
+        })
+    }
+}
+
+/// The artifacts a source resolves to, and the release they belong to.
+pub(crate) struct Resolved {
+    pub(crate) kind: SourceKind,
+    pub(crate) tag: Option<String>,
+    pub(crate) artifacts: Vec<ArtifactUrl>,
+}
+
+/// Resolve a source to the artifacts to download, without installing
+/// anything. Both `install` and `ei plan` go through here.
+pub(crate) async fn resolve(url: &str, config: &InstallConfig) -> Result<Resolved> {
+    trace!("resolve {}", url);
+    // Tagged sources (`owner/repo@tag`, release and download URLs) carry
+    // the tag in the URL itself.
+    let tag = Repo::try_from(url).ok().and_then(|r| r.tag);
 
     if is_dist_manfiest(url) {
         let manifest = if is_url(url) {
-            download_dist_manfiest(url, config.retry, config.timeout).await
+            download_dist_manfiest(url, config).await
         } else {
             read_dist_manfiest(url)
         };
-        match manifest {
-            Ok(m) if !m.artifacts.is_empty() => {
-                return install_from_manfiest(m, url, config).await;
-            }
+        let artifacts = match manifest {
+            Ok(m) if !m.artifacts.is_empty() => resolve_manfiest(&m, url, config),
             Ok(_) => {
                 if !config.quiet {
                     println!("dist-manifest at {url} contains no artifacts");
                 }
-                return Ok(Output::new());
+                vec![]
             }
             Err(e) => {
                 if !config.quiet {
                     println!("failed to read dist-manifest from {url}: {e}");
                 }
-                return Ok(Output::new());
+                vec![]
             }
-        }
+        };
+        return Ok(Resolved {
+            kind: SourceKind::Manifest,
+            tag,
+            artifacts,
+        });
     }
 
-    let filename = get_filename(url);
-    let name = name_no_ext(&filename);
-    let guess = guess_target(&name);
-    let local = config.get_local_target();
-    let item = guess.iter().find(|i| local.contains(&i.target));
-    let name = item.map_or(name, |i| i.name.clone());
-
     // GitHub Actions CI — must be checked first because CI workflow URLs
     // end with .yml which would otherwise be treated as a known format
     // and downloaded directly. CI run URLs also match Repo patterns, so
@@ -80,46 +114,102 @@ pub(crate) async fn install(url: &str, config: &InstallConfig) -> Result<Output>
         let ci = if let Ok(ci) = CiRun::try_from(url) {
             ci
         } else {
-            resolve_ci_workflow(url, config.retry, config.timeout).await?
+            resolve_ci_workflow(url, config).await?
         };
-        return install_from_ci(&ci, config).await;
+        return Ok(Resolved {
+            kind: SourceKind::Ci,
+            tag,
+            artifacts: resolve_ci(&ci, config).await?,
+        });
     }
 
-    if is_url(url) {
+    let filename = get_filename(url);
+    let direct = if is_url(url) {
         let proxied = apply_proxy(url, config.proxy);
-
-        if is_archive_file(&proxied) {
-            return install_from_artifact_url(&proxied, &name, config).await;
-        }
-
-        if is_exe_file(&proxied).unwrap_or(false) || is_known_format(&proxied) {
-            return install_from_single_file(&proxied, &filename, config).await;
-        }
-    }
-
-    if std::fs::exists(url).unwrap_or(false) {
-        if is_archive_file(url) {
-            if let Ok(bytes) = get_bytes(url, config.retry, config.timeout).await
-                && let Some(fmt) = Fmt::guess(url)
-            {
-                return install_from_download_file(bytes, fmt, url, &name, config);
-            }
+        (is_archive_file(&proxied)
+            || is_package_file(&proxied)
+            || is_compressed_file(&proxied)
+            || is_exe_file(&proxied).unwrap_or(false)
+            || is_known_format(&proxied))
+        .then_some(proxied)
+    } else {
+        std::fs::exists(url)
+            .unwrap_or(false)
+            .then(|| url.to_string())
+    };
+    if let Some(direct) = direct {
+        let kind = if is_url(url) {
+            SourceKind::Url
         } else {
-            // Same as the URL branch above: pass the full source filename so
-            // known extensions (e.g. `cli.ts`, `run.sh`) are preserved.
-            return install_from_single_file(url, &filename, config).await;
-        }
+            SourceKind::File
+        };
+        let name = if is_archive_file(&direct)
+            || is_package_file(&direct)
+            || is_compressed_file(&direct)
+        {
+            tool_name(&filename, config)
+        } else {
+            // Pass the full source filename so known extensions (e.g.
+            // `cli.ts`, `run.sh`) are preserved.
+            filename
+        };
+        return Ok(Resolved {
+            kind,
+            tag,
+            artifacts: vec![ArtifactUrl::new(&name, &direct)],
+        });
     }
 
     if let Ok(nightly) = Nightly::try_from(url) {
-        return install_from_nightly(&nightly, config).await;
+        return Ok(Resolved {
+            kind: SourceKind::Nightly,
+            tag,
+            artifacts: resolve_nightly(&nightly, config).await?,
+        });
     }
 
-    if let Ok(repo) = repo {
-        return install_from_github(&repo, config).await;
+    match Repo::try_from(url) {
+        Ok(repo) => resolve_github(&repo, config).await,
+        // Any other URL is downloaded as a single file.
+        Err(_) if is_url(url) => Ok(Resolved {
+            kind: SourceKind::Url,
+            tag,
+            artifacts: vec![ArtifactUrl::new(&tool_name(&filename, config), url)],
+        }),
+        Err(e) => Err(e),
     }
+}
+
+/// The tool name in an asset filename, without extension and target.
+pub(crate) fn tool_name(filename: &str, config: &InstallConfig) -> String {
+    let name = name_no_ext(filename);
+    let local = config.get_local_target();
+    guess_target(&name)
+        .into_iter()
+        .find(|i| local.contains(&i.target))
+        .map_or(name, |i| i.name)
+}
 
-    install_from_single_file(url, &name, config).await
+/// Install a source: resolve it to artifacts and install each of them.
+pub(crate) async fn install(url: &str, config: &InstallConfig) -> Result<Output> {
+    trace!("install {}", url);
+    let resolved = resolve(url, config).await?;
+    let mut output = match resolved.kind {
+        SourceKind::Ci => install_ci_artifacts(resolved.artifacts, config).await?,
+        _ => install_artifacts(resolved.artifacts, config).await?,
+    };
+    set_tag(&mut output, resolved.tag.as_deref());
+    Ok(output)
+}
+
+/// Record the release tag on every installed item that doesn't carry one yet.
+pub(crate) fn set_tag(output: &mut Output, tag: Option<&str>) {
+    let Some(tag) = tag else { return };
+    for item in output.values_mut() {
+        if item.tag.is_none() {
+            item.tag = Some(tag.to_string());
+        }
+    }
 }
 
 /// Apply the configured GitHub proxy to a URL if it is a GitHub resource.
@@ -130,28 +220,27 @@ fn apply_proxy(url: &str, proxy: github_proxy::Proxy) -> String {
     }
 }
 
-/// Install a list of (name, url) artifacts, downloading concurrently when
+/// Install a list of artifacts, downloading concurrently when
 /// there is more than one. Results are merged into a single `Output`.
 pub(crate) async fn install_artifacts(
-    artifact_url: Vec<(String, String)>,
+    artifact_url: Vec<ArtifactUrl>,
     config: &InstallConfig,
 ) -> Result<Output> {
+    check_sha256_pin(&artifact_url, config)?;
+
     // Fast path: zero or one artifact — no need to spawn tasks.
     if artifact_url.len() <= 1 {
         let mut v = Output::new();
-        for (name, url) in artifact_url {
-            v.extend(install_from_artifact_url(&url, &name, config).await?);
+        for art in artifact_url {
+            v.extend(install_from_artifact_url(&art, config).await?);
         }
         return Ok(v);
     }
 
     let mut tasks: JoinSet<Result<Output>> = JoinSet::new();
-    for (name, url) in artifact_url {
+    for art in artifact_url {
         let config = config.clone();
-        tasks.spawn(async move {
-            let _permit = DOWNLOAD_SEM.acquire().await.expect("semaphore closed");
-            install_from_artifact_url(&url, &name, &config).await
-        });
+        tasks.spawn(async move { install_from_artifact_url(&art, &config).await });
     }
 
     let mut v = Output::new();
@@ -160,3 +249,52 @@ pub(crate) async fn install_artifacts(
     }
     Ok(v)
 }
+
+/// `--sha256` pins the bytes of a single file, so it can't cover a release
+/// that resolves to several artifacts.
+pub(crate) fn check_sha256_pin(artifact_url: &[ArtifactUrl], config: &InstallConfig) -> Result<()> {
+    if config.sha256.is_some() && artifact_url.len() > 1 {
+        let names: Vec<String> = artifact_url.iter().map(|a| get_filename(&a.url)).collect();
+        anyhow::bail!(
+            "--sha256 requires a single artifact, but {} would be installed: {}\n  Narrow the selection with --name or --regex.",
+            artifact_url.len(),
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::install;
+    use crate::InstallConfig;
+
+    #[tokio::test]
+    async fn test_local_archive_errors() {
+        let dir =
+            std::env::temp_dir().join(format!("ei-local-archive-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("tool-x86_64-unknown-linux-gnu.tar.gz");
+        std::fs::write(&archive, b"not an archive").unwrap();
+        let archive = archive.to_str().unwrap();
+        let config = InstallConfig {
+            dir: Some(dir.join("bin").to_string_lossy().to_string()),
+            quiet: true,
+            no_path: true,
+            ..Default::default()
+        };
+
+        // A local archive that can't be verified or read fails instead of
+        // falling through to the other source kinds.
+        let pinned = InstallConfig {
+            sha256: Some("0".repeat(64)),
+            ..config.clone()
+        };
+        let err = install(archive, &pinned).await.unwrap_err();
+        assert!(format!("{err:?}").contains("pinned sha256"), "{err:?}");
+        assert!(install(archive, &config).await.is_err());
+        assert!(!dir.join("bin").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
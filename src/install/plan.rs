@@ -0,0 +1,255 @@
+use crate::InstallConfig;
+use crate::appimage::is_appimage;
+use crate::archive::{self, Compression, Container};
+use crate::artifact::ArtifactUrl;
+use crate::cache;
+use crate::install::artifact::{archive_output_files, nested_archive};
+use crate::install::file::{compressed_file_names, single_file_name};
+use crate::install::{SourceKind, check_sha256_pin, resolve};
+use crate::package::PackageFmt;
+use crate::tool::{
+    ends_with_exe, get_filename, get_target_dir, is_url, output_path_dirs, path_to_str,
+    settle_output_files,
+};
+use crate::types::{Output, OutputFile, OutputItem};
+use crate::verify::parse_digest;
+use anyhow::Result;
+use easy_archive::Fmt;
+use guess_target::Os;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+/// What installing a source would do.
+pub(crate) struct Plan {
+    source: String,
+    kind: SourceKind,
+    tag: Option<String>,
+    assets: Vec<PlannedAsset>,
+    /// Directories that would be put on PATH, and whether they already are.
+    path: Vec<(String, bool)>,
+}
+
+struct PlannedAsset {
+    url: String,
+    item: OutputItem,
+    /// Whether `item.files` is complete. Files of an archive are only known
+    /// once it is downloaded.
+    known: bool,
+}
+
+/// Resolve `url` like `install` does and work out the files it would
+/// install, without downloading or writing anything.
+pub(crate) async fn plan(url: &str, config: &InstallConfig) -> Result<Plan> {
+    let resolved = resolve(url, config).await?;
+    check_sha256_pin(&resolved.artifacts, config)?;
+
+    let mut assets = vec![];
+    let mut output = Output::new();
+    for art in &resolved.artifacts {
+        let (item, known) = plan_artifact(art, resolved.kind, config)?;
+        output.insert(art.url.clone(), item.clone());
+        assets.push(PlannedAsset {
+            url: art.url.clone(),
+            item,
+            known,
+        });
+    }
+    let path = if config.no_path || output.is_empty() {
+        vec![]
+    } else {
+        output_path_dirs(&output)
+            .into_iter()
+            .map(|dir| {
+                let present = crud_path::has_path(&dir);
+                (dir, present)
+            })
+            .collect()
+    };
+    Ok(Plan {
+        source: url.to_string(),
+        kind: resolved.kind,
+        tag: resolved.tag,
+        assets,
+        path,
+    })
+}
+
+/// Plan every source and print the plans, continuing past failures.
+pub(crate) async fn plan_sources(sources: &[(String, InstallConfig)]) -> Result<()> {
+    let mut failed = vec![];
+    for (source, config) in sources {
+        match plan(source, config).await {
+            Ok(plan) => println!("{plan}"),
+            Err(e) => {
+                eprintln!("{source}: {e:?}");
+                failed.push(source.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to resolve: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// The files `install_from_artifact_url` would install from `art`.
+fn plan_artifact(
+    art: &ArtifactUrl,
+    kind: SourceKind,
+    config: &InstallConfig,
+) -> Result<(OutputItem, bool)> {
+    let url = art.url.as_str();
+    let install_dir = get_target_dir(config)?;
+    let mut item = OutputItem {
+        install_dir: path_to_str(&install_dir),
+        ..Default::default()
+    };
+    let file = |name: &str, mode| OutputFile {
+        mode,
+        size: 0,
+        origin_path: get_filename(url),
+        is_dir: false,
+        install_path: path_to_str(&install_dir.join(name)),
+        link: None,
+    };
+
+    // CI artifacts are zip archives behind an API endpoint.
+    let fmt = if kind == SourceKind::Ci {
+        Some(Fmt::Zip)
+    } else {
+        Fmt::guess(url)
+    };
+    if PackageFmt::guess(url).is_some() {
+        // The package payload has to be unpacked to be listed.
+        return Ok((item, false));
+    } else if Compression::guess_file(&get_filename(url)).is_some() {
+        let (_, bin) = compressed_file_names(url, config);
+        item.files = vec![file(&bin, Some(0o755))];
+    } else if let Some(fmt) = fmt {
+        let Some(path) = downloaded_file(art) else {
+            return Ok((item, false));
+        };
+        let container = Container::from(fmt);
+        let entries = archive::list(&path, container, None)?;
+        archive::check_link_parents(&entries)?;
+        if nested_archive(&entries).is_some() {
+            return Ok((item, false));
+        }
+        let (dir, files) = archive_output_files(entries, &art.name, config)?;
+        item.install_dir = path_to_str(&dir);
+        item.files = files;
+    } else {
+        let local_target = config.get_local_target();
+        if ends_with_exe(url) && local_target.iter().any(|t| t.os() != Os::Windows) {
+            return Ok((item, true));
+        }
+        let mode = is_appimage(url).then_some(0o755);
+        item.files = vec![file(&single_file_name(url, &art.name), mode)];
+    }
+    settle_output_files(&mut item.files, config);
+    Ok((item, true))
+}
+
+/// A local artifact, or a copy `get_artifact_file` would take from the
+/// download cache instead of downloading it. Looking it up leaves the cache
+/// as it is.
+fn downloaded_file(art: &ArtifactUrl) -> Option<PathBuf> {
+    if !is_url(&art.url) {
+        return Some(PathBuf::from(&art.url));
+    }
+    if cache::is_offline() || cache::is_immutable(&art.url) {
+        return cache::get_path(&art.url, true);
+    }
+    let hex = art
+        .digest
+        .as_deref()
+        .and_then(parse_digest)
+        .filter(|hex| hex.len() == 64)?;
+    cache::get_path_by_sha256(&art.url, hex, true)
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.source)?;
+        writeln!(f, "  source: {}", self.kind)?;
+        writeln!(f, "  tag:    {}", self.tag.as_deref().unwrap_or("-"))?;
+        if self.assets.is_empty() {
+            writeln!(f, "  no matching asset, nothing would be installed")?;
+        }
+        for asset in &self.assets {
+            writeln!(f, "  asset:  {}", get_filename(&asset.url))?;
+            writeln!(f, "          {}", asset.url)?;
+            for file in &asset.item.files {
+                writeln!(f, "          -> {}", file.install_path)?;
+            }
+            if !asset.known {
+                writeln!(
+                    f,
+                    "          -> {}/... (known after download)",
+                    asset.item.install_dir.trim_end_matches('/')
+                )?;
+            }
+        }
+        for (dir, present) in &self.path {
+            let note = if *present { " (already on PATH)" } else { "" };
+            writeln!(f, "  PATH:   {dir}{note}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Plan, PlannedAsset};
+    use crate::install::SourceKind;
+    use crate::types::{OutputFile, OutputItem};
+
+    #[test]
+    fn test_display_plan() {
+        let url = "https://github.com/ahaoboy/ansi2/releases/download/v0.2.11/ansi2-x86_64-unknown-linux-musl.tar.gz";
+        let plan = Plan {
+            source: "ahaoboy/ansi2".to_string(),
+            kind: SourceKind::Repo,
+            tag: Some("v0.2.11".to_string()),
+            assets: vec![
+                PlannedAsset {
+                    url: url.to_string(),
+                    item: OutputItem {
+                        install_dir: "/home/u/.ei".to_string(),
+                        files: vec![OutputFile {
+                            install_path: "/home/u/.ei/ansi2".to_string(),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    },
+                    known: true,
+                },
+                PlannedAsset {
+                    url: "https://example.com/tool.deb".to_string(),
+                    item: OutputItem {
+                        install_dir: "/home/u/.ei/".to_string(),
+                        ..Default::default()
+                    },
+                    known: false,
+                },
+            ],
+            path: vec![("/home/u/.ei".to_string(), true)],
+        };
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "ahaoboy/ansi2
+  source: GitHub release
+  tag:    v0.2.11
+  asset:  ansi2-x86_64-unknown-linux-musl.tar.gz
+          {url}
+          -> /home/u/.ei/ansi2
+  asset:  tool.deb
+          https://example.com/tool.deb
+          -> /home/u/.ei/... (known after download)
+  PATH:   /home/u/.ei (already on PATH)
+"
+            )
+        );
+    }
+}
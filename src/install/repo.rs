@@ -1,35 +1,41 @@
 use crate::InstallConfig;
-use crate::install::install_artifacts;
-use crate::install::manfiest::install_from_manfiest;
-use crate::tool::{filter_artifacts, get_artifact_url, not_found_asset_message};
-use crate::types::{Output, Repo};
+use crate::install::manfiest::resolve_manfiest;
+use crate::install::{Resolved, SourceKind};
+use crate::tool::{get_artifact_url, not_found_asset_message};
+use crate::types::Repo;
+use crate::verify::report_missing_digests;
 use anyhow::Result;
 use tracing::trace;
 
-pub(crate) async fn install_from_github(repo: &Repo, config: &InstallConfig) -> Result<Output> {
-    trace!("install_from_git {}", repo);
-    if let Ok(man) = repo
-        .get_manfiest(config.retry, config.proxy, config.timeout)
-        .await
-    {
-        return install_from_manfiest(
-            man,
-            &repo
-                .get_manfiest_url(config.proxy, config.retry, config.timeout)
-                .await?,
-            config,
-        )
-        .await;
+/// Pick the artifacts to install from a GitHub release: the dist manifest
+/// when the release has one, otherwise the release assets.
+pub(crate) async fn resolve_github(repo: &Repo, config: &InstallConfig) -> Result<Resolved> {
+    trace!("resolve_github {}", repo);
+    if let Ok(man) = repo.get_manfiest(config).await {
+        let manfiest_url = repo
+            .get_manfiest_url(config.proxy, config.retry, config.timeout)
+            .await?;
+        let tag = Repo::try_from(manfiest_url.as_str())
+            .ok()
+            .and_then(|r| r.tag);
+        return Ok(Resolved {
+            kind: SourceKind::Repo,
+            tag,
+            artifacts: resolve_manfiest(&man, &manfiest_url, config),
+        });
     }
 
-    let artifacts = repo.get_raw_artifacts(config.retry, config.timeout).await?;
+    let artifacts = repo.get_raw_artifacts(config).await?;
+    let tag = artifacts.tag_name.clone().or_else(|| repo.tag.clone());
+    report_missing_digests(&artifacts, &repo.to_string(), config);
     let available: Vec<String> = artifacts.assets.iter().map(|a| a.name.clone()).collect();
     let artifact_url = get_artifact_url(artifacts, config)?;
     if artifact_url.is_empty() {
         not_found_asset_message(&repo.get_gh_url(), config, Some(&available));
-        return Ok(Output::new());
     }
-
-    let artifact_url = filter_artifacts(artifact_url, config);
-    install_artifacts(artifact_url, config).await
+    Ok(Resolved {
+        kind: SourceKind::Repo,
+        tag,
+        artifacts: artifact_url,
+    })
 }
@@ -1,21 +1,43 @@
+mod appimage;
+mod archive;
 mod artifact;
+mod batch;
+mod cache;
 mod ci;
 mod config;
 mod download;
 mod env;
+mod file_lock;
 mod install;
+mod lock;
 mod manfiest;
 mod optimize;
+mod package;
+mod partial;
+mod picker;
+mod receipt;
+mod signature;
+mod staging;
 mod tool;
+mod toolfile;
 mod types;
+mod uninstall;
+mod update;
+mod verify;
 
-use crate::tool::expand_path;
+use crate::package::is_package_file;
+use crate::tool::{
+    expand_path, is_archive_file, is_compressed_file, is_exe_file, is_known_format, is_url,
+};
 use anyhow::{Context, Result};
 use clap::{ArgAction, CommandFactory, Parser, Subcommand};
-use config::PersistentConfig;
+use config::{PackageConfig, PersistentConfig};
+use easy_archive::human_size;
 use github_proxy::Proxy;
 use guess_target::Target;
+use receipt::Receipts;
 use tool::add_output_to_path;
+use types::{Nightly, Output, Repo};
 
 #[derive(Debug, Clone)]
 pub struct InstallConfig {
@@ -32,6 +54,16 @@ pub struct InstallConfig {
     pub no_path: bool,
     pub fuzzy: bool,
     pub regex: Option<String>,
+    pub no_verify: bool,
+    pub sha256: Option<String>,
+    pub pubkeys: Vec<String>,
+    pub desktop: bool,
+    pub explain: bool,
+    pub asset: Option<String>,
+    /// Set for `--dry-run` and `ei plan`: the cache and config are read, but
+    /// nothing is stored, dropped or touched, and no directory is created
+    /// under the install root.
+    pub dry_run: bool,
 }
 
 impl Default for InstallConfig {
@@ -50,6 +82,13 @@ impl Default for InstallConfig {
             no_path: false,
             fuzzy: false,
             regex: None,
+            no_verify: false,
+            sha256: None,
+            pubkeys: Vec::new(),
+            desktop: false,
+            explain: false,
+            asset: None,
+            dry_run: false,
         }
     }
 }
@@ -74,6 +113,76 @@ impl InstallConfig {
             no_path: false,
             fuzzy: false,
             regex: None,
+            no_verify: false,
+            sha256: None,
+            pubkeys: Vec::new(),
+            desktop: false,
+            explain: false,
+            asset: None,
+            dry_run: false,
+        }
+    }
+
+    /// Fill in settings left unset from the package preset of a source.
+    /// The global `dir` and `target` are not consulted; callers layer them
+    /// below the preset.
+    pub fn with_preset(self, preset: PackageConfig) -> Self {
+        Self {
+            dir: self.dir.or(preset.dir),
+            target: self.target.or(preset.target),
+            name: if self.name.is_empty() {
+                preset.name
+            } else {
+                self.name
+            },
+            alias: self.alias.or(preset.alias),
+            regex: self.regex.or(preset.regex),
+            fuzzy: self.fuzzy || preset.fuzzy.unwrap_or(false),
+            sha256: self.sha256.or(preset.sha256),
+            asset: self.asset.or(preset.asset),
+            pubkeys: if self.pubkeys.is_empty() {
+                preset.pubkeys
+            } else {
+                self.pubkeys
+            },
+            ..self
+        }
+    }
+
+    /// The config for installing `source` (or no particular source): the
+    /// command line wins over the package preset of `source`, which wins
+    /// over the global settings.
+    fn from_args(value: &Args, persistent: &PersistentConfig, source: Option<&str>) -> Self {
+        let preset = source.map(|s| persistent.package(s)).unwrap_or_default();
+        InstallConfig {
+            dir: value.dir.clone().or(preset.dir).or(persistent.dir.clone()),
+            name: if value.name.is_empty() {
+                preset.name
+            } else {
+                value.name.clone()
+            },
+            alias: value.alias.clone().or(preset.alias),
+            target: value.target.or(preset.target).or(persistent.target),
+            retry: value.retry,
+            proxy: value.proxy.or(persistent.proxy).unwrap_or(Proxy::Github),
+            timeout: value.timeout.or(persistent.timeout).unwrap_or(600),
+            strip: value.strip.or(persistent.strip).unwrap_or(false),
+            upx: value.upx.or(persistent.upx).unwrap_or(false),
+            quiet: value.quiet,
+            no_path: value.no_path,
+            fuzzy: value.fuzzy.or(preset.fuzzy).unwrap_or(false),
+            regex: value.regex.clone().or(preset.regex),
+            no_verify: value.no_verify,
+            sha256: value.sha256.clone().or(preset.sha256),
+            pubkeys: if value.pubkey.is_empty() {
+                preset.pubkeys
+            } else {
+                value.pubkey.clone()
+            },
+            desktop: value.desktop,
+            explain: value.explain,
+            asset: preset.asset,
+            dry_run: value.is_dry_run(),
         }
     }
 
@@ -122,6 +231,46 @@ pub enum ConfigSubcommand {
         /// Enable or disable stripping debug symbols (omit to view current value)
         value: Option<bool>,
     },
+    /// View or set the download cache size limit
+    CacheSize {
+        /// Size such as 500M or 2G, 0 for no limit (omit to view current value)
+        #[arg(value_parser = cache::parse_size)]
+        value: Option<u64>,
+    },
+    /// View or set caching of GitHub API responses for --offline
+    CacheApi {
+        /// Enable or disable caching API responses (omit to view current value)
+        value: Option<bool>,
+    },
+    /// View or set a per-repository install preset
+    Package {
+        /// Repository (`owner/repo`) or install source the preset applies to
+        repo: String,
+        /// Preset key
+        #[arg(value_parser = config::PACKAGE_KEYS)]
+        key: String,
+        /// Value to set, comma-separated for name and pubkeys (omit to view current value)
+        value: Option<String>,
+        /// Remove the key from the preset
+        #[arg(long, conflicts_with = "value")]
+        unset: bool,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum CacheSubcommand {
+    /// List cached downloads
+    List,
+    /// Show the disk space used by the cache
+    Size,
+    /// Remove every cached and partial download
+    Clean,
+    /// Remove downloads not used recently
+    Prune {
+        /// Age such as 30d, 12h or 2w
+        #[arg(long, value_name = "AGE", value_parser = cache::parse_age)]
+        older_than: u64,
+    },
 }
 
 #[derive(Debug, Clone, Subcommand)]
@@ -131,6 +280,11 @@ pub enum Command {
         #[command(subcommand)]
         subcmd: Option<ConfigSubcommand>,
     },
+    /// Manage the download cache
+    Cache {
+        #[command(subcommand)]
+        subcmd: CacheSubcommand,
+    },
     /// Generate shell completion scripts
     Completions {
         /// Shell type to generate completions for
@@ -139,6 +293,60 @@ pub enum Command {
     },
     /// Upgrade crash to the latest version
     Upgrade,
+    /// List packages installed by ei
+    List {
+        /// Show the files and assets of a single package
+        name: Option<String>,
+        /// Print receipts as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Remove installed packages and the PATH entries they added
+    Uninstall {
+        /// Package names as shown by `ei list`
+        #[arg(required = true)]
+        names: Vec<String>,
+        /// Remove files even if they were modified after installation
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Show installed GitHub packages with a newer release (exits non-zero if any)
+    Outdated,
+    /// Reinstall GitHub packages whose latest release is newer
+    Update {
+        /// Package names as shown by `ei list`
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        names: Vec<String>,
+        /// Update every installed GitHub package
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Resolve sources to exact downloads and record them in ei.lock
+    Lock {
+        /// Sources to lock (omit to lock ei.toml, or re-resolve every package in ei.lock)
+        sources: Vec<String>,
+    },
+    /// Install every package listed in ei.toml that is missing or outdated
+    Sync {
+        /// Remove packages installed by an earlier sync that ei.toml no longer lists
+        #[arg(long, default_value_t = false)]
+        prune: bool,
+    },
+    /// Show what installing sources would do, without changing anything
+    Plan {
+        /// Sources to plan
+        #[arg(required = true)]
+        sources: Vec<String>,
+    },
+    /// Install sources, or with --locked the packages recorded in ei.lock
+    Install {
+        /// Sources to install, or package names from ei.lock with --locked
+        #[arg(required_unless_present = "locked")]
+        sources: Vec<String>,
+        /// Install exactly the URLs and hashes recorded in ei.lock
+        #[arg(long, default_value_t = false)]
+        locked: bool,
+    },
 }
 
 const CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -155,6 +363,18 @@ pub struct Args {
     #[arg(default_value_t = String::new())]
     pub url: String,
 
+    /// More sources to install along with `url`
+    pub urls: Vec<String>,
+
+    /// Read sources from a file, one per line (`-` for stdin)
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        help = "Read sources from a file, one per line (`-` for stdin)"
+    )]
+    pub file: Option<String>,
+
     /// Installation directory for downloaded binaries
     #[arg(short, long, help = "Installation directory")]
     pub dir: Option<String>,
@@ -243,10 +463,9 @@ pub struct Args {
         long,
         help = "Use fuzzy target matching (match arch+os, ignoring abi)",
         default_missing_value = "true",
-        num_args = 0..=1,
-        default_value_t = false
+        num_args = 0..=1
     )]
-    pub fuzzy: bool,
+    pub fuzzy: Option<bool>,
 
     /// Regex pattern to match against the original GitHub asset filenames
     ///
@@ -266,13 +485,165 @@ pub struct Args {
         help = "Regex to match asset filenames directly (bypasses guess_target)"
     )]
     pub regex: Option<String>,
+
+    /// Skip checksum verification of downloaded assets
+    ///
+    /// By default ei looks for a checksum asset published next to the
+    /// selected one (`<asset>.sha256`, `checksums.txt`, `SHA256SUMS`, ...)
+    /// and aborts the install when the downloaded bytes don't match. This
+    /// also skips signature checks for trusted public keys, with a warning.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = false,
+        help = "Skip checksum verification of downloaded assets"
+    )]
+    pub no_verify: bool,
+
+    /// Expected SHA-256 of the downloaded asset
+    ///
+    /// The install fails if the downloaded bytes don't match, and is
+    /// rejected when more than one asset would be installed. The pin is
+    /// saved as a package preset in the config file and enforced on later
+    /// installs of the same source.
+    #[arg(
+        long,
+        value_name = "HEX",
+        value_parser = verify::parse_sha256,
+        help = "Expected SHA-256 of the downloaded asset"
+    )]
+    pub sha256: Option<String>,
+
+    /// Trusted public key for release signatures (repeatable)
+    ///
+    /// A minisign key (`RWQ...` or a `minisign.pub` file) or an OpenSSH
+    /// Ed25519 key (`ssh-ed25519 AAAA...` or a `.pub` file). When set, the
+    /// selected asset must have a `.minisig` or `.sig` signature published
+    /// next to it that verifies against one of the keys.
+    #[arg(
+        long,
+        value_name = "KEY",
+        value_parser = signature::parse_pubkey,
+        help = "Trusted minisign/SSH public key for release signatures"
+    )]
+    pub pubkey: Vec<String>,
+
+    /// Add installed AppImages to the desktop application menu
+    ///
+    /// Extracts the `.desktop` file and icon embedded in the AppImage into
+    /// `~/.local/share/applications` and `~/.local/share/icons`. They are
+    /// written there directly, not staged with the install, so a failed
+    /// install does not roll them back. They are removed again by
+    /// `ei uninstall`.
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Add installed AppImages to the desktop application menu (written outside the staged install, not rolled back)"
+    )]
+    pub desktop: bool,
+
+    /// Print every candidate asset and why it was selected or not
+    ///
+    /// Lists each release asset with the tool name and target parsed from
+    /// its filename, the rule that accepted it (exact target, ABI fallback,
+    /// fuzzy match, package or AppImage) with its final rank, and why each
+    /// of the others was rejected or lost to a better candidate.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = false,
+        help = "Print every candidate asset and why it was selected or not"
+    )]
+    pub explain: bool,
+
+    /// Install only from the download cache, without network access
+    ///
+    /// Fails on the first download that isn't cached. Releases resolve
+    /// through the cached API responses of earlier online installs.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = false,
+        help = "Install only from the download cache, without network access"
+    )]
+    pub offline: bool,
+
+    /// Seconds to wait for another ei process installing to the same directory
+    ///
+    /// Concurrent ei runs take turns writing the same install directory,
+    /// receipts and config file; this bounds how long one waits for another.
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        default_value_t = file_lock::DEFAULT_LOCK_TIMEOUT,
+        help = "Seconds to wait for another ei process"
+    )]
+    pub lock_timeout: u64,
+
+    /// Show what would be installed, without changing anything
+    ///
+    /// Resolves the sources like an install and prints the source kind,
+    /// release tag, selected assets, install paths and PATH entries. Nothing
+    /// is downloaded or written; install paths inside archives are listed
+    /// when the archive is already in the download cache.
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Show what would be installed, without changing anything"
+    )]
+    pub dry_run: bool,
+}
+
+impl Args {
+    /// The sources given on the command line: `url` followed by `urls`.
+    pub fn sources(&self) -> Vec<String> {
+        std::iter::once(&self.url)
+            .chain(&self.urls)
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.dry_run || matches!(self.cmd, Some(Command::Plan { .. }))
+    }
+
+    /// The config file, left untouched during a dry run.
+    fn persistent_config(&self) -> PersistentConfig {
+        if self.is_dry_run() {
+            PersistentConfig::read()
+        } else {
+            PersistentConfig::load()
+        }
+    }
+
+    /// A config for each of `sources`, each with its own package preset.
+    fn source_configs(&self, sources: &[String]) -> Result<Vec<(String, InstallConfig)>> {
+        if self.sha256.is_some() && sources.len() > 1 {
+            anyhow::bail!(
+                "--sha256 pins a single source, but {} were given",
+                sources.len()
+            );
+        }
+        let persistent_config = self.persistent_config();
+        Ok(sources
+            .iter()
+            .map(|s| {
+                let config = InstallConfig::from_args(self, &persistent_config, Some(s));
+                (s.clone(), config)
+            })
+            .collect())
+    }
 }
 
 impl Default for Args {
     fn default() -> Self {
         Self {
             cmd: None,
-            url: "".to_string(),
+            url: String::new(),
+            urls: vec![],
+            file: None,
             dir: None,
             no_path: false,
             name: vec![],
@@ -284,48 +655,48 @@ impl Default for Args {
             strip: None,
             upx: None,
             quiet: false,
-            fuzzy: false,
+            fuzzy: None,
             regex: None,
+            no_verify: false,
+            sha256: None,
+            pubkey: vec![],
+            desktop: false,
+            explain: false,
+            offline: false,
+            lock_timeout: file_lock::DEFAULT_LOCK_TIMEOUT,
+            dry_run: false,
         }
     }
 }
 
 impl From<Args> for InstallConfig {
     fn from(value: Args) -> Self {
-        let persistent_config = PersistentConfig::load();
-
-        let proxy = value
-            .proxy
-            .or(persistent_config.proxy)
-            .unwrap_or(Proxy::Github);
-
-        let timeout = value.timeout.or(persistent_config.timeout).unwrap_or(600);
-
-        let dir = value.dir.clone().or(persistent_config.dir);
-
-        let target = value.target.or(persistent_config.target);
-        let strip = value.strip.or(persistent_config.strip).unwrap_or(false);
-        let upx = value.upx.or(persistent_config.upx).unwrap_or(false);
-
-        InstallConfig {
-            dir,
-            name: value.name.clone(),
-            alias: value.alias.clone(),
-            target,
-            retry: value.retry,
-            proxy,
-            timeout,
-            strip,
-            upx,
-            quiet: value.quiet,
-            no_path: value.no_path,
-            fuzzy: value.fuzzy,
-            regex: value.regex,
+        let persistent_config = value.persistent_config();
+        // Presets belong to a single source; batches apply them per source.
+        match value.sources().as_slice() {
+            [url] => InstallConfig::from_args(&value, &persistent_config, Some(url)),
+            _ => InstallConfig::from_args(&value, &persistent_config, None),
         }
     }
 }
 
 pub async fn run_main(args: Args) -> Result<()> {
+    cache::set_offline(args.offline);
+    file_lock::set_lock_timeout(args.lock_timeout);
+
+    if args.dry_run {
+        match &args.cmd {
+            None | Some(Command::Plan { .. }) => {}
+            Some(Command::Install {
+                sources,
+                locked: false,
+            }) => {
+                return install::plan_sources(&args.source_configs(sources)?).await;
+            }
+            Some(_) => anyhow::bail!("--dry-run only applies to installs; see `ei plan`"),
+        }
+    }
+
     // Handle completions subcommand
     if let Some(Command::Completions { shell }) = args.cmd {
         return handle_completions_command(shell);
@@ -335,6 +706,53 @@ pub async fn run_main(args: Args) -> Result<()> {
         return handle_upgrade(args.into()).await;
     }
 
+    if let Some(Command::List { name, json }) = args.cmd {
+        return handle_list_command(name.as_deref(), json);
+    }
+
+    if let Some(Command::Cache { subcmd }) = args.cmd {
+        return match subcmd {
+            CacheSubcommand::List => cache::list(),
+            CacheSubcommand::Size => cache::size(),
+            CacheSubcommand::Clean => cache::clean(args.quiet),
+            CacheSubcommand::Prune { older_than } => cache::prune(older_than, args.quiet),
+        };
+    }
+
+    if let Some(Command::Outdated) = args.cmd {
+        return update::outdated(&args.into()).await;
+    }
+
+    if let Some(Command::Update { names, .. }) = args.cmd.clone() {
+        return update::update(&names, &args.into()).await;
+    }
+
+    if let Some(Command::Lock { sources }) = args.cmd.clone() {
+        return lock::lock(&sources, &args.into()).await;
+    }
+
+    if let Some(Command::Sync { prune }) = args.cmd {
+        return toolfile::sync(prune, &args.into()).await;
+    }
+
+    if let Some(Command::Plan { sources }) = &args.cmd {
+        return install::plan_sources(&args.source_configs(sources)?).await;
+    }
+
+    if let Some(Command::Install { sources, locked }) = args.cmd.clone() {
+        if locked {
+            return lock::install_locked(&sources, &args.into()).await;
+        }
+        return batch::install_batch(&args.source_configs(&sources)?).await;
+    }
+
+    if let Some(Command::Uninstall { names, force }) = args.cmd {
+        for name in &names {
+            uninstall::uninstall(name, force, args.quiet)?;
+        }
+        return Ok(());
+    }
+
     // Handle config subcommand
     if let Some(Command::Config { subcmd }) = args.cmd {
         let quiet = args.quiet;
@@ -342,27 +760,121 @@ pub async fn run_main(args: Args) -> Result<()> {
     }
 
     // Regular install command
-    let url = args.url.clone();
+    let mut urls = args.sources();
+    if let Some(file) = &args.file {
+        urls.extend(batch::read_source_list(file)?);
+        if urls.is_empty() {
+            anyhow::bail!("No sources found in {file}");
+        }
+    }
 
-    if url.is_empty() {
+    if urls.is_empty() {
         let s = Args::command().render_help();
         println!("{s}");
         return Ok(());
     }
-    let config = args.into();
+    if args.dry_run {
+        return install::plan_sources(&args.source_configs(&urls)?).await;
+    }
+    if urls.len() > 1 {
+        return batch::install_batch(&args.source_configs(&urls)?).await;
+    }
+
+    // The source may come from --file, so its preset is looked up here.
+    let (url, config) = args.source_configs(&urls)?.remove(0);
     ei(&url, &config).await?;
+    if let Some(sha256) = args.sha256 {
+        save_sha256_pin(&url, sha256, config.quiet)?;
+    }
     Ok(())
 }
 
 pub async fn ei(url: &str, config: &InstallConfig) -> Result<()> {
     let output = install::install(url, config).await?;
-    if !config.no_path {
-        add_output_to_path(&output, config);
+    record_install(url, config, &output);
+    Ok(())
+}
+
+/// Put the installed directories on PATH and write the receipt.
+pub(crate) fn record_install(url: &str, config: &InstallConfig, output: &Output) {
+    // PATH edits and the receipt are shared by every ei process.
+    let _lock = match Receipts::lock(config.quiet) {
+        Ok(lock) => Some(lock),
+        Err(e) => {
+            if !config.quiet {
+                eprintln!("Warning: {e:?}");
+            }
+            None
+        }
+    };
+    let path = if config.no_path {
+        vec![]
+    } else {
+        add_output_to_path(output, config)
+    };
+    if output.is_empty() {
+        if !config.quiet {
+            println!("No file installed from {url}");
+        }
+    } else if let Err(e) = Receipts::record(url, config, output, path)
+        && !config.quiet
+    {
+        eprintln!("Warning: failed to record installation of {url}: {e:?}");
     }
-    if output.is_empty() && !config.quiet {
-        println!("No file installed from {url}");
+}
+
+/// Whether `url` always names the same bytes: a tagged release
+/// (`owner/repo@tag`, release download URLs) or a direct asset URL.
+fn is_fixed_source(url: &str) -> bool {
+    if Repo::try_from(url).is_ok_and(|r| r.tag.is_some()) {
+        return true;
     }
-    Ok(())
+    is_url(url)
+        && Nightly::try_from(url).is_err()
+        && (is_archive_file(url)
+            || is_package_file(url)
+            || is_compressed_file(url)
+            || is_exe_file(url).unwrap_or(false)
+            || is_known_format(url))
+}
+
+/// Remember a `--sha256` pin so later installs of `url` enforce it too.
+/// Only sources that can't move to a new release keep their pin; the hash
+/// of `owner/repo` would stop matching at its next release.
+fn save_sha256_pin(url: &str, sha256: String, quiet: bool) -> Result<()> {
+    if !is_fixed_source(url) {
+        if !quiet {
+            eprintln!(
+                "Note: the --sha256 pin was not saved, {url} does not name a fixed release or asset"
+            );
+        }
+        return Ok(());
+    }
+    let _lock = PersistentConfig::lock(quiet)?;
+    let mut config = PersistentConfig::load();
+    if config.packages.get(url).and_then(|p| p.sha256.as_ref()) == Some(&sha256) {
+        return Ok(());
+    }
+    config.set_package_sha256(url, sha256);
+    config.save_quiet(quiet)
+}
+
+fn handle_list_command(name: Option<&str>, json: bool) -> Result<()> {
+    let receipts = Receipts::load()?;
+    if json {
+        let s = match name {
+            Some(name) => {
+                let key = receipts
+                    .find(name)
+                    .with_context(|| format!("{name} is not installed"))?;
+                serde_json::to_string_pretty(&receipts.packages[&key])?
+            }
+            None => serde_json::to_string_pretty(&receipts)?,
+        };
+        println!("{s}");
+        return Ok(());
+    }
+    receipt::display_receipts(&receipts, name)
 }
 
 fn handle_completions_command(shell: clap_complete::Shell) -> Result<()> {
@@ -419,6 +931,7 @@ async fn handle_upgrade(user_config: InstallConfig) -> Result<()> {
 }
 
 fn handle_config_command(subcmd: Option<ConfigSubcommand>, quiet: bool) -> Result<()> {
+    let _lock = PersistentConfig::lock(quiet)?;
     let mut config = PersistentConfig::load();
 
     let Some(subcmd) = subcmd else {
@@ -531,6 +1044,77 @@ fn handle_config_command(subcmd: Option<ConfigSubcommand>, quiet: bool) -> Resul
                 current,
             )?
         }
+        ConfigSubcommand::CacheSize { value } => {
+            let current = match config.cache_size {
+                None => format!(
+                    "not set (default: {})",
+                    human_size(cache::DEFAULT_CACHE_SIZE as usize)
+                ),
+                Some(0) => "no limit".to_string(),
+                Some(n) => human_size(n as usize),
+            };
+            apply_config(
+                &mut config,
+                quiet,
+                value,
+                PersistentConfig::set_cache_size,
+                |v: &u64| match v {
+                    0 => "no limit".to_string(),
+                    n => human_size(*n as usize),
+                },
+                "Cache size",
+                current,
+            )?
+        }
+        ConfigSubcommand::CacheApi { value } => {
+            let current = config
+                .cache_api
+                .map_or("not set (default: false)".to_string(), |t| format!("{t}"));
+            apply_config(
+                &mut config,
+                quiet,
+                value,
+                PersistentConfig::set_cache_api,
+                |v: &bool| v.to_string(),
+                "Cache API",
+                current,
+            )?
+        }
+        ConfigSubcommand::Package {
+            repo,
+            key,
+            value,
+            unset,
+        } => {
+            if value.is_none() && !unset {
+                let current = config
+                    .packages
+                    .get(&repo)
+                    .map(|p| p.get(&key))
+                    .transpose()?
+                    .flatten();
+                if !quiet {
+                    println!(
+                        "Current {repo} {key}: {}",
+                        current.as_deref().unwrap_or("not set")
+                    );
+                }
+                return Ok(());
+            }
+            let preset = config.packages.entry(repo.clone()).or_default();
+            preset.set(&key, value.as_deref())?;
+            let msg = match preset.get(&key)? {
+                Some(v) => format!("{repo} {key} set to: {v}"),
+                None => format!("{repo} {key} unset"),
+            };
+            if *preset == PackageConfig::default() {
+                config.packages.remove(&repo);
+            }
+            config.save_quiet(quiet)?;
+            if !quiet {
+                println!("{msg}");
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,237 @@
+use crate::artifact::ArtifactUrl;
+use crate::ci::{CiRun, RE_CI_WORKFLOW};
+use crate::download::get_artifact_file;
+use crate::install::{install_artifacts, resolve, set_tag};
+use crate::receipt::{InstallOptions, asset_target, package_name};
+use crate::toolfile::{DEFAULT_TOOLFILE_NAME, Toolfile};
+use crate::types::Repo;
+use crate::verify::{parse_digest, sha256_file};
+use crate::{InstallConfig, record_install};
+use anyhow::{Context, Result};
+use github_proxy::Proxy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const DEFAULT_LOCKFILE_NAME: &str = "ei.lock";
+const LOCKFILE_VERSION: u32 = 1;
+
+/// Exact downloads for every package of a project, keyed by package name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Lockfile {
+    pub(crate) version: u32,
+    #[serde(default)]
+    pub(crate) packages: BTreeMap<String, LockedPackage>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            packages: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LockedPackage {
+    /// Source as given to `ei lock`.
+    pub(crate) source: String,
+    /// Release tag the source resolved to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tag: Option<String>,
+    /// Target triple the artifacts were selected for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) target: Option<String>,
+    pub(crate) artifacts: Vec<LockedArtifact>,
+    #[serde(default)]
+    pub(crate) options: InstallOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LockedArtifact {
+    pub(crate) name: String,
+    /// Canonical download URL; the configured proxy is applied at install time.
+    pub(crate) url: String,
+    pub(crate) sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) signature_url: Option<String>,
+}
+
+impl LockedArtifact {
+    fn to_artifact_url(&self, proxy: Proxy) -> ArtifactUrl {
+        ArtifactUrl {
+            name: self.name.clone(),
+            url: Repo::convert_github_url_to_proxy(&self.url, proxy),
+            signature_url: self
+                .signature_url
+                .as_deref()
+                .map(|u| Repo::convert_github_url_to_proxy(u, proxy)),
+            sha256: Some(self.sha256.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+impl Lockfile {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let lock: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if lock.version > LOCKFILE_VERSION {
+            anyhow::bail!(
+                "{} was written by a newer ei (lockfile version {})",
+                path.display(),
+                lock.version
+            );
+        }
+        Ok(lock)
+    }
+
+    /// Replace the file whole, so a crash or a concurrent `ei lock` can't
+    /// leave it truncated.
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize lockfile")?;
+        let tmp = path.with_extension(format!("lock.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, content + "\n")
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Resolve `source` to a concrete release and hash every artifact it selects.
+async fn lock_package(source: &str, config: &InstallConfig) -> Result<LockedPackage> {
+    if CiRun::try_from(source).is_ok() || RE_CI_WORKFLOW.is_match(source) {
+        anyhow::bail!(
+            "GitHub Actions artifacts expire and can't be resolved ahead of time: {source}"
+        );
+    }
+    // Resolve against github.com so the lockfile holds canonical URLs.
+    let resolve_config = InstallConfig {
+        proxy: Proxy::Github,
+        ..config.clone()
+    };
+    // Pin "latest" first so every URL below belongs to one release.
+    let source_tagged = match Repo::try_from(source) {
+        Ok(repo) if repo.tag.is_none() && !Path::new(source).exists() => {
+            let tag = repo.get_latest_release_tag(config).await?;
+            format!("{}/{}@{tag}", repo.owner, repo.name)
+        }
+        _ => source.to_string(),
+    };
+    let resolved = resolve(&source_tagged, &resolve_config).await?;
+    if resolved.artifacts.is_empty() {
+        anyhow::bail!("No artifact found for {source}");
+    }
+
+    let mut artifacts = vec![];
+    for art in &resolved.artifacts {
+        let sha256 = match art.digest.as_deref().and_then(parse_digest) {
+            Some(hex) if hex.len() == 64 => hex.to_ascii_lowercase(),
+            _ => {
+                let download = ArtifactUrl {
+                    url: Repo::convert_github_url_to_proxy(&art.url, config.proxy),
+                    ..art.clone()
+                };
+                if !config.quiet {
+                    println!("download {}", download.url);
+                }
+                sha256_file(&get_artifact_file(&download, config).await?)?
+            }
+        };
+        artifacts.push(LockedArtifact {
+            name: art.name.clone(),
+            url: art.url.clone(),
+            sha256,
+            signature_url: art.signature_url.clone(),
+        });
+    }
+
+    Ok(LockedPackage {
+        source: source.to_string(),
+        tag: resolved.tag,
+        target: artifacts.first().and_then(|a| asset_target(&a.url, config)),
+        artifacts,
+        options: config.into(),
+    })
+}
+
+/// Resolve sources and record them in the lockfile. Without sources, the
+/// packages of `ei.toml` are locked, or else every package already in the
+/// lockfile is resolved again.
+pub(crate) async fn lock(sources: &[String], config: &InstallConfig) -> Result<()> {
+    let path = Path::new(DEFAULT_LOCKFILE_NAME);
+    let mut lockfile = Lockfile::load(path)?;
+    let toolfile = Path::new(DEFAULT_TOOLFILE_NAME);
+
+    let entries: Vec<(String, InstallConfig)> = if !sources.is_empty() {
+        sources
+            .iter()
+            .map(|s| (s.clone(), config.clone()))
+            .collect()
+    } else if toolfile.exists() {
+        Toolfile::load(toolfile)?.entries(config)?
+    } else {
+        lockfile
+            .packages
+            .values()
+            .map(|p| (p.source.clone(), p.options.apply(config.clone())))
+            .collect()
+    };
+    if entries.is_empty() {
+        anyhow::bail!("Nothing to lock: pass sources or list packages in {DEFAULT_TOOLFILE_NAME}");
+    }
+
+    for (source, config) in entries {
+        let locked = lock_package(&source, &config)
+            .await
+            .with_context(|| format!("Failed to lock {source}"))?;
+        if !config.quiet {
+            println!(
+                "locked {source} {}",
+                locked.tag.as_deref().unwrap_or("(no tag)")
+            );
+        }
+        lockfile
+            .packages
+            .insert(package_name(&source, &config), locked);
+    }
+    lockfile.save(path)
+}
+
+/// Install packages exactly as recorded in the lockfile, without resolving
+/// tags or guessing targets again.
+pub(crate) async fn install_locked(names: &[String], config: &InstallConfig) -> Result<()> {
+    let path = Path::new(DEFAULT_LOCKFILE_NAME);
+    if !path.exists() {
+        anyhow::bail!("{DEFAULT_LOCKFILE_NAME} not found; run `ei lock <SOURCE>...` first");
+    }
+    let lockfile = Lockfile::load(path)?;
+    for name in names {
+        if !lockfile.packages.contains_key(name) {
+            anyhow::bail!("{name} is not in {DEFAULT_LOCKFILE_NAME}");
+        }
+    }
+
+    for (name, pkg) in &lockfile.packages {
+        if !names.is_empty() && !names.contains(name) {
+            continue;
+        }
+        let config = pkg.options.apply(config.clone());
+        let artifacts = pkg
+            .artifacts
+            .iter()
+            .map(|a| a.to_artifact_url(config.proxy))
+            .collect();
+        let mut output = install_artifacts(artifacts, &config)
+            .await
+            .with_context(|| format!("Failed to install {name} from {DEFAULT_LOCKFILE_NAME}"))?;
+        set_tag(&mut output, pkg.tag.as_deref());
+        record_install(&pkg.source, &config, &output);
+    }
+    Ok(())
+}
@@ -29,6 +29,10 @@ pub(crate) struct Artifact {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(default)]
     pub(crate) assets: Vec<Asset>,
+    /// The checksum artifact for this artifact (e.g. `<artifact>.sha256`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub(crate) checksum: Option<ArtifactId>,
 }
 /// An asset contained in an artifact (executable, license, etc.)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,581 @@
+use crate::archive::{Compression, Container, Entry};
+use crate::cache::scratch_path;
+use crate::types::Link;
+use anyhow::{Context, Result};
+use easy_archive::Fmt;
+use guess_target::{Arch, Os, Target};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::trace;
+
+/// Top-level directories of `/usr` that are installed; everything else in a
+/// package (`etc`, maintainer scripts, ...) belongs to the system.
+const USR_DIRS: [&str; 6] = ["bin", "sbin", "lib", "lib64", "libexec", "share"];
+
+/// Linux distribution packages ei can unpack without a package manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PackageFmt {
+    Deb,
+    Rpm,
+}
+
+impl PackageFmt {
+    pub(crate) fn guess(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name.ends_with(".deb") {
+            Some(PackageFmt::Deb)
+        } else if name.ends_with(".rpm") {
+            Some(PackageFmt::Rpm)
+        } else {
+            None
+        }
+    }
+
+    /// Tool name from a file name like `ripgrep_14.1.0-1_amd64.deb` or
+    /// `ripgrep-14.1.0-1.x86_64.rpm`.
+    pub(crate) fn package_name(&self, stem: &str) -> String {
+        match self {
+            PackageFmt::Deb => stem.split('_').next().unwrap_or(stem).to_string(),
+            PackageFmt::Rpm => stem.rsplitn(3, '-').last().unwrap_or(stem).to_string(),
+        }
+    }
+}
+
+pub(crate) fn is_package_file(s: &str) -> bool {
+    PackageFmt::guess(s).is_some()
+}
+
+/// Whether a package file name is built for `target`: Linux, and either the
+/// same architecture or none in particular.
+pub(crate) fn package_matches(stem: &str, target: &Target) -> bool {
+    if target.os() != Os::Linux {
+        return false;
+    }
+    let stem = stem.replace("x86_64", "amd64");
+    stem.split(['_', '-', '.']).any(|token| match token {
+        "amd64" => target.arch() == Arch::X86_64,
+        "arm64" | "aarch64" => target.arch() == Arch::Aarch64,
+        "all" | "noarch" => true,
+        _ => false,
+    })
+}
+
+/// The file tree a package installs. A `.deb` payload is unpacked next to
+/// partial downloads and removed on drop; an RPM payload is read from the
+/// package itself.
+pub(crate) struct Payload {
+    pub(crate) path: PathBuf,
+    pub(crate) container: Container,
+    /// Package name from the package metadata.
+    pub(crate) name: Option<String>,
+    /// Package version from the package metadata.
+    pub(crate) version: Option<String>,
+    scratch: bool,
+}
+
+impl Drop for Payload {
+    fn drop(&mut self) {
+        if self.scratch {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+pub(crate) fn extract_payload(path: &Path, pkg: PackageFmt, url: &str) -> Result<Payload> {
+    match pkg {
+        #[cfg(feature = "deb")]
+        PackageFmt::Deb => deb_payload(path, url),
+        #[cfg(not(feature = "deb"))]
+        PackageFmt::Deb => {
+            _ = url;
+            anyhow::bail!("ei was built without the deb feature")
+        }
+        PackageFmt::Rpm => {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            let header = read_rpm_header(&mut std::io::BufReader::new(file))?;
+            // Fail before anything is listed if the payload can't be read.
+            header.compression()?;
+            Ok(Payload {
+                path: path.to_path_buf(),
+                container: Container::Rpm,
+                version: header.version(),
+                name: header.name,
+                scratch: false,
+            })
+        }
+    }
+}
+
+/// Format of a `data.tar.*` or `control.tar.*` member.
+fn tar_fmt(name: &str) -> Option<Fmt> {
+    let (_, ext) = name.split_once(".tar")?;
+    match ext {
+        "" => Some(Fmt::Tar),
+        ".gz" => Some(Fmt::TarGz),
+        ".xz" => Some(Fmt::TarXz),
+        ".bz2" => Some(Fmt::TarBz),
+        ".zst" => Some(Fmt::TarZstd),
+        _ => None,
+    }
+}
+
+/// A `.deb` is an `ar` archive of `debian-binary`, `control.tar.*` with the
+/// metadata and `data.tar.*` with the files.
+#[cfg(feature = "deb")]
+fn deb_payload(path: &Path, url: &str) -> Result<Payload> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = ar::Archive::new(file);
+    let mut control = None;
+    let mut payload = None;
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry.context("Failed to read deb archive")?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).to_string();
+        let Some(fmt) = tar_fmt(&name)
+            .filter(|_| name.starts_with("control.tar") || name.starts_with("data.tar"))
+        else {
+            trace!("skip deb member {name}");
+            continue;
+        };
+        let dst = scratch_path(url, &name)?;
+        let mut out = std::fs::File::create(&dst)
+            .with_context(|| format!("Failed to create {}", dst.display()))?;
+        std::io::copy(&mut entry, &mut out).with_context(|| format!("Failed to extract {name}"))?;
+        if name.starts_with("control.tar") {
+            let res = read_control(&dst, fmt);
+            let _ = std::fs::remove_file(&dst);
+            control = Some(res?);
+        } else {
+            payload = Some(Payload {
+                path: dst,
+                container: fmt.into(),
+                name: None,
+                version: None,
+                scratch: true,
+            });
+        }
+    }
+    let mut payload = payload.context("No data.tar member in deb package")?;
+    if let Some(control) = control {
+        payload.name = control_field(&control, "Package");
+        payload.version = control_field(&control, "Version");
+    }
+    Ok(payload)
+}
+
+#[cfg(feature = "deb")]
+fn read_control(path: &Path, fmt: Fmt) -> Result<String> {
+    let mut control = String::new();
+    crate::archive::for_each_entry(path, fmt, |entry, reader| {
+        if entry.path == "control" {
+            reader
+                .read_to_string(&mut control)
+                .context("Failed to read deb control file")?;
+        }
+        Ok(())
+    })?;
+    Ok(control)
+}
+
+/// A single-line field of a Debian control file.
+fn control_field(control: &str, name: &str) -> Option<String> {
+    control.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key == name && !value.trim().is_empty()).then(|| value.trim().to_string())
+    })
+}
+
+const RPM_LEAD_LEN: usize = 96;
+const RPM_LEAD_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
+const RPM_HEADER_MAGIC: [u8; 3] = [0x8e, 0xad, 0xe8];
+/// Upper bound for the index and data of a header, against corrupt input.
+const RPM_HEADER_MAX: usize = 64 << 20;
+const RPMTAG_NAME: u32 = 1000;
+const RPMTAG_VERSION: u32 = 1001;
+const RPMTAG_RELEASE: u32 = 1002;
+const RPMTAG_PAYLOADFORMAT: u32 = 1124;
+const RPMTAG_PAYLOADCOMPRESSOR: u32 = 1125;
+const RPM_STRING_TYPE: u32 = 6;
+
+/// The string tags of an RPM header that ei uses.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RpmHeader {
+    pub(crate) name: Option<String>,
+    version: Option<String>,
+    release: Option<String>,
+    payload_format: Option<String>,
+    payload_compressor: Option<String>,
+}
+
+impl RpmHeader {
+    /// `version-release`, as `rpm -q` prints it.
+    pub(crate) fn version(&self) -> Option<String> {
+        let version = self.version.clone()?;
+        Some(match &self.release {
+            Some(release) => format!("{version}-{release}"),
+            None => version,
+        })
+    }
+
+    pub(crate) fn compression(&self) -> Result<Compression> {
+        if let Some(format) = &self.payload_format
+            && format != "cpio"
+        {
+            anyhow::bail!("Unsupported RPM payload format {format}");
+        }
+        // Packages that predate the tag are gzip compressed.
+        Ok(match self.payload_compressor.as_deref().unwrap_or("gzip") {
+            "gzip" => Compression::Gzip,
+            "xz" => Compression::Xz,
+            "zstd" => Compression::Zstd,
+            "bzip2" => Compression::Bzip2,
+            "identity" => Compression::None,
+            other => anyhow::bail!("Unsupported RPM payload compressor {other}"),
+        })
+    }
+}
+
+/// Read one header structure: a 16 byte intro, the index of 16 byte tag
+/// entries, then the data they point into. Returns the string tags and the
+/// length of the structure.
+fn read_header_structure(reader: &mut impl Read) -> Result<(Vec<(u32, String)>, usize)> {
+    let mut intro = [0u8; 16];
+    reader
+        .read_exact(&mut intro)
+        .context("Failed to read RPM header")?;
+    if intro[..3] != RPM_HEADER_MAGIC {
+        anyhow::bail!("Invalid RPM header");
+    }
+    let be = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize;
+    let (count, data_len) = (be(&intro[8..12]), be(&intro[12..16]));
+    if count * 16 + data_len > RPM_HEADER_MAX {
+        anyhow::bail!("RPM header too large");
+    }
+    let mut index = vec![0u8; count * 16];
+    let mut data = vec![0u8; data_len];
+    reader
+        .read_exact(&mut index)
+        .and_then(|_| reader.read_exact(&mut data))
+        .context("Failed to read RPM header")?;
+
+    let mut tags = vec![];
+    for entry in index.chunks_exact(16) {
+        let (tag, kind, offset) = (
+            be(&entry[..4]) as u32,
+            be(&entry[4..8]) as u32,
+            be(&entry[8..12]),
+        );
+        if kind != RPM_STRING_TYPE {
+            continue;
+        }
+        let Some(rest) = data.get(offset..) else {
+            anyhow::bail!("Invalid RPM header");
+        };
+        let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        tags.push((tag, String::from_utf8_lossy(&rest[..end]).to_string()));
+    }
+    Ok((tags, 16 + index.len() + data_len))
+}
+
+/// Read the lead, signature and header of an RPM, leaving `reader` at the
+/// start of the compressed payload.
+pub(crate) fn read_rpm_header(reader: &mut impl Read) -> Result<RpmHeader> {
+    let mut lead = [0u8; RPM_LEAD_LEN];
+    reader
+        .read_exact(&mut lead)
+        .context("Failed to read RPM lead")?;
+    if lead[..4] != RPM_LEAD_MAGIC {
+        anyhow::bail!("Not an RPM package");
+    }
+    // The signature is a header structure too, padded to eight bytes.
+    let (_, len) = read_header_structure(reader)?;
+    let padding = (8 - len % 8) % 8;
+    std::io::copy(&mut reader.take(padding as u64), &mut std::io::sink())?;
+
+    let (tags, _) = read_header_structure(reader)?;
+    let mut header = RpmHeader::default();
+    for (tag, value) in tags {
+        let field = match tag {
+            RPMTAG_NAME => &mut header.name,
+            RPMTAG_VERSION => &mut header.version,
+            RPMTAG_RELEASE => &mut header.release,
+            RPMTAG_PAYLOADFORMAT => &mut header.payload_format,
+            RPMTAG_PAYLOADCOMPRESSOR => &mut header.payload_compressor,
+            _ => continue,
+        };
+        *field = Some(value);
+    }
+    Ok(header)
+}
+
+/// Where a path of the system root is installed: `usr/bin/x` becomes
+/// `bin/x` and `opt/x` stays as it is, so executables end up one level
+/// below the install directory, where `add_output_to_path` looks.
+fn rebase(path: &str) -> Option<String> {
+    if let Some(rest) = path.strip_prefix("usr/") {
+        let top = rest.split('/').next().unwrap_or_default();
+        USR_DIRS.contains(&top).then(|| rest.to_string())
+    } else if path.starts_with("opt/") {
+        Some(path.to_string())
+    } else {
+        None
+    }
+}
+
+/// Resolve `target` of a link at `path` to a path of the system root,
+/// `None` if it leaves the root.
+fn resolve(path: &str, target: &str) -> Option<String> {
+    let mut parts: Vec<&str> = vec![];
+    if !target.starts_with('/') {
+        parts.extend(path.split('/'));
+        parts.pop();
+    }
+    for c in target.split('/') {
+        match c {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            c => parts.push(c),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Relative link target from the directory of `path` to `target`.
+fn relative_target(path: &str, target: &str) -> String {
+    let from: Vec<&str> = path.split('/').collect();
+    let from = &from[..from.len() - 1];
+    let to: Vec<&str> = target.split('/').collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; from.len() - common];
+    parts.extend(&to[common..]);
+    parts.join("/")
+}
+
+/// `archive::Remap` for the files of a distribution package: keeps what
+/// `rebase` maps and points links at the mapped paths, dropping links
+/// whose target isn't installed, such as `/etc/alternatives/x`.
+pub(crate) fn system_entry(mut entry: Entry) -> Option<Entry> {
+    let path = rebase(&entry.path)?;
+    entry.link = match entry.link.take() {
+        Some(Link::Symlink(target)) => {
+            let Some(mapped) = resolve(&entry.path, &target).as_deref().and_then(rebase) else {
+                trace!("skip link {} -> {target}", entry.path);
+                return None;
+            };
+            Some(Link::Symlink(relative_target(&path, &mapped)))
+        }
+        Some(Link::Hardlink(target)) => Some(Link::Hardlink(rebase(&target)?)),
+        None => None,
+    };
+    entry.path = path;
+    Some(entry)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PackageFmt, control_field, system_entry};
+    use crate::archive::Entry;
+    use crate::types::Link;
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(
+            PackageFmt::Deb.package_name("ripgrep_14.1.0-1_amd64"),
+            "ripgrep"
+        );
+        assert_eq!(
+            PackageFmt::Rpm.package_name("ripgrep-14.1.0-1.x86_64"),
+            "ripgrep"
+        );
+        assert_eq!(
+            PackageFmt::Rpm.package_name("git-lfs-3.5.1-1.el9.aarch64"),
+            "git-lfs"
+        );
+    }
+
+    #[test]
+    fn test_control_field() {
+        let control = "Package: ripgrep\nVersion: 14.1.0-1\nDescription: grep\n more\n";
+        assert_eq!(
+            control_field(control, "Package").as_deref(),
+            Some("ripgrep")
+        );
+        assert_eq!(
+            control_field(control, "Version").as_deref(),
+            Some("14.1.0-1")
+        );
+        assert_eq!(control_field(control, "Depends"), None);
+    }
+
+    #[test]
+    fn test_system_entry() {
+        let entry = |path: &str, link: Option<&str>| Entry {
+            path: path.to_string(),
+            link: link.map(|t| Link::Symlink(t.to_string())),
+            ..Default::default()
+        };
+        let mapped = |path: &str, link: Option<&str>| system_entry(entry(path, link));
+
+        assert_eq!(mapped("usr/bin/rg", None), Some(entry("bin/rg", None)));
+        assert_eq!(mapped("etc/rg.conf", None), None);
+        assert_eq!(mapped("usr/local/bin/rg", None), None);
+        assert_eq!(
+            mapped("usr/bin/code", Some("/usr/share/code/bin/code")),
+            Some(entry("bin/code", Some("../share/code/bin/code")))
+        );
+        assert_eq!(
+            mapped("usr/bin/chrome", Some("../../opt/google/chrome/chrome")),
+            Some(entry("bin/chrome", Some("../opt/google/chrome/chrome")))
+        );
+        assert_eq!(
+            mapped("usr/bin/editor", Some("/etc/alternatives/editor")),
+            None
+        );
+    }
+
+    #[cfg(all(feature = "deb", feature = "tar-gz"))]
+    #[test]
+    fn test_deb_payload() {
+        use super::deb_payload;
+        use crate::archive::list;
+
+        let tar_gz = |files: &[(&str, &[u8])], links: &[(&str, &str)]| {
+            let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+                vec![],
+                flate2::Compression::default(),
+            ));
+            for (path, content) in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o755);
+                header.set_cksum();
+                builder.append_data(&mut header, path, *content).unwrap();
+            }
+            for (path, target) in links {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap()
+        };
+        let control = tar_gz(&[("./control", b"Package: tool\nVersion: 1.2.3-1\n")], &[]);
+        let data = tar_gz(
+            &[("./usr/bin/tool", b"binary"), ("./etc/tool.conf", b"")],
+            &[("./usr/bin/tool-link", "/usr/bin/tool")],
+        );
+        let mut builder = ar::Builder::new(vec![]);
+        for (name, content) in [
+            ("debian-binary", &b"2.0\n"[..]),
+            ("control.tar.gz", &control),
+            ("data.tar.gz", &data),
+        ] {
+            let header = ar::Header::new(name.as_bytes().to_vec(), content.len() as u64);
+            builder.append(&header, content).unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("ei-package-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tool_1.2.3-1_amd64.deb");
+        std::fs::write(&path, builder.into_inner().unwrap()).unwrap();
+
+        let payload = deb_payload(&path, "tool_1.2.3-1_amd64.deb").unwrap();
+        assert_eq!(payload.name.as_deref(), Some("tool"));
+        assert_eq!(payload.version.as_deref(), Some("1.2.3-1"));
+        let entries = list(&payload.path, payload.container, Some(system_entry)).unwrap();
+        let paths: Vec<_> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["bin/tool", "bin/tool-link"]);
+        assert_eq!(entries[1].link, Some(Link::Symlink("tool".to_string())));
+
+        let payload_path = payload.path.clone();
+        drop(payload);
+        assert!(!payload_path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "tar-gz")]
+    #[test]
+    fn test_rpm_payload() {
+        use super::extract_payload;
+        use crate::archive::list;
+        use std::io::Write;
+
+        let mut cpio = vec![];
+        let mut append = |name: &str, ino: u32, mode: u32, nlink: u32, data: &[u8]| {
+            let name = format!("{name}\0");
+            let fields = [ino, mode, 0, 0, nlink, 0, data.len() as u32, 0, 0, 0, 0];
+            cpio.extend(b"070701");
+            for field in fields.into_iter().chain([name.len() as u32, 0]) {
+                cpio.extend(format!("{field:08x}").as_bytes());
+            }
+            cpio.extend(name.as_bytes());
+            cpio.resize(cpio.len().next_multiple_of(4), 0);
+            cpio.extend(data);
+            cpio.resize(cpio.len().next_multiple_of(4), 0);
+        };
+        append("./usr/bin", 1, 0o040755, 2, b"");
+        append("./usr/bin/tool-hard", 2, 0o100755, 2, b"");
+        append("./usr/bin/tool", 2, 0o100755, 2, b"binary");
+        append("./usr/bin/tool-link", 3, 0o120777, 1, b"tool");
+        append("./etc/tool.conf", 4, 0o100644, 1, b"");
+        append("TRAILER!!!", 0, 0, 1, b"");
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(&cpio).unwrap();
+
+        let header = |tags: &[(u32, &str)]| {
+            let mut index = vec![];
+            let mut data = vec![];
+            for (tag, value) in tags {
+                for field in [*tag, 6, data.len() as u32, 1] {
+                    index.extend(field.to_be_bytes());
+                }
+                data.extend(value.as_bytes());
+                data.push(0);
+            }
+            let mut header = vec![0x8e, 0xad, 0xe8, 1, 0, 0, 0, 0];
+            header.extend((tags.len() as u32).to_be_bytes());
+            header.extend((data.len() as u32).to_be_bytes());
+            header.extend(index);
+            header.extend(data);
+            header
+        };
+        let mut rpm = vec![0xed, 0xab, 0xee, 0xdb];
+        rpm.resize(96, 0);
+        let signature = header(&[(1000, "sig")]);
+        rpm.extend(&signature);
+        rpm.resize(rpm.len().next_multiple_of(8), 0);
+        rpm.extend(header(&[
+            (1000, "tool"),
+            (1001, "1.2.3"),
+            (1002, "1.el9"),
+            (1124, "cpio"),
+            (1125, "gzip"),
+        ]));
+        rpm.extend(gz.finish().unwrap());
+
+        let dir = std::env::temp_dir().join(format!("ei-rpm-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tool-1.2.3-1.el9.x86_64.rpm");
+        std::fs::write(&path, rpm).unwrap();
+
+        let payload = extract_payload(&path, PackageFmt::Rpm, "tool.rpm").unwrap();
+        assert_eq!(payload.name.as_deref(), Some("tool"));
+        assert_eq!(payload.version.as_deref(), Some("1.2.3-1.el9"));
+        let entries = list(&payload.path, payload.container, Some(system_entry)).unwrap();
+        let paths: Vec<_> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["bin", "bin/tool", "bin/tool-hard", "bin/tool-link"]);
+        assert_eq!(entries[1].size, 6);
+        assert_eq!(entries[1].mode, Some(0o755));
+        assert_eq!(
+            entries[2].link,
+            Some(Link::Hardlink("bin/tool".to_string()))
+        );
+        assert_eq!(entries[3].link, Some(Link::Symlink("tool".to_string())));
+        drop(payload);
+        assert!(path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
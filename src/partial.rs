@@ -0,0 +1,244 @@
+use crate::cache::PARTIAL_DIR;
+use crate::download::download_with_headers;
+use crate::env::get_cache_dir;
+use crate::file_lock::FileLock;
+use crate::tool::get_filename;
+use crate::verify::sha256_hex;
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use reqwest::header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_RANGE, ETAG, HeaderMap, HeaderValue, IF_RANGE,
+    LAST_MODIFIED, RANGE,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+use tokio::sync::OwnedMutexGuard;
+use tracing::trace;
+
+/// One lock per `.part` file, so concurrent installs of the same URL in
+/// this process don't write to it at the same time. Other processes are
+/// kept out by a file lock next to it.
+static PART_LOCKS: LazyLock<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+/// Validators of the response a `.part` file was started from, stored next
+/// to it. Only written when the server accepts byte ranges.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PartMeta {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+impl PartMeta {
+    /// The `If-Range` value: a strong ETag, else `Last-Modified`. Weak ETags
+    /// can't validate a range.
+    fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|e| !e.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+/// Held while a `.part` file is fetched and finished.
+pub(crate) struct PartLock {
+    _file: FileLock,
+    _task: OwnedMutexGuard<()>,
+}
+
+/// A download streamed to `<cache>/partial/<key>-<filename>.part`.
+pub(crate) struct PartialDownload {
+    url: String,
+    path: PathBuf,
+    meta_path: PathBuf,
+}
+
+/// Filename-safe, bounded version of the last URL segment.
+fn part_name(url: &str) -> String {
+    let filename: String = get_filename(url)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect();
+    format!("{}-{filename}", &sha256_hex(url.as_bytes())[..16])
+}
+
+/// Start offset of a `Content-Range: bytes <start>-<end>/<total>` header.
+fn content_range_start(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = value.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+impl PartialDownload {
+    pub(crate) fn new(url: &str) -> Result<Self> {
+        let dir = get_cache_dir()?.join(PARTIAL_DIR);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let name = part_name(url);
+        Ok(Self {
+            url: url.to_string(),
+            path: dir.join(format!("{name}.part")),
+            meta_path: dir.join(format!("{name}.part.json")),
+        })
+    }
+
+    /// Wait for other downloads of the same URL, in this process and in
+    /// other ei processes.
+    pub(crate) async fn lock(&self) -> Result<PartLock> {
+        let lock = PART_LOCKS
+            .lock()
+            .expect("part locks poisoned")
+            .entry(self.path.clone())
+            .or_default()
+            .clone();
+        let task = lock.lock_owned().await;
+        // Only one task of this process gets here per path, so the file
+        // lock is never taken twice on one thread.
+        let file = FileLock::for_file(&self.path, false)?;
+        Ok(PartLock {
+            _file: file,
+            _task: task,
+        })
+    }
+
+    /// Bytes on disk from an earlier attempt that the server can be asked
+    /// to continue.
+    fn resume_state(&self) -> Option<(u64, PartMeta)> {
+        let content = std::fs::read_to_string(&self.meta_path).ok()?;
+        let meta: PartMeta = serde_json::from_str(&content).ok()?;
+        let len = std::fs::metadata(&self.path).ok()?.len();
+        (meta.url == self.url && len > 0 && meta.validator().is_some()).then_some((len, meta))
+    }
+
+    /// Truncate the `.part` file for a full response, and record its
+    /// validators when the server supports resuming it.
+    fn restart(&self, headers: &HeaderMap) -> Result<File> {
+        let meta = PartMeta {
+            url: self.url.clone(),
+            etag: header(headers, ETAG),
+            last_modified: header(headers, LAST_MODIFIED),
+        };
+        let ranges = header(headers, ACCEPT_RANGES).is_some_and(|v| v.contains("bytes"));
+        if ranges && meta.validator().is_some() {
+            std::fs::write(&self.meta_path, serde_json::to_string(&meta)?)
+                .with_context(|| format!("Failed to write {}", self.meta_path.display()))?;
+        } else {
+            let _ = std::fs::remove_file(&self.meta_path);
+        }
+        File::create(&self.path)
+            .with_context(|| format!("Failed to create {}", self.path.display()))
+    }
+
+    /// One download attempt, continuing the `.part` file where the previous
+    /// attempt stopped when the server still serves the same file.
+    pub(crate) async fn fetch(&self, timeout: u64) -> Result<()> {
+        let state = self.resume_state();
+        let mut headers = HeaderMap::new();
+        // Ranges count encoded bytes; ask for the file as stored.
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+        if let Some((offset, meta)) = &state {
+            headers.insert(RANGE, HeaderValue::from_str(&format!("bytes={offset}-"))?);
+            headers.insert(
+                IF_RANGE,
+                HeaderValue::from_str(meta.validator().unwrap_or_default())?,
+            );
+        }
+
+        let mut response = download_with_headers(&self.url, 0, timeout, &headers).await?;
+        let (mut file, offset) = match (response.status(), state) {
+            (StatusCode::PARTIAL_CONTENT, Some((offset, _)))
+                if content_range_start(response.headers()) == Some(offset) =>
+            {
+                trace!("resume {} at {offset} bytes", self.url);
+                let file = OpenOptions::new()
+                    .append(true)
+                    .open(&self.path)
+                    .with_context(|| format!("Failed to open {}", self.path.display()))?;
+                (file, offset)
+            }
+            (StatusCode::PARTIAL_CONTENT, _) => {
+                self.remove();
+                anyhow::bail!("unexpected partial response for {}", self.url);
+            }
+            // A full response: nothing to resume, or the file changed.
+            _ => (self.restart(response.headers())?, 0),
+        };
+
+        let expected = response.content_length().map(|len| len + offset);
+        let mut written = offset;
+        while let Some(chunk) = response.chunk().await.context("download interrupted")? {
+            file.write_all(&chunk)
+                .with_context(|| format!("Failed to write {}", self.path.display()))?;
+            written += chunk.len() as u64;
+        }
+        file.flush()?;
+        if let Some(expected) = expected
+            && written != expected
+        {
+            anyhow::bail!(
+                "incomplete download of {}: {written} of {expected} bytes",
+                self.url
+            );
+        }
+        Ok(())
+    }
+
+    /// The completed download. It is no longer resumable; the caller moves
+    /// it into the cache.
+    pub(crate) fn finish(&self) -> PathBuf {
+        let _ = std::fs::remove_file(&self.meta_path);
+        self.path.clone()
+    }
+
+    fn remove(&self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_file(&self.meta_path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{content_range_start, part_name};
+    use reqwest::header::{CONTENT_RANGE, HeaderMap, HeaderValue};
+
+    #[test]
+    fn test_content_range_start() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(content_range_start(&headers), None);
+        headers.insert(
+            CONTENT_RANGE,
+            HeaderValue::from_static("bytes 1000-2999/3000"),
+        );
+        assert_eq!(content_range_start(&headers), Some(1000));
+        headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes */3000"));
+        assert_eq!(content_range_start(&headers), None);
+    }
+
+    #[test]
+    fn test_part_name() {
+        let name = part_name("https://example.com/dl/ei x86?.tar.gz");
+        assert!(name.ends_with("-ei_x86_.tar.gz"));
+        assert_eq!(name.len(), 16 + 1 + "ei_x86_.tar.gz".len());
+    }
+}
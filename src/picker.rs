@@ -0,0 +1,135 @@
+use crate::InstallConfig;
+use crate::config::PersistentConfig;
+use crate::file_lock::wait;
+use crate::tool::replace_version;
+use crate::types::Repo;
+use anyhow::{Context, Result};
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::Mutex;
+
+/// Concurrent installs of a batch ask one at a time.
+static PROMPT_LOCK: Mutex<()> = Mutex::new(());
+
+/// An asset filename with the release version replaced by `{version}`, so
+/// a remembered choice still applies to later releases.
+pub(crate) fn variant_key(filename: &str, tag: Option<&str>) -> String {
+    match tag.filter(|t| !t.is_empty()) {
+        Some(tag) => replace_version(filename, tag, "{version}"),
+        None => filename.to_string(),
+    }
+}
+
+/// Choose between assets of the same tool that tie for the best match, e.g.
+/// `llrt-linux-x64-full-sdk.zip` and `llrt-linux-x64-no-sdk.zip`. `variants`
+/// holds one of `filenames` per variant, leaving out other formats of it.
+///
+/// A remembered preset decides first. Otherwise the user picks from a
+/// numbered list when stdin is a terminal; without one this fails with the
+/// candidates rather than guessing.
+pub(crate) fn pick_variant(
+    name: &str,
+    filenames: &[&str],
+    variants: &[&str],
+    url: &str,
+    tag: Option<&str>,
+    config: &InstallConfig,
+) -> Result<String> {
+    if let Some(preset) = &config.asset
+        && let Some(f) = filenames.iter().find(|f| variant_key(f, tag) == *preset)
+    {
+        return Ok(f.to_string());
+    }
+
+    let list = variants
+        .iter()
+        .map(|f| format!("  {f}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "Several assets match `{name}`:\n{list}\nPick one with --regex, or run ei in a terminal to choose"
+        );
+    }
+
+    // Reading stdin blocks; batch installs run on the async workers.
+    wait(|| ask(name, variants, url, tag, config))
+}
+
+fn ask(
+    name: &str,
+    variants: &[&str],
+    url: &str,
+    tag: Option<&str>,
+    config: &InstallConfig,
+) -> Result<String> {
+    let _guard = PROMPT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    eprintln!("Several assets match `{name}`:");
+    for (i, f) in variants.iter().enumerate() {
+        eprintln!("  {}) {f}", i + 1);
+    }
+    let picked = loop {
+        let answer = prompt(&format!("Pick one [1-{}]: ", variants.len()))?;
+        match answer.parse::<usize>() {
+            Ok(i) if (1..=variants.len()).contains(&i) => break variants[i - 1],
+            _ => eprintln!("Enter a number between 1 and {}", variants.len()),
+        }
+    };
+
+    // Presets are per repository, so only GitHub release assets qualify.
+    if !config.dry_run
+        && url.starts_with("https://github.com/")
+        && let Ok(repo) = Repo::try_from(url)
+    {
+        let source = format!("{}/{}", repo.owner, repo.name);
+        let answer =
+            prompt(&format!("Remember this choice for {source}? [y/N]: ")).unwrap_or_default();
+        if answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes") {
+            let _lock = PersistentConfig::lock(config.quiet)?;
+            let mut presets = PersistentConfig::load();
+            presets.set_package_asset(&source, variant_key(picked, tag));
+            presets.save_quiet(config.quiet)?;
+        }
+    }
+    Ok(picked.to_string())
+}
+
+/// Ask on stderr and read one trimmed line from stdin.
+fn prompt(question: &str) -> Result<String> {
+    eprint!("{question}");
+    std::io::stderr().flush().ok();
+    let mut line = String::new();
+    let n = std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("Failed to read the answer")?;
+    if n == 0 {
+        anyhow::bail!("No asset picked");
+    }
+    Ok(line.trim().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::variant_key;
+
+    #[test]
+    fn test_variant_key() {
+        assert_eq!(
+            variant_key("tool-v1.2.0-x86_64-linux.tar.gz", Some("v1.2.0")),
+            "tool-{version}-x86_64-linux.tar.gz"
+        );
+        assert_eq!(
+            variant_key("tool-1.2.0-x86_64-linux.zip", Some("v1.2.0")),
+            "tool-{version}-x86_64-linux.zip"
+        );
+        assert_eq!(
+            variant_key("llrt-linux-x64-full-sdk.zip", Some("v0.5.1-beta")),
+            "llrt-linux-x64-full-sdk.zip"
+        );
+        assert_eq!(
+            variant_key("tool-2-x86_64-linux-musl2.tar.gz", Some("v2")),
+            "tool-{version}-x86_64-linux-musl2.tar.gz"
+        );
+        assert_eq!(variant_key("tool.zip", None), "tool.zip");
+    }
+}
@@ -0,0 +1,498 @@
+use crate::InstallConfig;
+use crate::ci::{CiRun, RE_CI_WORKFLOW};
+use crate::env::get_install_dir;
+use crate::file_lock::FileLock;
+use crate::tool::{get_filename, is_dist_manfiest, name_no_ext, print_table};
+use crate::types::{Output, Repo};
+use anyhow::{Context, Result};
+use easy_archive::{human_size, mode_to_string};
+use guess_target::{Target, guess_target};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_RECEIPTS_NAME: &str = "ei_receipts.json";
+
+/// Every installation ei has performed, keyed by package name.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub(crate) struct Receipts {
+    #[serde(default)]
+    pub(crate) packages: BTreeMap<String, Receipt>,
+}
+
+/// What a single `ei <url>` put on the machine.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub(crate) struct Receipt {
+    /// Source as given on the command line.
+    pub(crate) source: String,
+    /// `owner/repo` when the source is a GitHub repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) repo: Option<String>,
+    /// Resolved release tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tag: Option<String>,
+    /// Version from the metadata of a distribution package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) version: Option<String>,
+    /// Target triple the assets were selected for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) target: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub(crate) installed_at: u64,
+    #[serde(default)]
+    pub(crate) assets: Vec<ReceiptAsset>,
+    /// Directories the installation needs on PATH.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) path: Vec<PathEntry>,
+    /// Options the package was installed with, reused by `ei update`.
+    #[serde(default)]
+    pub(crate) options: InstallOptions,
+    /// The `ei.toml` that lists this package, when `ei sync` installed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) toolfile: Option<String>,
+}
+
+/// The asset-selection options of an installation.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub(crate) struct InstallOptions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) name: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) alias: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) target: Option<Target>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) dir: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) fuzzy: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) desktop: bool,
+}
+
+impl From<&InstallConfig> for InstallOptions {
+    fn from(config: &InstallConfig) -> Self {
+        InstallOptions {
+            name: config.name.clone(),
+            alias: config.alias.clone(),
+            regex: config.regex.clone(),
+            target: config.target,
+            dir: config.dir.clone(),
+            fuzzy: config.fuzzy,
+            desktop: config.desktop,
+        }
+    }
+}
+
+impl InstallOptions {
+    /// Overlay these options on `config`, keeping its network and output settings.
+    pub(crate) fn apply(&self, config: InstallConfig) -> InstallConfig {
+        InstallConfig {
+            name: self.name.clone(),
+            alias: self.alias.clone(),
+            regex: self.regex.clone(),
+            target: self.target,
+            dir: self.dir.clone(),
+            fuzzy: self.fuzzy,
+            desktop: self.desktop,
+            ..config
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub(crate) struct PathEntry {
+    pub(crate) dir: String,
+    /// Whether ei added `dir` to PATH (as opposed to finding it there), and
+    /// is therefore responsible for removing it again.
+    #[serde(default)]
+    pub(crate) added: bool,
+    /// Shell whose startup file ei added `dir` to, as reported by crud-path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) shell: Option<String>,
+}
+
+/// One downloaded asset and the files it produced.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub(crate) struct ReceiptAsset {
+    pub(crate) name: String,
+    pub(crate) url: String,
+    pub(crate) install_dir: String,
+    #[serde(default)]
+    pub(crate) files: Vec<ReceiptFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub(crate) struct ReceiptFile {
+    pub(crate) path: String,
+    pub(crate) size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) mode: Option<u32>,
+    /// Modification time in nanoseconds since the Unix epoch, used to detect
+    /// files changed after installation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) modified: Option<u64>,
+}
+
+impl ReceiptFile {
+    fn from_disk(path: &str, size: u64, mode: Option<u32>) -> Self {
+        let meta = std::fs::metadata(path).ok();
+        ReceiptFile {
+            path: path.to_string(),
+            size: meta.as_ref().map_or(size, |m| m.len()),
+            mode,
+            modified: meta.and_then(|m| file_modified(&m)),
+        }
+    }
+
+    /// Whether the file on disk differs from what was installed.
+    pub(crate) fn is_modified(&self) -> bool {
+        let Ok(meta) = std::fs::metadata(&self.path) else {
+            return false;
+        };
+        meta.len() != self.size
+            || (self.modified.is_some() && file_modified(&meta) != self.modified)
+    }
+}
+
+fn file_modified(meta: &std::fs::Metadata) -> Option<u64> {
+    let d = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(d.as_nanos()).ok()
+}
+
+fn get_receipts_path() -> Result<PathBuf> {
+    Ok(get_install_dir()?.join(DEFAULT_RECEIPTS_NAME))
+}
+
+impl Receipts {
+    /// Held across load and save so concurrent ei runs don't drop each
+    /// other's receipts.
+    pub(crate) fn lock(quiet: bool) -> Result<FileLock> {
+        FileLock::for_file(&get_receipts_path()?, quiet)
+    }
+
+    pub(crate) fn load() -> Result<Self> {
+        Self::load_from(&get_receipts_path()?)
+    }
+
+    fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub(crate) fn save(&self) -> Result<()> {
+        self.save_to(&get_receipts_path()?)
+    }
+
+    /// Replace the file whole, so a crash mid-write can't leave it truncated.
+    fn save_to(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize receipts")?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, content)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Record an installation, replacing any earlier receipt of the same package.
+    pub(crate) fn record(
+        url: &str,
+        config: &InstallConfig,
+        output: &Output,
+        path: Vec<PathEntry>,
+    ) -> Result<()> {
+        let mut receipts = Self::load()?;
+        receipts.insert(url, config, output, path);
+        receipts.save()
+    }
+
+    fn insert(&mut self, url: &str, config: &InstallConfig, output: &Output, path: Vec<PathEntry>) {
+        let mut receipt = Receipt::new(url, config, output);
+        receipt.path = path;
+        // Keep responsibility for PATH entries ei added on an earlier run.
+        let name = package_name(url, config);
+        if let Some(old) = self.packages.get(&name) {
+            for entry in receipt.path.iter_mut().filter(|e| !e.added) {
+                if let Some(o) = old.path.iter().find(|o| o.dir == entry.dir && o.added) {
+                    entry.added = true;
+                    entry.shell = o.shell.clone();
+                }
+            }
+            receipt.toolfile = old.toolfile.clone();
+        }
+        self.packages.insert(name, receipt);
+    }
+
+    /// Find a package by its receipt key, or by repo/source when that is
+    /// unambiguous.
+    pub(crate) fn find(&self, name: &str) -> Option<String> {
+        if self.packages.contains_key(name) {
+            return Some(name.to_string());
+        }
+        let mut iter = self
+            .packages
+            .iter()
+            .filter(|(_, r)| r.repo.as_deref() == Some(name) || r.source == name);
+        match (iter.next(), iter.next()) {
+            (Some((key, _)), None) => Some(key.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Key under which an installation is recorded: the alias when one was
+/// given, `owner/repo` for GitHub sources, otherwise the source as typed.
+pub(crate) fn package_name(url: &str, config: &InstallConfig) -> String {
+    if let Some(alias) = &config.alias {
+        return alias.clone();
+    }
+    match source_repo(url) {
+        Some(repo) => format!("{}/{}", repo.owner, repo.name),
+        None => url.to_string(),
+    }
+}
+
+/// The GitHub repository behind `url`, following the same precedence as
+/// `install::install` so manifests, CI runs and local files are not
+/// mistaken for `owner/repo`.
+fn source_repo(url: &str) -> Option<Repo> {
+    if is_dist_manfiest(url)
+        || CiRun::try_from(url).is_ok()
+        || RE_CI_WORKFLOW.is_match(url)
+        || std::fs::exists(url).unwrap_or(false)
+    {
+        return None;
+    }
+    Repo::try_from(url).ok()
+}
+
+pub(crate) fn asset_target(url: &str, config: &InstallConfig) -> Option<String> {
+    let local = config.get_local_target();
+    let stem = name_no_ext(&get_filename(url));
+    guess_target(&stem)
+        .into_iter()
+        .map(|g| g.target)
+        .find(|t| local.contains(t))
+        .or_else(|| local.first().copied())
+        .map(|t| t.to_str().to_string())
+}
+
+impl Receipt {
+    pub(crate) fn new(url: &str, config: &InstallConfig, output: &Output) -> Self {
+        let mut assets: Vec<ReceiptAsset> = output
+            .iter()
+            .map(|(asset_url, item)| ReceiptAsset {
+                name: get_filename(asset_url),
+                url: asset_url.clone(),
+                install_dir: item.install_dir.clone(),
+                files: item
+                    .files
+                    .iter()
+                    .filter(|f| !f.is_dir)
+                    // strip/upx rewrite the file after install, so
+                    // record what is actually on disk.
+                    .map(|f| ReceiptFile::from_disk(&f.install_path, f.size, f.mode))
+                    .collect(),
+            })
+            .collect();
+        assets.sort_by(|a, b| a.url.cmp(&b.url));
+
+        let target = assets.first().and_then(|a| asset_target(&a.url, config));
+        let installed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Receipt {
+            source: url.to_string(),
+            repo: source_repo(url).map(|r| format!("{}/{}", r.owner, r.name)),
+            tag: output.values().find_map(|i| i.tag.clone()),
+            version: output.values().find_map(|i| i.version.clone()),
+            target,
+            installed_at,
+            assets,
+            path: vec![],
+            options: config.into(),
+            toolfile: None,
+        }
+    }
+
+    pub(crate) fn files(&self) -> impl Iterator<Item = &ReceiptFile> {
+        self.assets.iter().flat_map(|a| a.files.iter())
+    }
+
+    fn size(&self) -> u64 {
+        self.files().map(|f| f.size).sum()
+    }
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub(crate) fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Print installed packages as a table, or one package in detail.
+pub(crate) fn display_receipts(receipts: &Receipts, name: Option<&str>) -> Result<()> {
+    if let Some(name) = name {
+        let key = receipts
+            .find(name)
+            .with_context(|| format!("{name} is not installed"))?;
+        display_receipt(&key, &receipts.packages[&key]);
+        return Ok(());
+    }
+
+    if receipts.packages.is_empty() {
+        println!("No packages installed");
+        return Ok(());
+    }
+
+    let rows: Vec<[String; 5]> = receipts
+        .packages
+        .iter()
+        .map(|(name, r)| {
+            [
+                name.clone(),
+                r.tag
+                    .clone()
+                    .or_else(|| r.version.clone())
+                    .unwrap_or_else(|| "-".to_string()),
+                r.target.clone().unwrap_or_else(|| "-".to_string()),
+                format!("{} ({})", human_size(r.size() as usize), r.files().count()),
+                format_timestamp(r.installed_at),
+            ]
+        })
+        .collect();
+    print_table(["NAME", "TAG", "TARGET", "SIZE", "INSTALLED"], &rows);
+    Ok(())
+}
+
+fn display_receipt(name: &str, receipt: &Receipt) {
+    println!("{name}");
+    println!("  source:    {}", receipt.source);
+    if let Some(repo) = &receipt.repo {
+        println!("  repo:      {repo}");
+    }
+    if let Some(tag) = &receipt.tag {
+        println!("  tag:       {tag}");
+    }
+    if let Some(version) = &receipt.version {
+        println!("  version:   {version}");
+    }
+    if let Some(target) = &receipt.target {
+        println!("  target:    {target}");
+    }
+    println!("  installed: {}", format_timestamp(receipt.installed_at));
+    for entry in &receipt.path {
+        println!("  path:      {}", entry.dir);
+    }
+    for asset in &receipt.assets {
+        println!("  asset:     {}", asset.name);
+        println!("    url:     {}", asset.url);
+        println!("    dir:     {}", asset.install_dir);
+        for f in &asset.files {
+            println!(
+                "    {} {:>10} {}",
+                mode_to_string(f.mode.unwrap_or(0), false),
+                human_size(f.size as usize),
+                f.path
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PathEntry, Receipts, format_timestamp};
+    use crate::InstallConfig;
+    use crate::types::{OutputFile, OutputItem};
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(1700000000), "2023-11-14 22:13:20");
+    }
+
+    #[test]
+    fn test_receipts_round_trip() {
+        let dir = std::env::temp_dir().join(format!("ei-receipt-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("rg");
+        std::fs::write(&file, b"binary").unwrap();
+        let file = file.to_string_lossy().to_string();
+
+        let url = "https://github.com/BurntSushi/ripgrep/releases/download/14.1.0/ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz";
+        let mut output = crate::types::Output::new();
+        output.insert(
+            url.to_string(),
+            OutputItem {
+                install_dir: dir.to_string_lossy().to_string(),
+                files: vec![OutputFile {
+                    install_path: file.clone(),
+                    size: 6,
+                    mode: Some(0o755),
+                    ..Default::default()
+                }],
+                tag: Some("14.1.0".to_string()),
+                ..Default::default()
+            },
+        );
+        let config = InstallConfig {
+            alias: Some("rg".to_string()),
+            ..Default::default()
+        };
+        let path = vec![PathEntry {
+            dir: dir.to_string_lossy().to_string(),
+            added: true,
+            shell: Some("bash".to_string()),
+        }];
+
+        let mut receipts = Receipts::default();
+        receipts.insert("BurntSushi/ripgrep", &config, &output, path);
+        let json = dir.join("ei_receipts.json");
+        receipts.save_to(&json).unwrap();
+        assert!(!json.with_extension("json.tmp").exists());
+
+        let loaded = Receipts::load_from(&json).unwrap();
+        let receipt = &loaded.packages["rg"];
+        assert_eq!(receipt.source, "BurntSushi/ripgrep");
+        assert_eq!(receipt.repo.as_deref(), Some("BurntSushi/ripgrep"));
+        assert_eq!(receipt.tag.as_deref(), Some("14.1.0"));
+        assert_eq!(receipt.options.alias.as_deref(), Some("rg"));
+        assert!(receipt.path[0].added);
+        assert_eq!(
+            receipt.files().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+            [file]
+        );
+        assert!(!receipt.files().any(|f| f.is_modified()));
+
+        assert_eq!(loaded.find("rg").as_deref(), Some("rg"));
+        assert_eq!(loaded.find("BurntSushi/ripgrep").as_deref(), Some("rg"));
+        assert_eq!(loaded.find("ripgrep"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
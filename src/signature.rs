@@ -0,0 +1,361 @@
+use crate::InstallConfig;
+use crate::artifact::{ArtifactUrl, GhArtifact};
+use crate::download::get_bytes;
+use crate::manfiest::{Artifact, ArtifactId};
+use crate::tool::get_filename;
+use anyhow::{Context, Result};
+use easy_archive::human_size;
+use sha2::{Digest, Sha256, Sha512};
+use signature::Verifier;
+use ssh_key::{HashAlg, SshSig};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+/// Namespace used by `ssh-keygen -Y sign -n file`, the convention for
+/// signing release files.
+const SSH_NAMESPACE: &str = "file";
+
+/// Suffixes of detached signatures looked up next to an asset, preferred
+/// first.
+const SIGNATURE_SUFFIXES: [&str; 2] = [".minisig", ".sig"];
+
+/// A trusted public key from `--pubkey` or a package preset.
+pub(crate) enum PublicKey {
+    Minisign(minisign_verify::PublicKey),
+    Ssh(ssh_key::PublicKey),
+}
+
+impl PublicKey {
+    /// Parse an OpenSSH public key line, or a minisign public key given
+    /// either bare (`RWQ...`) or as the contents of a `minisign.pub` file.
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.starts_with("ssh-") || s.starts_with("ecdsa-") || s.starts_with("sk-") {
+            return ssh_key::PublicKey::from_openssh(s)
+                .map(Self::Ssh)
+                .context("invalid SSH public key");
+        }
+        let key = if s.contains('\n') {
+            minisign_verify::PublicKey::decode(s)
+        } else {
+            minisign_verify::PublicKey::from_base64(s)
+        };
+        key.map(Self::Minisign)
+            .map_err(|e| anyhow::anyhow!("invalid minisign public key: {e}"))
+    }
+
+    /// Parse a key given inline or as a path to a key file.
+    pub(crate) fn load(s: &str) -> Result<Self> {
+        if Path::new(s).is_file() {
+            let content =
+                std::fs::read_to_string(s).with_context(|| format!("failed to read key {s}"))?;
+            return Self::parse(&content);
+        }
+        Self::parse(s)
+    }
+}
+
+/// `--pubkey` value parser: checks the key up front so typos fail before
+/// anything is downloaded.
+pub(crate) fn parse_pubkey(s: &str) -> Result<String, String> {
+    PublicKey::load(s).map_err(|e| format!("{e:#}"))?;
+    Ok(s.to_string())
+}
+
+/// Find the detached signature of `filename` among `items`; `is_named`
+/// tells whether an item has the given file name.
+fn find_signature<'a, T: ?Sized>(
+    items: impl Iterator<Item = &'a T> + Clone,
+    is_named: impl Fn(&T, &str) -> bool,
+    filename: &str,
+) -> Option<&'a T> {
+    SIGNATURE_SUFFIXES.iter().find_map(|suffix| {
+        let sig = format!("{filename}{suffix}");
+        items.clone().find(|i| is_named(i, &sig))
+    })
+}
+
+/// Find the detached signature published for `filename`.
+pub(crate) fn find_signature_asset<'a>(
+    assets: &'a HashSet<GhArtifact>,
+    filename: &str,
+) -> Option<&'a GhArtifact> {
+    find_signature(
+        assets.iter(),
+        |a, sig| a.name.eq_ignore_ascii_case(sig),
+        filename,
+    )
+}
+
+/// Find the detached signature a dist manifest lists for artifact `key`.
+pub(crate) fn find_manifest_signature<'a>(
+    artifacts: &'a BTreeMap<ArtifactId, Artifact>,
+    key: &str,
+) -> Option<&'a str> {
+    find_signature(
+        artifacts.keys(),
+        |k, sig| get_filename(k).eq_ignore_ascii_case(sig),
+        &get_filename(key),
+    )
+    .map(String::as_str)
+}
+
+/// Legacy (non-prehashed) minisign signatures sign the raw message, which
+/// has to be held in memory; larger files need a prehashed signature.
+const LEGACY_MINISIGN_MAX: u64 = 64 << 20;
+
+/// Read `message` in chunks, passing each to `f`.
+fn read_chunks(mut message: impl Read, mut f: impl FnMut(&[u8])) -> Result<()> {
+    let mut buf = vec![0; 64 << 10];
+    loop {
+        let n = message
+            .read(&mut buf)
+            .context("Failed to read signed file")?;
+        if n == 0 {
+            return Ok(());
+        }
+        f(&buf[..n]);
+    }
+}
+
+/// The blob an SSH signature signs: the `SSHSIG` preamble, namespace,
+/// reserved field, hash algorithm and the message digest, each but the
+/// preamble as an SSH string.
+fn ssh_signed_data(sig: &SshSig, digest: &[u8]) -> Vec<u8> {
+    let mut data = b"SSHSIG".to_vec();
+    for field in [
+        sig.namespace().as_bytes(),
+        sig.reserved(),
+        sig.hash_alg().as_str().as_bytes(),
+        digest,
+    ] {
+        data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        data.extend_from_slice(field);
+    }
+    data
+}
+
+/// Check a detached minisign or SSH signature over everything `message`
+/// yields against the trusted keys, reading it in chunks. Returns the kind
+/// of signature that matched.
+pub(crate) fn verify_signature(
+    mut message: impl Read,
+    signature: &str,
+    keys: &[PublicKey],
+) -> Result<&'static str> {
+    if signature.contains("-----BEGIN SSH SIGNATURE-----") {
+        let sig = SshSig::from_pem(signature.trim()).context("invalid SSH signature")?;
+        if !keys.iter().any(|k| matches!(k, PublicKey::Ssh(_))) {
+            anyhow::bail!("SSH signature found, but no SSH public key is trusted");
+        }
+        if sig.namespace() != SSH_NAMESPACE {
+            anyhow::bail!(
+                "SSH signature is for namespace {}, expected {SSH_NAMESPACE}",
+                sig.namespace()
+            );
+        }
+        let digest = match sig.hash_alg() {
+            HashAlg::Sha256 => {
+                let mut hasher = Sha256::new();
+                read_chunks(message, |b| hasher.update(b))?;
+                hasher.finalize().to_vec()
+            }
+            HashAlg::Sha512 => {
+                let mut hasher = Sha512::new();
+                read_chunks(message, |b| hasher.update(b))?;
+                hasher.finalize().to_vec()
+            }
+            alg => anyhow::bail!("unsupported SSH signature hash {alg}"),
+        };
+        let signed_data = ssh_signed_data(&sig, &digest);
+        let key = keys.iter().find_map(|k| match k {
+            PublicKey::Ssh(k) if k.key_data() == sig.public_key() => Some(k),
+            _ => None,
+        });
+        let Some(key) = key else {
+            anyhow::bail!("SSH signature does not match any trusted key");
+        };
+        return match Verifier::verify(key, &signed_data, sig.signature()) {
+            Ok(()) => Ok("ssh"),
+            Err(e) => anyhow::bail!("SSH signature does not match any trusted key: {e}"),
+        };
+    }
+
+    if signature.starts_with("untrusted comment:") {
+        let sig = minisign_verify::Signature::decode(signature)
+            .map_err(|e| anyhow::anyhow!("invalid minisign signature: {e}"))?;
+        let mut last_err = None;
+        for key in keys {
+            let PublicKey::Minisign(key) = key else {
+                continue;
+            };
+            match key.verify_stream(&sig) {
+                Ok(mut verifier) => {
+                    read_chunks(&mut message, |b| verifier.update(b))?;
+                    match verifier.finalize() {
+                        Ok(()) => return Ok("minisign"),
+                        Err(e) => last_err = Some(e),
+                    }
+                    break;
+                }
+                Err(minisign_verify::Error::UnsupportedLegacyMode) => {
+                    let mut bytes = vec![];
+                    (&mut message)
+                        .take(LEGACY_MINISIGN_MAX + 1)
+                        .read_to_end(&mut bytes)
+                        .context("Failed to read signed file")?;
+                    if bytes.len() as u64 > LEGACY_MINISIGN_MAX {
+                        anyhow::bail!(
+                            "legacy minisign signatures are only checked for files up to {}; sign with a prehashed (minisign -H) signature",
+                            human_size(LEGACY_MINISIGN_MAX as usize)
+                        );
+                    }
+                    match key.verify(&bytes, &sig, true) {
+                        Ok(()) => return Ok("minisign"),
+                        Err(e) => last_err = Some(e),
+                    }
+                    break;
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        match last_err {
+            Some(e) => anyhow::bail!("minisign signature does not match any trusted key: {e}"),
+            None => {
+                anyhow::bail!("minisign signature found, but no minisign public key is trusted")
+            }
+        }
+    }
+
+    if signature.contains("-----BEGIN PGP SIGNATURE-----") {
+        anyhow::bail!("GPG signatures are not supported");
+    }
+    anyhow::bail!("unrecognized signature format")
+}
+
+/// When public keys are trusted for this install, require the artifact's
+/// signature asset and verify it.
+pub(crate) async fn verify_artifact_signature(
+    path: &Path,
+    art: &ArtifactUrl,
+    config: &InstallConfig,
+) -> Result<()> {
+    if config.pubkeys.is_empty() {
+        return Ok(());
+    }
+    let filename = get_filename(&art.url);
+    if config.no_verify {
+        if !config.quiet {
+            eprintln!(
+                "Warning: --no-verify skips the signature check of {filename} despite a trusted public key"
+            );
+        }
+        return Ok(());
+    }
+    let Some(signature_url) = &art.signature_url else {
+        anyhow::bail!(
+            "No signature ({}) published for {filename}, but a public key is configured",
+            SIGNATURE_SUFFIXES.join(", ")
+        );
+    };
+
+    let keys = config
+        .pubkeys
+        .iter()
+        .map(|k| PublicKey::load(k))
+        .collect::<Result<Vec<_>>>()?;
+    let signature = get_bytes(signature_url, config)
+        .await
+        .with_context(|| format!("Failed to download signature {signature_url}"))?;
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let kind =
+        verify_signature(file, &String::from_utf8_lossy(&signature), &keys).with_context(|| {
+            format!("Signature verification failed for {filename} ({signature_url})")
+        })?;
+    if !config.quiet {
+        println!("{kind} signature verified {filename}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PublicKey, find_manifest_signature, verify_signature};
+    use crate::manfiest::DistManifest;
+
+    const SSH_PUBKEY: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIH5v3KiVNoswSziGYRAk1gIDTloiCQhcj5OxboBNkwvu ei-test";
+    const SSH_SIG: &str = "-----BEGIN SSH SIGNATURE-----
+U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgfm/cqJU2izBLOIZhECTWAgNOWi
+IJCFyPk7FugE2TC+4AAAAEZmlsZQAAAAAAAAAGc2hhNTEyAAAAUwAAAAtzc2gtZWQyNTUx
+OQAAAEAsh81kPZuVx4Voxla9+7xpP+HTvNySz26Jarx7vhqO4fqFV/w87I2035gqy82BgM
+0Bt/+p4H6KdGD2Hu3TAPQP
+-----END SSH SIGNATURE-----
+";
+    const MINISIGN_PUBKEY: &str = "RWQBAgMEBQYHCOhumb6wyeaUvsNOkL5K/B3yd7ywtKX7v7uCzrdf+xV0";
+    const MINISIGN_SIG: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCK0GbJoOmktLJoUIM4UkkPO9RhO98iphgyRwJqJg64oVkp6Vrz3E7Crcic8PwVgP/IDCRoSt2C/KxgLFM5na4A8=
+trusted comment: ei test
+SpljKNe525eZ0ZNpLxtCmE7zQf24BWh5RLuAo+JW7sj+600QoUbEde0EneVJv09W8MzAYmFexPOCjDS3kT63Dw==
+";
+
+    #[test]
+    fn test_verify_signature() {
+        let keys = vec![
+            PublicKey::parse(SSH_PUBKEY).unwrap(),
+            PublicKey::parse(MINISIGN_PUBKEY).unwrap(),
+        ];
+        assert_eq!(
+            verify_signature(&b"hello"[..], SSH_SIG, &keys).unwrap(),
+            "ssh"
+        );
+        assert_eq!(
+            verify_signature(&b"hello"[..], MINISIGN_SIG, &keys).unwrap(),
+            "minisign"
+        );
+        assert!(verify_signature(&b"hello!"[..], SSH_SIG, &keys).is_err());
+        assert!(verify_signature(&b"hello!"[..], MINISIGN_SIG, &keys).is_err());
+
+        // A valid signature from a key that isn't trusted.
+        let ssh_only = vec![PublicKey::parse(SSH_PUBKEY).unwrap()];
+        assert!(verify_signature(&b"hello"[..], MINISIGN_SIG, &ssh_only).is_err());
+        assert!(verify_signature(&b"hello"[..], "-----BEGIN PGP SIGNATURE-----", &keys).is_err());
+    }
+
+    #[test]
+    fn test_find_manifest_signature() {
+        let manifest: DistManifest = serde_json::from_str(
+            r#"{"artifacts": {
+                "tool-x86_64-unknown-linux-gnu.tar.gz": {},
+                "tool-x86_64-unknown-linux-gnu.tar.gz.sig": {},
+                "tool-x86_64-unknown-linux-gnu.tar.gz.minisig": {},
+                "tool-aarch64-apple-darwin.tar.gz": {}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            find_manifest_signature(&manifest.artifacts, "tool-x86_64-unknown-linux-gnu.tar.gz"),
+            Some("tool-x86_64-unknown-linux-gnu.tar.gz.minisig")
+        );
+        assert_eq!(
+            find_manifest_signature(&manifest.artifacts, "tool-aarch64-apple-darwin.tar.gz"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_pubkey() {
+        assert!(matches!(
+            PublicKey::parse(SSH_PUBKEY),
+            Ok(PublicKey::Ssh(_))
+        ));
+        let minisign_pub = format!("untrusted comment: minisign public key\n{MINISIGN_PUBKEY}\n");
+        assert!(matches!(
+            PublicKey::parse(&minisign_pub),
+            Ok(PublicKey::Minisign(_))
+        ));
+        assert!(PublicKey::parse("not a key").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::types::OutputFile;
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::trace;
+
+const STAGING_PREFIX: &str = ".ei-staging-";
+const NEW_DIR: &str = "new";
+const BACKUP_DIR: &str = "backup";
+
+static STAGING_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory inside the install directory where a new version is written
+/// before it replaces the old one, so the two are never mixed on disk. Being
+/// on the same filesystem, every file is swapped in with a rename.
+pub(crate) struct Staging {
+    install_dir: PathBuf,
+    dir: PathBuf,
+    created_install_dir: bool,
+    /// Set when a rollback couldn't put everything back, so the files
+    /// moved aside survive in the staging directory.
+    keep: bool,
+}
+
+impl Staging {
+    pub(crate) fn new(install_dir: &Path) -> Result<Self> {
+        let created_install_dir = !install_dir.exists();
+        let dir = install_dir.join(format!(
+            "{STAGING_PREFIX}{}-{}",
+            std::process::id(),
+            STAGING_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(dir.join(NEW_DIR))
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self {
+            install_dir: install_dir.to_path_buf(),
+            dir,
+            created_install_dir,
+            keep: false,
+        })
+    }
+
+    fn relative<'a>(&self, install_path: &'a str) -> Result<&'a Path> {
+        let relative = Path::new(install_path).strip_prefix(&self.install_dir).ok();
+        match relative {
+            Some(relative)
+                if relative
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_))) =>
+            {
+                Ok(relative)
+            }
+            _ => anyhow::bail!(
+                "{install_path} is outside the install directory {}",
+                self.install_dir.display()
+            ),
+        }
+    }
+
+    /// Where `install_path` is written while staged.
+    pub(crate) fn staged_path(&self, install_path: &str) -> Result<String> {
+        let path = self.dir.join(NEW_DIR).join(self.relative(install_path)?);
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    /// Move every staged file to its install path, and the `stale` files of
+    /// the version being replaced, which the new one doesn't ship, out of
+    /// the install directory. Files being replaced or removed are moved
+    /// aside first and put back if anything fails, leaving the previous
+    /// version as it was. Should putting one back fail too, the staging
+    /// directory is kept and named in the error.
+    pub(crate) fn commit(mut self, files: &[OutputFile], stale: &[PathBuf]) -> Result<()> {
+        for file in files {
+            if std::fs::symlink_metadata(&file.install_path).is_ok_and(|m| m.is_dir()) {
+                anyhow::bail!(
+                    "target path is a directory, refusing to overwrite: {}",
+                    file.install_path
+                );
+            }
+        }
+
+        let mut swapped: Vec<(PathBuf, Option<PathBuf>)> = vec![];
+        // Stale files go first: a new file may sit where one was a directory.
+        let res = stale
+            .iter()
+            .try_for_each(|path| {
+                let backup = self.move_aside(path)?;
+                swapped.push((path.clone(), Some(backup)));
+                Ok(())
+            })
+            .and_then(|()| {
+                files
+                    .iter()
+                    .try_for_each(|file| self.swap(file, &mut swapped))
+            });
+        if let Err(e) = res {
+            let mut restored = true;
+            for (target, backup) in swapped.iter().rev() {
+                let _ = std::fs::remove_file(target);
+                if let Some(backup) = backup
+                    && let Err(e) = std::fs::rename(backup, target)
+                {
+                    eprintln!("Failed to restore {}: {e}", target.display());
+                    restored = false;
+                }
+            }
+            if !restored {
+                self.keep = true;
+                return Err(e.context(format!(
+                    "Installation rolled back, but some previous files could not be put back; they are kept in {}",
+                    self.dir.join(BACKUP_DIR).display()
+                )));
+            }
+            return Err(e.context("Installation rolled back"));
+        }
+        Ok(())
+    }
+
+    /// Move the installed `target` into the backup directory, returning
+    /// where it went.
+    fn move_aside(&self, target: &Path) -> Result<PathBuf> {
+        let install_path = target.to_string_lossy();
+        let relative = self.relative(&install_path)?;
+        let backup = self.dir.join(BACKUP_DIR).join(relative);
+        if let Some(parent) = backup.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(target, &backup)
+            .with_context(|| format!("Failed to move aside {}", target.display()))?;
+        Ok(backup)
+    }
+
+    fn swap(&self, file: &OutputFile, swapped: &mut Vec<(PathBuf, Option<PathBuf>)>) -> Result<()> {
+        let target = PathBuf::from(&file.install_path);
+        let relative = self.relative(&file.install_path)?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let backup = if target.exists() || target.is_symlink() {
+            Some(self.move_aside(&target)?)
+        } else {
+            None
+        };
+        swapped.push((target.clone(), backup));
+        std::fs::rename(self.dir.join(NEW_DIR).join(relative), &target)
+            .with_context(|| format!("Failed to install {}", target.display()))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            trace!("failed to remove {}: {e}", self.dir.display());
+        }
+        if self.created_install_dir {
+            // Only succeeds when nothing was installed into it.
+            let _ = std::fs::remove_dir(&self.install_dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Staging;
+    use crate::types::OutputFile;
+
+    #[test]
+    fn test_staging_rollback() {
+        let dir = std::env::temp_dir().join(format!("ei-staging-test-{}", std::process::id()));
+        let file = |name: &str| OutputFile {
+            install_path: dir.join(name).to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a"), "old a").unwrap();
+        std::fs::write(dir.join("b"), "old b").unwrap();
+
+        // `b` is never staged, so its swap fails after `a` was replaced.
+        let staging = Staging::new(&dir).unwrap();
+        std::fs::write(
+            staging.staged_path(&file("a").install_path).unwrap(),
+            "new a",
+        )
+        .unwrap();
+        assert!(staging.commit(&[file("a"), file("b")], &[]).is_err());
+        assert_eq!(std::fs::read_to_string(dir.join("a")).unwrap(), "old a");
+        assert_eq!(std::fs::read_to_string(dir.join("b")).unwrap(), "old b");
+
+        let staging = Staging::new(&dir).unwrap();
+        for name in ["a", "b"] {
+            let staged = staging.staged_path(&file(name).install_path).unwrap();
+            std::fs::write(staged, format!("new {name}")).unwrap();
+        }
+        staging.commit(&[file("a"), file("b")], &[]).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("a")).unwrap(), "new a");
+        assert_eq!(std::fs::read_to_string(dir.join("b")).unwrap(), "new b");
+
+        // `c` was shipped by the old version only; a rollback brings it back.
+        std::fs::write(dir.join("c"), "old c").unwrap();
+        let stale = [dir.join("c")];
+        let staging = Staging::new(&dir).unwrap();
+        assert!(staging.commit(&[file("a")], &stale).is_err());
+        assert_eq!(std::fs::read_to_string(dir.join("c")).unwrap(), "old c");
+
+        let staging = Staging::new(&dir).unwrap();
+        let staged = staging.staged_path(&file("a").install_path).unwrap();
+        std::fs::write(staged, "newer a").unwrap();
+        staging.commit(&[file("a")], &stale).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("a")).unwrap(), "newer a");
+        assert!(!dir.join("c").exists());
+
+        let mut left: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["a", "b"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
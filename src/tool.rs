@@ -1,14 +1,25 @@
 use crate::InstallConfig;
-use crate::artifact::GhArtifacts;
-use crate::env::add_to_path;
+use crate::appimage::{appimage_matches, appimage_name, appimage_stem, is_appimage};
+use crate::archive::{COMPRESSED_EXTS, Compression};
+use crate::artifact::{ArtifactUrl, GhArtifact, GhArtifacts};
+use crate::env::{add_to_path, get_install_dir, install_dir};
+use crate::file_lock::FileLock;
 use crate::manfiest::DistManifest;
+use crate::package::{PackageFmt, package_matches};
+use crate::picker::pick_variant;
+use crate::receipt::PathEntry;
+use crate::signature::{find_manifest_signature, find_signature_asset};
+use crate::staging::Staging;
 use crate::types::{Output, OutputFile};
+use crate::uninstall::replaced_files;
+use crate::verify::find_checksum_asset;
 use anyhow::{Context, Result};
 use easy_archive::{Fmt, clean};
 use easy_archive::{human_size, mode_to_string, types::IntoEnumIterator};
 use guess_target::{Abi, Arch, Os, guess_target};
 use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
 #[cfg(unix)]
 use std::os::unix::prelude::PermissionsExt;
 use std::path::{Path, PathBuf};
@@ -39,7 +50,7 @@ pub(crate) const MAYBE_EXECUTABLE_EXTS: [&str; 13] = [
     ".nu",
 ];
 
-pub(crate) const SKIP_FMT_LIST: [&str; 20] = [
+pub(crate) const SKIP_FMT_LIST: [&str; 21] = [
     ".sha256sum",
     ".sha256",
     ".sha1",
@@ -56,6 +67,7 @@ pub(crate) const SKIP_FMT_LIST: [&str; 20] = [
     ".log",
     ".sig",
     ".asc",
+    ".minisig",
     ".intoto.jsonl",
     ".jsonl",
     ".sha256",
@@ -179,20 +191,14 @@ fn file_size(p: &str) -> usize {
     std::fs::metadata(p).map(|i| i.len()).unwrap_or(0) as usize
 }
 
-pub(crate) fn add_output_to_path(output: &Output, config: &InstallConfig) {
-    // Collect candidate executable files (non-skipped, non-license).
-    // If exactly one candidate exists, it is treated as the executable
-    // even without an exec bit or known extension.
-    let mut maybe_exe = HashSet::new();
+/// Add the directories holding installed executables to PATH and return
+/// every directory the installation relies on being there.
+pub(crate) fn add_output_to_path(output: &Output, config: &InstallConfig) -> Vec<PathEntry> {
     for v in output.values() {
         for f in &v.files {
-            let is_installable = !is_skip(&f.install_path) && !is_license_file(&f.install_path);
-            if is_installable {
-                maybe_exe.insert(f.install_path.clone());
-            }
             let deep = f.origin_path.split("/").count();
             if deep <= DEEP
-                && is_installable
+                && is_installable(f)
                 && let Some(p) = check(f)
                 && !config.quiet
             {
@@ -206,21 +212,120 @@ pub(crate) fn add_output_to_path(output: &Output, config: &InstallConfig) {
         }
     }
 
-    let mut filter = HashSet::new();
+    output_path_dirs(output)
+        .into_iter()
+        .map(|dir| {
+            let shell = add_to_path(&dir, config.quiet);
+            PathEntry {
+                dir,
+                added: shell.is_some(),
+                shell,
+            }
+        })
+        .collect()
+}
+
+fn is_installable(f: &OutputFile) -> bool {
+    !is_skip(&f.install_path) && !is_license_file(&f.install_path)
+}
+
+/// Directories `add_output_to_path` puts on PATH: every install directory
+/// and the directories of top-level executables.
+pub(crate) fn output_path_dirs(output: &Output) -> Vec<String> {
+    // Collect candidate executable files (non-skipped, non-license).
+    // If exactly one candidate exists, it is treated as the executable
+    // even without an exec bit or known extension.
+    let maybe_exe: HashSet<&str> = output
+        .values()
+        .flat_map(|v| &v.files)
+        .filter(|f| is_installable(f))
+        .map(|f| f.install_path.as_str())
+        .collect();
+
+    let mut dirs: Vec<String> = vec![];
+    let mut push = |dir: &str| {
+        let dir = dir.trim_end_matches('/');
+        if !dirs.iter().any(|d| d == dir) {
+            dirs.push(dir.to_string());
+        }
+    };
     for v in output.values() {
-        add_to_path(&v.install_dir, config.quiet);
+        push(&v.install_dir);
 
         for f in &v.files {
             let deep = f.origin_path.split("/").count();
-            let is_exe = (maybe_exe.len() == 1 && maybe_exe.contains(&f.install_path))
+            let is_exe = (maybe_exe.len() == 1 && maybe_exe.contains(f.install_path.as_str()))
                 || ends_with_exe(&f.origin_path)
                 || (f.mode.unwrap_or(0) & EXEC_MASK != 0);
-            let dir = dirname(&f.install_path);
-            if deep <= DEEP && is_exe && !filter.contains(&dir) {
-                add_to_path(&dir, config.quiet);
-                filter.insert(dir);
+            if deep <= DEEP && is_exe {
+                push(&dirname(&f.install_path));
+            }
+        }
+    }
+    dirs
+}
+
+/// Replace the release version in `name`, written as `tag` or as `tag`
+/// without its `v` prefix, by `new_tag`. Only occurrences bounded by
+/// separators count, so tag `v6` leaves `x86_64` alone.
+pub(crate) fn replace_version(name: &str, tag: &str, new_tag: &str) -> String {
+    if tag.is_empty() {
+        return name.to_string();
+    }
+    let mut pairs = vec![(tag, new_tag)];
+    if let Some(version) = tag.strip_prefix('v').filter(|v| !v.is_empty()) {
+        pairs.push((version, new_tag.strip_prefix('v').unwrap_or(new_tag)));
+    }
+    let bounded = |i: usize, end: usize| {
+        let before = name[..i].chars().next_back();
+        let mut after = name[end..].chars();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric())
+            && match after.next() {
+                None => true,
+                Some('.') => !after.next().is_some_and(|c| c.is_ascii_digit()),
+                Some(c) => !c.is_ascii_alphanumeric(),
+            }
+    };
+    let mut out = String::new();
+    let mut i = 0;
+    while i < name.len() {
+        let hit = pairs
+            .iter()
+            .find(|(from, _)| name[i..].starts_with(from) && bounded(i, i + from.len()));
+        if let Some((from, to)) = hit {
+            out.push_str(to);
+            i += from.len();
+        } else {
+            let c = name[i..].chars().next().unwrap_or_default();
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    out
+}
+
+/// Print `rows` in left-aligned columns under `header`.
+pub(crate) fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
+        }
+    }
+    let line = |cells: [&str; N]| {
+        let mut s = String::new();
+        for (i, (cell, w)) in cells.iter().zip(widths).enumerate() {
+            if i + 1 == N {
+                s.push_str(cell);
+            } else {
+                s.push_str(&format!("{cell:<w$}  "));
             }
         }
+        s
+    };
+    println!("{}", line(header));
+    for row in rows {
+        println!("{}", line(row.each_ref().map(String::as_str)));
     }
 }
 
@@ -251,7 +356,7 @@ pub(crate) fn check(file: &OutputFile) -> Option<String> {
     None
 }
 
-pub(crate) fn write_to_file(src: &str, buffer: &[u8], mode: &Option<u32>) -> Result<()> {
+pub(crate) fn write_to_file(src: &str, reader: &mut dyn Read, mode: &Option<u32>) -> Result<()> {
     let d = std::path::PathBuf::from_str(src).context("invalid path for write_to_file")?;
     if let Some(p) = d.parent()
         && !std::fs::exists(p).unwrap_or(false)
@@ -269,14 +374,12 @@ pub(crate) fn write_to_file(src: &str, buffer: &[u8], mode: &Option<u32>) -> Res
         }
     }
 
-    if !buffer.is_empty() {
-        std::fs::write(src, buffer).context("failed to write file")?;
-    }
+    let mut file = std::fs::File::create(src).context("failed to create file")?;
+    std::io::copy(reader, &mut file).context("failed to write file")?;
 
     #[cfg(unix)]
     if let Some(mode) = mode
         && *mode > 0
-        && !buffer.is_empty()
     {
         std::fs::set_permissions(src, PermissionsExt::from_mode(*mode))
             .context("failed to set_permissions")?;
@@ -289,6 +392,59 @@ pub(crate) fn write_to_file(src: &str, buffer: &[u8], mode: &Option<u32>) -> Res
     Ok(())
 }
 
+/// Create a symlink at `path` pointing to `target`, relative to the
+/// directory of `path`. Where symlinks aren't available, the file it points
+/// to is copied instead, and links to directories are skipped.
+pub(crate) fn write_symlink(target: &str, path: &str, quiet: bool) -> Result<()> {
+    let path = Path::new(path);
+    #[cfg(unix)]
+    {
+        let _ = quiet;
+        prepare_link_path(path)?;
+        std::os::unix::fs::symlink(target, path)
+            .with_context(|| format!("failed to create symlink {}", path.display()))?;
+    }
+    #[cfg(not(unix))]
+    {
+        let resolved = path.parent().unwrap_or(Path::new("")).join(target);
+        if resolved.is_dir() {
+            if !quiet {
+                eprintln!(
+                    "Warning: skipping {}, a link to the directory {target}",
+                    path.display()
+                );
+            }
+            return Ok(());
+        }
+        prepare_link_path(path)?;
+        std::fs::copy(&resolved, path)
+            .with_context(|| format!("failed to copy {} for a symlink", resolved.display()))?;
+    }
+    Ok(())
+}
+
+/// Create `path` as a hard link to the already written file `target`.
+pub(crate) fn write_hardlink(target: &str, path: &str) -> Result<()> {
+    let path = Path::new(path);
+    prepare_link_path(path)?;
+    std::fs::hard_link(target, path)
+        .with_context(|| format!("failed to create hard link {}", path.display()))
+}
+
+fn prepare_link_path(path: &Path) -> Result<()> {
+    if let Some(p) = path.parent() {
+        std::fs::create_dir_all(p).context("failed to create_dir_all")?;
+    }
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => anyhow::bail!(
+            "target path is a directory, refusing to overwrite: {}",
+            path.display()
+        ),
+        Ok(_) => std::fs::remove_file(path).context("failed to remove file"),
+        Err(_) => Ok(()),
+    }
+}
+
 fn has_common_elements(arr1: &[String], arr2: &[String]) -> bool {
     arr1.iter().any(|x| arr2.contains(x))
 }
@@ -297,7 +453,7 @@ pub(crate) fn get_artifact_url_from_manfiest(
     url: &str,
     manfiest: &DistManifest,
     config: &InstallConfig,
-) -> Vec<(String, String)> {
+) -> Vec<ArtifactUrl> {
     let mut v = vec![];
     let local_target = config.get_local_target();
     let local_strs: Vec<String> = local_target
@@ -315,7 +471,7 @@ pub(crate) fn get_artifact_url_from_manfiest(
             continue;
         }
         if has_common_elements(&art.target_triples, &local_strs) {
-            push_manifest_artifact(&mut v, url, key, art);
+            push_manifest_artifact(&mut v, url, manfiest, key, art);
         }
     }
 
@@ -339,7 +495,7 @@ pub(crate) fn get_artifact_url_from_manfiest(
                 })
             });
             if abi_match {
-                push_manifest_artifact(&mut v, url, key, art);
+                push_manifest_artifact(&mut v, url, manfiest, key, art);
             }
         }
     }
@@ -348,8 +504,9 @@ pub(crate) fn get_artifact_url_from_manfiest(
 }
 
 fn push_manifest_artifact(
-    v: &mut Vec<(String, String)>,
+    v: &mut Vec<ArtifactUrl>,
     url: &str,
+    manfiest: &DistManifest,
     key: &str,
     art: &crate::manfiest::Artifact,
 ) {
@@ -361,11 +518,21 @@ fn push_manifest_artifact(
     let filename = get_filename(key);
     let name = name_no_ext(&filename);
     let name = guess_target(&name).pop().map_or(name, |i| i.name);
-    if !is_url(key) {
-        v.push((name, replace_filename(url, key)));
-    } else {
-        v.push((name, key.to_string()));
-    }
+    let resolve = |id: &str| {
+        if is_url(id) {
+            id.to_string()
+        } else {
+            replace_filename(url, id)
+        }
+    };
+    v.push(ArtifactUrl {
+        name,
+        url: resolve(key),
+        checksum_url: art.checksum.as_deref().map(resolve),
+        digest: None,
+        signature_url: find_manifest_signature(&manfiest.artifacts, key).map(resolve),
+        sha256: None,
+    });
 }
 
 pub(crate) fn get_common_prefix_len(list: &[&str]) -> usize {
@@ -412,10 +579,11 @@ pub(crate) fn maybe_executable(name: &str) -> bool {
 }
 
 // if no executable file is found, then the only possible executable program is set to executable
+// links take their mode from what they point to, so they are never picked
 pub(crate) fn guess_executable(files: &mut [OutputFile]) {
     let exe_files: Vec<_> = files
         .iter()
-        .filter(|i| is_executable(i.mode.unwrap_or(0)))
+        .filter(|i| i.link.is_none() && is_executable(i.mode.unwrap_or(0)))
         .collect();
 
     if !exe_files.is_empty() {
@@ -424,7 +592,7 @@ pub(crate) fn guess_executable(files: &mut [OutputFile]) {
 
     let mut no_ext_files: Vec<_> = files
         .iter_mut()
-        .filter(|i| !get_filename(&i.origin_path).contains("."))
+        .filter(|i| i.link.is_none() && !get_filename(&i.origin_path).contains("."))
         .collect();
 
     if let &mut [first] = &mut no_ext_files.as_mut_slice() {
@@ -434,7 +602,7 @@ pub(crate) fn guess_executable(files: &mut [OutputFile]) {
 
     let mut maybe_executable: Vec<_> = files
         .iter_mut()
-        .filter(|i| maybe_executable(&i.origin_path))
+        .filter(|i| i.link.is_none() && maybe_executable(&i.origin_path))
         .collect();
     if let &mut [first] = &mut maybe_executable.as_mut_slice() {
         first.mode = Some(0o755);
@@ -447,7 +615,7 @@ fn rename_alias(files: &mut [OutputFile], alias: &str) {
     } else {
         let mut iter = files.iter_mut().filter(|i| {
             let name = get_filename(&i.origin_path);
-            executable(&name, &i.mode)
+            i.link.is_none() && executable(&name, &i.mode)
         });
 
         let first = iter.next();
@@ -511,44 +679,72 @@ pub(crate) fn check_disk_space(files: &[OutputFile], dir: &PathBuf) -> Result<()
     Ok(())
 }
 
-pub(crate) fn install_output_files(files: &mut [OutputFile], config: &InstallConfig) -> Result<()> {
+/// The directory an install goes to: `~/.ei`, or `--dir`, either a path or
+/// the name of a directory inside `~/.ei`.
+pub(crate) fn get_target_dir(config: &InstallConfig) -> Result<PathBuf> {
+    let install_dir = if config.dry_run {
+        install_dir()?
+    } else {
+        get_install_dir()?
+    };
+    Ok(match &config.dir {
+        Some(dir) if dir.contains("/") || dir.contains("\\") => expand_path(dir).into(),
+        Some(dir) => install_dir.join(dir),
+        None => install_dir,
+    })
+}
+
+/// Apply `--alias` and pick the executable, fixing the final install paths
+/// and modes.
+pub(crate) fn settle_output_files(files: &mut [OutputFile], config: &InstallConfig) {
     if let Some(alias) = config.alias.clone() {
         rename_alias(files, &alias);
     }
-
     guess_executable(files);
-    for OutputFile {
-        install_path,
-        buffer,
-        mode,
-        origin_path,
-        ..
-    } in files.iter()
-    {
-        // FIXME: skip __MACOSX
-        if origin_path.starts_with("__MACOSX") {
-            continue;
-        }
-        write_to_file(install_path, buffer, mode)?;
-    }
+}
+
+/// Settle install paths and modes, let `write` put the content into a
+/// staging directory, then swap it into `install_dir` and optimize the
+/// executable. A failure before the swap completes leaves the previously
+/// installed files untouched.
+pub(crate) fn install_output_files(
+    files: &mut [OutputFile],
+    install_dir: &Path,
+    config: &InstallConfig,
+    write: impl FnOnce(&[OutputFile]) -> Result<()>,
+) -> Result<()> {
+    settle_output_files(files, config);
+    let _lock = FileLock::install_root(&get_target_dir(config)?, config.quiet)?;
+    let staging = Staging::new(install_dir)?;
+    let staged = files
+        .iter()
+        .map(|f| {
+            Ok(OutputFile {
+                install_path: staging.staged_path(&f.install_path)?,
+                ..f.clone()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    write(&staged)?;
 
     #[cfg(not(windows))]
     {
-        let maybe_exe = files
+        let maybe_exe = staged
             .iter()
-            .filter(|i| !is_skip(&i.origin_path))
+            .filter(|i| i.link.is_none() && !is_skip(&i.origin_path))
             .collect::<Vec<_>>();
 
         if let [single_exe] = maybe_exe.as_slice() {
             add_execute_permission(&single_exe.install_path)?;
         }
     }
+    staging.commit(files, &replaced_files(files, install_dir))?;
 
     // Optimize single executable if strip or upx flags are enabled
     if config.strip || config.upx {
         let executables: Vec<_> = files
             .iter()
-            .filter(|f| executable(&get_filename(&f.install_path), &f.mode))
+            .filter(|f| f.link.is_none() && executable(&get_filename(&f.install_path), &f.mode))
             .collect();
 
         if let [single_exe] = executables.as_slice() {
@@ -609,8 +805,9 @@ fn is_compatible_abi(a: Option<Abi>, b: Option<Abi>) -> bool {
     )
 }
 
-/// Cached, length-sorted list of all known file extensions (archive formats
-/// plus executable/installer/text/skip extensions). Built once and reused.
+/// Cached, length-sorted list of all known file extensions (archive and
+/// compression formats plus executable/installer/text/skip extensions).
+/// Built once and reused.
 fn known_extensions() -> &'static Vec<String> {
     static CACHE: LazyLock<Vec<String>> = LazyLock::new(|| {
         let mut v: Vec<String> = Fmt::iter()
@@ -623,6 +820,7 @@ fn known_extensions() -> &'static Vec<String> {
                     .chain(TEXT_FILE_EXTS.iter())
                     .chain(MAYBE_EXECUTABLE_EXTS.iter())
                     .chain(SKIP_FMT_LIST.iter())
+                    .chain(COMPRESSED_EXTS.iter())
                     .map(|e| e.to_string()),
             )
             .collect();
@@ -666,6 +864,11 @@ pub(crate) fn is_archive_file(s: &str) -> bool {
     Fmt::guess(s).is_some()
 }
 
+/// A single compressed file such as `tool.gz`, as opposed to a tarball.
+pub(crate) fn is_compressed_file(s: &str) -> bool {
+    Compression::guess_file(&get_filename(s)).is_some()
+}
+
 pub(crate) fn ends_with_exe(s: &str) -> bool {
     WINDOWS_EXE_EXTS.iter().any(|i| s.ends_with(i))
 }
@@ -745,46 +948,38 @@ pub(crate) fn replace_filename(base_url: &str, name: &str) -> String {
     }
 }
 
+/// Pick the release assets to install for the target, printing why each
+/// asset was taken or left with `--explain`.
 pub(crate) fn get_artifact_url(
     artifacts: GhArtifacts,
     config: &InstallConfig,
-) -> Result<Vec<(String, String)>> {
+) -> Result<Vec<ArtifactUrl>> {
+    let mut explain = Explain::new(config.explain);
+    let list = select_artifacts(artifacts, config, &mut explain);
+    explain.print(config);
+    list
+}
+
+fn select_artifacts(
+    artifacts: GhArtifacts,
+    config: &InstallConfig,
+    explain: &mut Explain,
+) -> Result<Vec<ArtifactUrl>> {
     use crate::types::Repo;
 
     let mut v = vec![];
     let local_target = config.get_local_target();
 
-    // When --regex is supplied, pre-compute the compiled regex and verify
-    // it matches exactly one asset. The regex is matched against the
-    // original filename (not the stem), and the matching asset is selected
-    // directly — no guess_target, no target-triple inference.
+    // When --regex is supplied, it must match exactly one asset. The regex
+    // is matched against the original filename (not the stem), and the
+    // matching asset is selected directly — no guess_target, no
+    // target-triple inference.
     let regex_compiled: Option<regex::Regex> = match &config.regex {
         Some(re_str) => Some(regex::Regex::new(re_str).context("invalid --regex pattern")?),
         None => None,
     };
-    if let Some(re) = &regex_compiled {
-        let matched: Vec<String> = artifacts
-            .assets
-            .iter()
-            .filter(|a| {
-                let f = get_filename(&a.browser_download_url);
-                re.is_match(&f)
-            })
-            .map(|a| a.name.clone())
-            .collect();
-        if matched.is_empty() {
-            anyhow::bail!("--regex did not match any assets. Check the pattern and try again.");
-        }
-        if matched.len() > 1 {
-            anyhow::bail!(
-                "--regex matched {} assets, expected exactly 1. Pattern is too permissive.\n  Matched: {:#?}\n  Tighten the regex (e.g. anchor it to the platform triple) so only one asset remains.",
-                matched.len(),
-                matched
-            );
-        }
-    }
 
-    for i in artifacts.assets {
+    for i in &artifacts.assets {
         let filename = get_filename(&i.browser_download_url);
         // When the download URL is an API endpoint (e.g. GitHub Actions
         // artifact download URL ending in .../zip), get_filename returns
@@ -797,6 +992,8 @@ pub(crate) fn get_artifact_url(
             i.name.clone()
         };
 
+        explain.add(&filename);
+
         // --regex mode: match directly against the original filename.
         // When matched, the asset is selected immediately — no guess_target,
         // no target-triple matching. The regex is the sole authority.
@@ -804,21 +1001,41 @@ pub(crate) fn get_artifact_url(
             if re.is_match(&filename) {
                 let rank = u32::MAX;
                 let name = name_no_ext(&filename);
-                v.push((rank, name, i.browser_download_url.clone()));
+                explain.rule("matches --regex".to_string());
+                v.push(Matched {
+                    rank,
+                    name,
+                    asset: i,
+                    filename,
+                    kind: AssetKind::Archive,
+                });
+            } else {
+                explain.reject("does not match --regex".to_string());
             }
             continue;
         }
 
-        if is_skip(&i.browser_download_url) {
+        // Distribution packages and AppImages are installer formats too, but
+        // ei can install them.
+        let package = PackageFmt::guess(&filename);
+        let kind = AssetKind::of(&filename);
+        if is_skip(&i.browser_download_url) && kind == AssetKind::Archive {
+            explain.reject("installer, checksum or text file".to_string());
             continue;
         }
         if ends_with_exe(&i.browser_download_url)
             && local_target.iter().any(|t| t.os() != Os::Windows)
         {
+            explain.reject(".exe for a non-Windows target".to_string());
             continue;
         }
         let name_no_ext_str = name_no_ext(&filename);
         let guess = guess_target(&name_no_ext_str);
+        explain.parsed(
+            guess
+                .iter()
+                .map(|g| format!("{} {} (rank {})", g.name, g.target.to_str(), g.rank)),
+        );
 
         // --name filter: match against the guess_target-inferred tool name
         // when available (e.g. "lumen" from "lumen-x86_64-unknown-linux-gnu",
@@ -837,6 +1054,10 @@ pub(crate) fn get_artifact_url(
                     .any(|n| guess.iter().any(|g| g.name == n.as_str()))
             };
             if !matched {
+                explain.reject(format!(
+                    "tool name does not match --name {}",
+                    config.name.join(",")
+                ));
                 continue;
             }
         }
@@ -850,6 +1071,7 @@ pub(crate) fn get_artifact_url(
         //    fallback, so exact matches win over both.
         const RANK_PENALTY: u32 = 1;
         let mut penalized = false;
+        let mut rule = "exact target match";
         let item = if let Some(t) = config.target {
             let exact = guess.iter().find(|i| i.target == t);
             if let Some(m) = exact {
@@ -863,6 +1085,7 @@ pub(crate) fn get_artifact_url(
                 });
                 if let Some(m) = abi_fallback {
                     penalized = true;
+                    rule = "ABI fallback";
                     Some(m)
                 } else if config.fuzzy {
                     let fuzzy = guess
@@ -870,6 +1093,7 @@ pub(crate) fn get_artifact_url(
                         .find(|i| i.target.arch() == t.arch() && i.target.os() == t.os());
                     if fuzzy.is_some() {
                         penalized = true;
+                        rule = "fuzzy match";
                     }
                     fuzzy
                 } else {
@@ -891,6 +1115,7 @@ pub(crate) fn get_artifact_url(
                 });
                 if let Some(m) = abi_fallback {
                     penalized = true;
+                    rule = "ABI fallback";
                     Some(m)
                 } else if config.fuzzy {
                     let fuzzy = guess.iter().find(|i| {
@@ -900,6 +1125,7 @@ pub(crate) fn get_artifact_url(
                     });
                     if fuzzy.is_some() {
                         penalized = true;
+                        rule = "fuzzy match";
                     }
                     fuzzy
                 } else {
@@ -920,45 +1146,367 @@ pub(crate) fn get_artifact_url(
             } else {
                 rank
             };
-            v.push((rank, item.name.clone(), i.browser_download_url.clone()));
+            let mut score = format!("rank {}", item.rank);
+            if hack_musl > 0 {
+                score += &format!(" + {hack_musl} aarch64 musl bonus");
+            }
+            if penalized {
+                score += &format!(" - {RANK_PENALTY} penalty");
+            }
+            explain.rule(format!(
+                "{rule} on {}, {score} = {rank}",
+                item.target.to_str()
+            ));
+            v.push(Matched {
+                rank,
+                name: item.name.clone(),
+                asset: i,
+                filename,
+                kind,
+            });
+        } else if let Some(pkg) = package {
+            // Package names carry a Debian/RPM architecture rather than a
+            // target triple, e.g. `ripgrep_14.1.0-1_amd64.deb`.
+            let matched = match config.target {
+                Some(t) => package_matches(&name_no_ext_str, &t),
+                None => local_target
+                    .iter()
+                    .any(|t| package_matches(&name_no_ext_str, t)),
+            };
+            if matched {
+                explain.rule("package for this architecture".to_string());
+                v.push(Matched {
+                    rank: 0,
+                    name: pkg.package_name(&name_no_ext_str),
+                    asset: i,
+                    filename,
+                    kind,
+                });
+            } else {
+                explain.reject("package for another architecture".to_string());
+            }
+        } else if kind == AssetKind::AppImage {
+            let stem = appimage_stem(&filename);
+            let matched = match config.target {
+                Some(t) => appimage_matches(stem, &t),
+                None => local_target.iter().any(|t| appimage_matches(stem, t)),
+            };
+            if matched {
+                explain.rule("AppImage for this architecture".to_string());
+                v.push(Matched {
+                    rank: 0,
+                    name: appimage_name(stem),
+                    asset: i,
+                    filename,
+                    kind,
+                });
+            } else {
+                explain.reject("AppImage for another architecture".to_string());
+            }
+        } else if guess.is_empty() {
+            explain.reject("no target recognized in the name".to_string());
+        } else if !config.fuzzy
+            && local_target.iter().any(|t| {
+                guess
+                    .iter()
+                    .any(|g| g.target.arch() == t.arch() && g.target.os() == t.os())
+            })
+        {
+            explain.reject("only the abi differs (--fuzzy would accept it)".to_string());
+        } else {
+            explain.reject("no target matches".to_string());
+        }
+    }
+
+    if regex_compiled.is_some() {
+        let matched: Vec<&str> = v.iter().map(|m| m.filename.as_str()).collect();
+        if matched.is_empty() {
+            anyhow::bail!("--regex did not match any assets. Check the pattern and try again.");
+        }
+        if matched.len() > 1 {
+            for filename in &matched {
+                explain.lose(filename, "--regex matched more than one asset".to_string());
+            }
+            anyhow::bail!(
+                "--regex matched {} assets, expected exactly 1. Pattern is too permissive.\n  Matched: {:#?}\n  Tighten the regex (e.g. anchor it to the platform triple) so only one asset remains.",
+                matched.len(),
+                matched
+            );
         }
     }
 
+    if let Some(best) = v.iter().map(|m| m.kind).min() {
+        for m in &v {
+            if m.kind > best {
+                explain.lose(&m.filename, format!("a {} matched", best.describe()));
+            }
+        }
+        v.retain(|m| m.kind == best);
+    }
     // we should still apply rank-based deduplication (keep only highest-rank per name).
-    let max_rank = v.iter().fold(0, |pre, cur| pre.max(cur.0));
+    let max_rank = v.iter().fold(0, |pre, cur| pre.max(cur.rank));
+    // Several assets of one tool can tie, e.g. llrt-full-sdk and
+    // llrt-no-sdk variants, or the same build as .tar.gz and .zip.
+    let mut picked = HashMap::new();
+    for Matched {
+        name, asset, kind, ..
+    } in v.iter().filter(|m| m.rank == max_rank)
+    {
+        if picked.contains_key(name) {
+            continue;
+        }
+        let mut tied: Vec<&str> = v
+            .iter()
+            .filter(|m| m.rank == max_rank && m.name == *name)
+            .map(|m| m.filename.as_str())
+            .collect();
+        tied.sort();
+        tied.dedup();
+        // One build in several formats is no choice, so each variant counts
+        // once: archives by their name without extension, packages by the
+        // package name they already share.
+        let mut seen = HashSet::new();
+        let variants: Vec<&str> = tied
+            .iter()
+            .copied()
+            .filter(|f| match kind {
+                AssetKind::Package => seen.insert(String::new()),
+                _ => seen.insert(name_no_ext(f)),
+            })
+            .collect();
+        if variants.len() > 1 {
+            let url = &asset.browser_download_url;
+            let tag = artifacts.tag_name.as_deref();
+            let p = pick_variant(name, &tied, &variants, url, tag, config)?;
+            picked.insert(name.clone(), p);
+        } else if tied.len() > 1 {
+            // Of the formats, the first by name.
+            picked.insert(name.clone(), variants[0].to_string());
+        }
+    }
     let mut filter = vec![];
     let mut list = vec![];
-    // FIXME: Need user to select eg: llrt-no-sdk llrt-full-sdk
-    for (rank, name, url) in v {
+    for Matched {
+        rank,
+        name,
+        asset,
+        filename,
+        ..
+    } in v
+    {
         if rank < max_rank {
+            explain.lose(
+                &filename,
+                format!("rank {rank} is below the best rank {max_rank}"),
+            );
             continue;
         }
-        if filter.contains(&name) {
+        if let Some(p) = picked.get(&name)
+            && *p != filename
+        {
+            explain.lose(&filename, format!("{p} was picked for `{name}`"));
+            continue;
+        }
+        if let Some((_, winner)) = filter.iter().find(|(n, _)| *n == name) {
+            explain.lose(&filename, format!("{winner} was chosen for `{name}` first"));
             continue;
         }
 
-        filter.push(name.clone());
-        let proxied_url = Repo::convert_github_url_to_proxy(&url, config.proxy);
-        list.push((name, proxied_url));
+        filter.push((name.clone(), filename.clone()));
+        let url = Repo::convert_github_url_to_proxy(&asset.browser_download_url, config.proxy);
+        let checksum_url = find_checksum_asset(&artifacts.assets, &filename)
+            .map(|c| Repo::convert_github_url_to_proxy(&c.browser_download_url, config.proxy));
+        let signature_url = find_signature_asset(&artifacts.assets, &filename)
+            .map(|s| Repo::convert_github_url_to_proxy(&s.browser_download_url, config.proxy));
+        list.push((
+            filename,
+            ArtifactUrl {
+                name,
+                url,
+                checksum_url,
+                digest: asset.digest.clone(),
+                signature_url,
+                sha256: None,
+            },
+        ));
+    }
+    if let Some(alias) = &config.alias
+        && list.iter().any(|(_, a)| &a.name == alias)
+    {
+        for (filename, a) in &list {
+            if &a.name != alias {
+                explain.lose(filename, format!("--alias {alias} picks `{alias}`"));
+            }
+        }
+    }
+    let list = list.into_iter().map(|(_, a)| a).collect();
+    Ok(filter_artifacts(list, config))
+}
+
+/// What an asset installs from. Packages and AppImages are only used when
+/// no archive or binary matches, packages before AppImages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum AssetKind {
+    Archive,
+    Package,
+    AppImage,
+}
+
+impl AssetKind {
+    fn of(filename: &str) -> Self {
+        if PackageFmt::guess(filename).is_some() {
+            AssetKind::Package
+        } else if is_appimage(filename) {
+            AssetKind::AppImage
+        } else {
+            AssetKind::Archive
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            AssetKind::Archive => "archive or binary",
+            AssetKind::Package => "package",
+            AssetKind::AppImage => "AppImage",
+        }
+    }
+}
+
+/// An asset `get_artifact_url` accepted for the local target.
+struct Matched<'a> {
+    rank: u32,
+    /// Tool name the asset installs.
+    name: String,
+    asset: &'a GhArtifact,
+    filename: String,
+    kind: AssetKind,
+}
+
+/// Why `get_artifact_url` took or left each asset, printed with `--explain`.
+struct Explain {
+    enabled: bool,
+    candidates: Vec<Candidate>,
+}
+
+struct Candidate {
+    filename: String,
+    /// `guess_target` results as `name target (rank n)`.
+    parsed: Vec<String>,
+    /// The rule that accepted the asset.
+    rule: Option<String>,
+    /// Why the asset was rejected or lost; None if it was selected.
+    result: Option<String>,
+}
+
+impl Explain {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            candidates: vec![],
+        }
+    }
+
+    fn add(&mut self, filename: &str) {
+        if self.enabled {
+            self.candidates.push(Candidate {
+                filename: filename.to_string(),
+                parsed: vec![],
+                rule: None,
+                result: Some("not considered".to_string()),
+            });
+        }
+    }
+
+    fn last(&mut self) -> Option<&mut Candidate> {
+        self.candidates.last_mut()
+    }
+
+    fn parsed(&mut self, guess: impl Iterator<Item = String>) {
+        if let Some(c) = self.last() {
+            c.parsed = guess.collect();
+        }
+    }
+
+    fn rule(&mut self, rule: String) {
+        if let Some(c) = self.last() {
+            c.rule = Some(rule);
+            c.result = None;
+        }
+    }
+
+    fn reject(&mut self, reason: String) {
+        if let Some(c) = self.last() {
+            c.result = Some(format!("rejected: {reason}"));
+        }
+    }
+
+    fn find(&mut self, filename: &str) -> Option<&mut Candidate> {
+        self.candidates.iter_mut().find(|c| c.filename == filename)
+    }
+
+    fn lose(&mut self, filename: &str, reason: String) {
+        if let Some(c) = self.find(filename) {
+            c.result = Some(format!("lost: {reason}"));
+        }
+    }
+
+    fn print(mut self, config: &InstallConfig) {
+        if !self.enabled {
+            return;
+        }
+        self.candidates.sort_by(|a, b| a.filename.cmp(&b.filename));
+        print!("{}", self.render(config));
+    }
+
+    fn render(&self, config: &InstallConfig) -> String {
+        let mut s = match config.target {
+            Some(t) => format!("Asset candidates for {} (--target):\n", t.to_str()),
+            None => {
+                let targets: Vec<_> = config
+                    .get_local_target()
+                    .iter()
+                    .map(|t| t.to_str())
+                    .collect();
+                format!("Asset candidates for {}:\n", targets.join(", "))
+            }
+        };
+        for c in &self.candidates {
+            s += &format!("  {}\n", c.filename);
+            if c.parsed.is_empty() {
+                s += "    parsed: (unknown)\n";
+            }
+            for p in &c.parsed {
+                s += &format!("    parsed: {p}\n");
+            }
+            if let Some(rule) = &c.rule {
+                s += &format!("    rule:   {rule}\n");
+            }
+            s += &format!(
+                "    result: {}\n",
+                c.result.as_deref().unwrap_or("selected")
+            );
+        }
+        s
     }
-    Ok(list)
 }
 
-/// Apply `--alias` and `--name` filters to a list of (name, url) artifacts.
+/// Apply `--alias` and `--name` filters to a list of artifacts.
 ///
 /// - When `--alias` is set and matches at least one artifact name, only those
 ///   matching artifacts are kept (rename happens later in `install_output_files`).
 ///
 /// Note: `--name` filtering is applied earlier, inside `get_artifact_url`
 /// (against the raw asset filename), so it does not need to be re-applied here.
+/// `get_artifact_url` applies this filter to what it picks; dist manifests
+/// apply it themselves.
 pub(crate) fn filter_artifacts(
-    artifact_url: Vec<(String, String)>,
+    artifact_url: Vec<ArtifactUrl>,
     config: &InstallConfig,
-) -> Vec<(String, String)> {
+) -> Vec<ArtifactUrl> {
     if let Some(alias) = &config.alias {
         let matching: Vec<_> = artifact_url
             .iter()
-            .filter(|(name, _)| name == alias)
+            .filter(|a| &a.name == alias)
             .cloned()
             .collect();
         if matching.is_empty() {
@@ -1085,10 +1633,12 @@ mod test {
 
     use crate::{
         InstallConfig,
+        artifact::ArtifactUrl,
         download::download_dist_manfiest,
         tool::{
             dirname, get_artifact_url_from_manfiest, is_archive_file, is_compatible_abi,
-            is_exe_file, is_url, name_boundary_match,
+            is_compressed_file, is_exe_file, is_url, name_boundary_match, name_no_ext,
+            replace_version,
         },
         types::Repo,
     };
@@ -1096,6 +1646,27 @@ mod test {
 
     use super::{get_bin_name, get_common_prefix_len};
 
+    #[test]
+    fn test_replace_version() {
+        assert_eq!(
+            replace_version("tool-v1.2.0-x86_64-linux.tar.gz", "v1.2.0", "v1.3.0"),
+            "tool-v1.3.0-x86_64-linux.tar.gz"
+        );
+        assert_eq!(
+            replace_version("tool_1.2.0_amd64.deb", "v1.2.0", "v1.3.0"),
+            "tool_1.3.0_amd64.deb"
+        );
+        assert_eq!(
+            replace_version("tool-6-x86_64-linux.tar.gz", "v6", "{version}"),
+            "tool-{version}-x86_64-linux.tar.gz"
+        );
+        assert_eq!(
+            replace_version("tool-1.2.0.tar.gz", "1.2", "1.3"),
+            "tool-1.2.0.tar.gz"
+        );
+        assert_eq!(replace_version("tool.zip", "", "v1"), "tool.zip");
+    }
+
     #[test]
     fn test_is_file() {
         assert!(!is_archive_file("https://github.com/ahaoboy/ansi2"));
@@ -1111,6 +1682,16 @@ mod test {
         assert!(is_archive_file(
             "https://github.com/ahaoboy/ansi2/releases/download/v0.2.11/ansi2-x86_64-pc-windows-msvc.zip"
         ));
+        assert!(is_compressed_file(
+            "https://github.com/rust-lang/rust-analyzer/releases/download/2025-01-06/rust-analyzer-x86_64-unknown-linux-gnu.gz"
+        ));
+        assert!(!is_compressed_file(
+            "https://github.com/ahaoboy/ansi2/releases/download/v0.2.11/ansi2-x86_64-unknown-linux-musl.tar.gz"
+        ));
+        assert_eq!(
+            name_no_ext("rust-analyzer-x86_64-unknown-linux-gnu.gz"),
+            "rust-analyzer-x86_64-unknown-linux-gnu"
+        );
     }
 
     #[test]
@@ -1211,7 +1792,7 @@ mod test {
             "https://github.com/axodotdev/cargo-dist/releases/download/v0.25.1/dist-manifest.json"
         );
 
-        let manfiest = repo.get_manfiest(3, Proxy::Github, 30).await.unwrap();
+        let manfiest = repo.get_manfiest(&InstallConfig::default()).await.unwrap();
         assert!(!manfiest.artifacts.is_empty());
 
         let repo =
@@ -1222,7 +1803,7 @@ mod test {
             "https://github.com/ahaoboy/mujs-build/releases/download/v0.0.2/dist-manifest.json"
         );
 
-        let manfiest = repo.get_manfiest(3, Proxy::Github, 30).await.unwrap();
+        let manfiest = repo.get_manfiest(&InstallConfig::default()).await.unwrap();
         assert!(!manfiest.artifacts.is_empty())
     }
 
@@ -1241,7 +1822,9 @@ mod test {
     #[tokio::test]
     async fn test_cargo_dist() {
         let url = "https://github.com/axodotdev/cargo-dist/releases/download/v1.0.0-rc.1/dist-manifest.json";
-        let manfiest = download_dist_manfiest(url, 3, 30).await.unwrap();
+        let manfiest = download_dist_manfiest(url, &InstallConfig::default())
+            .await
+            .unwrap();
         let art_url = get_artifact_url_from_manfiest(url, &manfiest, &InstallConfig::default());
         assert!(!art_url.is_empty())
     }
@@ -1282,7 +1865,7 @@ mod test {
         let artifact_url = repo.get_artifact_url(&config).await.unwrap();
         println!("mpv artifact_url: {artifact_url:?}");
         assert_eq!(artifact_url.len(), 1);
-        let (name, url) = &artifact_url[0];
+        let ArtifactUrl { name, url, .. } = &artifact_url[0];
         assert!(!name.is_empty());
         assert!(url.contains("mpv-v"));
         assert!(url.ends_with("x86_64-pc-windows-msvc.zip"));
@@ -1394,4 +1977,170 @@ mod test {
         assert!(!is_compatible_abi(None, Some(Abi::Gnu)));
         assert!(!is_compatible_abi(Some(Abi::Gnu), None));
     }
+
+    fn release(names: &[&str]) -> crate::artifact::GhArtifacts {
+        crate::artifact::GhArtifacts {
+            assets: names
+                .iter()
+                .map(|name| crate::artifact::GhArtifact {
+                    name: name.to_string(),
+                    browser_download_url: format!(
+                        "https://github.com/owner/tool/releases/download/v1.0.0/{name}"
+                    ),
+                    url: None,
+                    digest: None,
+                })
+                .collect(),
+            tag_name: Some("v1.0.0".to_string()),
+        }
+    }
+
+    fn explained(
+        names: &[&str],
+        config: &InstallConfig,
+    ) -> (anyhow::Result<Vec<ArtifactUrl>>, super::Explain) {
+        let mut explain = super::Explain::new(true);
+        let list = super::select_artifacts(release(names), config, &mut explain);
+        (list, explain)
+    }
+
+    fn result<'a>(explain: &'a super::Explain, filename: &str) -> &'a str {
+        let c = explain.candidates.iter().find(|c| c.filename == filename);
+        c.and_then(|c| c.result.as_deref()).unwrap_or("selected")
+    }
+
+    #[test]
+    fn test_explain_reasons() {
+        use std::str::FromStr;
+
+        let config = InstallConfig {
+            target: Some(guess_target::Target::from_str("x86_64-unknown-linux-gnu").unwrap()),
+            ..Default::default()
+        };
+        let (list, explain) = explained(
+            &[
+                "checksums.txt",
+                "tool-x86_64-pc-windows-msvc.exe",
+                "tool-source.tar.gz",
+                "tool_1.0.0_arm64.deb",
+                "tool_1.0.0_amd64.deb",
+                "Tool-1.0.0-x86_64.AppImage",
+            ],
+            &config,
+        );
+        assert_eq!(list.unwrap().len(), 1);
+        assert_eq!(
+            result(&explain, "checksums.txt"),
+            "rejected: installer, checksum or text file"
+        );
+        assert_eq!(
+            result(&explain, "tool-x86_64-pc-windows-msvc.exe"),
+            "rejected: .exe for a non-Windows target"
+        );
+        assert_eq!(
+            result(&explain, "tool-source.tar.gz"),
+            "rejected: no target recognized in the name"
+        );
+        assert_eq!(
+            result(&explain, "tool_1.0.0_arm64.deb"),
+            "rejected: package for another architecture"
+        );
+        assert_eq!(result(&explain, "tool_1.0.0_amd64.deb"), "selected");
+        assert_eq!(
+            result(&explain, "Tool-1.0.0-x86_64.AppImage"),
+            "lost: a package matched"
+        );
+        assert!(
+            explain
+                .render(&config)
+                .starts_with("Asset candidates for x86_64-unknown-linux-gnu (--target):\n")
+        );
+    }
+
+    #[test]
+    fn test_explain_regex() {
+        let config = InstallConfig {
+            regex: Some("^tool_".to_string()),
+            ..Default::default()
+        };
+        let (list, explain) = explained(
+            &[
+                "checksums.txt",
+                "tool_1.0.0_arm64.deb",
+                "tool_1.0.0_amd64.deb",
+            ],
+            &config,
+        );
+        assert!(list.is_err());
+        assert_eq!(
+            result(&explain, "checksums.txt"),
+            "rejected: does not match --regex"
+        );
+        for filename in ["tool_1.0.0_arm64.deb", "tool_1.0.0_amd64.deb"] {
+            assert_eq!(
+                result(&explain, filename),
+                "lost: --regex matched more than one asset"
+            );
+        }
+    }
+
+    fn linux_config() -> InstallConfig {
+        use std::str::FromStr;
+        InstallConfig {
+            target: Some(guess_target::Target::from_str("x86_64-unknown-linux-gnu").unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tie() {
+        // The same build in several formats is not a choice.
+        let (list, explain) = explained(
+            &[
+                "tool-x86_64-unknown-linux-gnu.zip",
+                "tool-x86_64-unknown-linux-gnu.tar.gz",
+                "tool-x86_64-unknown-linux-gnu.tar.xz",
+            ],
+            &linux_config(),
+        );
+        let list = list.unwrap();
+        assert_eq!(list.len(), 1);
+        assert!(
+            list[0]
+                .url
+                .ends_with("/tool-x86_64-unknown-linux-gnu.tar.gz")
+        );
+        assert_eq!(
+            result(&explain, "tool-x86_64-unknown-linux-gnu.zip"),
+            "lost: tool-x86_64-unknown-linux-gnu.tar.gz was picked for `tool`"
+        );
+
+        // Different variants are, and a remembered choice makes it.
+        let names = [
+            "llrt-linux-x64-full-sdk.zip",
+            "llrt-linux-x64-no-sdk.zip",
+            "llrt-linux-x64-no-sdk.tar.gz",
+        ];
+        let config = InstallConfig {
+            asset: Some("llrt-linux-x64-no-sdk.zip".to_string()),
+            ..linux_config()
+        };
+        let list = explained(&names, &config).0.unwrap();
+        assert_eq!(list.len(), 1);
+        assert!(list[0].url.ends_with("/llrt-linux-x64-no-sdk.zip"));
+
+        // Without one, and without a terminal to ask in, it fails.
+        use std::io::IsTerminal;
+        if !std::io::stdin().is_terminal() {
+            let err = explained(&names, &linux_config()).0.unwrap_err();
+            assert_eq!(
+                err.to_string().lines().take(3).collect::<Vec<_>>(),
+                [
+                    "Several assets match `llrt`:",
+                    "  llrt-linux-x64-full-sdk.zip",
+                    "  llrt-linux-x64-no-sdk.tar.gz"
+                ]
+            );
+        }
+    }
 }
@@ -0,0 +1,302 @@
+use crate::config::{PackageConfig, PersistentConfig};
+use crate::install::install;
+use crate::receipt::{InstallOptions, Receipt, Receipts, package_name};
+use crate::types::{Output, Repo};
+use crate::uninstall::uninstall;
+use crate::verify::parse_sha256;
+use crate::{InstallConfig, record_install};
+use anyhow::{Context, Result};
+use guess_target::Target;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+pub const DEFAULT_TOOLFILE_NAME: &str = "ei.toml";
+
+/// The packages a project expects on the machine, keyed by source.
+///
+/// ```toml
+/// [packages."BurntSushi/ripgrep"]
+/// tag = "14.1.0"
+///
+/// [packages."quickjs-ng/quickjs"]
+/// name = ["qjs"]
+/// ```
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Toolfile {
+    #[serde(default)]
+    pub(crate) packages: BTreeMap<String, ToolEntry>,
+}
+
+/// Per-package install options, named like the command line flags.
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ToolEntry {
+    /// Release tag to install (latest when omitted)
+    tag: Option<String>,
+    #[serde(default)]
+    name: Vec<String>,
+    alias: Option<String>,
+    regex: Option<String>,
+    target: Option<Target>,
+    dir: Option<String>,
+    #[serde(default)]
+    fuzzy: bool,
+    strip: Option<bool>,
+    upx: Option<bool>,
+    sha256: Option<String>,
+    #[serde(default)]
+    pubkeys: Vec<String>,
+}
+
+impl ToolEntry {
+    /// The source to install, with `tag` folded into `owner/repo@tag`.
+    fn source(&self, key: &str) -> Result<String> {
+        let Some(tag) = &self.tag else {
+            return Ok(key.to_string());
+        };
+        match Repo::try_from(key) {
+            Ok(repo) if repo.tag.is_none() => Ok(format!("{}/{}@{tag}", repo.owner, repo.name)),
+            Ok(_) => anyhow::bail!("{key}: tag is given both in the source and as `tag`"),
+            Err(_) => anyhow::bail!("{key}: `tag` only applies to GitHub repositories"),
+        }
+    }
+
+    /// Overlay this entry, then the package `preset`, on the command line
+    /// config.
+    fn apply(&self, config: InstallConfig, preset: PackageConfig) -> Result<InstallConfig> {
+        let options = InstallOptions {
+            name: self.name.clone(),
+            alias: self.alias.clone(),
+            regex: self.regex.clone(),
+            target: self.target.or(preset.target).or(config.target),
+            dir: self
+                .dir
+                .clone()
+                .or(preset.dir.clone())
+                .or(config.dir.clone()),
+            fuzzy: self.fuzzy,
+            desktop: config.desktop,
+        };
+        let sha256 = self
+            .sha256
+            .as_deref()
+            .map(parse_sha256)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("invalid sha256: {e}"))?;
+        let config = options.apply(config);
+        Ok(InstallConfig {
+            strip: self.strip.unwrap_or(config.strip),
+            upx: self.upx.unwrap_or(config.upx),
+            sha256: sha256.or(config.sha256),
+            pubkeys: if self.pubkeys.is_empty() {
+                config.pubkeys
+            } else {
+                self.pubkeys.clone()
+            },
+            ..config
+        }
+        .with_preset(preset))
+    }
+}
+
+impl Toolfile {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Every entry as a source and the config to install it with.
+    pub(crate) fn entries(&self, config: &InstallConfig) -> Result<Vec<(String, InstallConfig)>> {
+        let presets = PersistentConfig::load();
+        self.packages
+            .iter()
+            .map(|(key, entry)| {
+                let source = entry.source(key)?;
+                let config = entry
+                    .apply(config.clone(), presets.package(&source))
+                    .with_context(|| format!("{key} in {DEFAULT_TOOLFILE_NAME}"))?;
+                Ok((source, config))
+            })
+            .collect()
+    }
+}
+
+/// Why a listed package has to be (re)installed, or `None` when the
+/// installed copy already matches.
+async fn install_reason(
+    source: &str,
+    config: &InstallConfig,
+    installed: Option<&Receipt>,
+) -> Result<Option<String>> {
+    let Some(receipt) = installed else {
+        return Ok(Some("missing".to_string()));
+    };
+    if receipt.options != InstallOptions::from(config) {
+        return Ok(Some("options changed".to_string()));
+    }
+    let repo = match Repo::try_from(source) {
+        Ok(repo) if receipt.repo.is_some() => repo,
+        // Direct URLs and files carry their version in the source itself.
+        _ if receipt.source == source => return Ok(None),
+        _ => return Ok(Some("source changed".to_string())),
+    };
+    let wanted = match repo.tag.clone() {
+        Some(tag) => tag,
+        None => repo.get_latest_release_tag(config).await?,
+    };
+    let installed = receipt.tag.as_deref().unwrap_or("-");
+    Ok((installed != wanted).then(|| format!("{installed} -> {wanted}")))
+}
+
+/// Install everything in the toolfile that is missing or at the wrong
+/// version. With `prune`, packages an earlier sync of the same toolfile
+/// installed but which are no longer listed are removed.
+pub(crate) async fn sync(prune: bool, config: &InstallConfig) -> Result<()> {
+    let path = Path::new(DEFAULT_TOOLFILE_NAME);
+    if !path.exists() {
+        anyhow::bail!("{DEFAULT_TOOLFILE_NAME} not found in the current directory");
+    }
+    let toolfile_path = std::fs::canonicalize(path)
+        .with_context(|| format!("Failed to resolve {}", path.display()))?
+        .to_string_lossy()
+        .to_string();
+    let entries = Toolfile::load(path)?.entries(config)?;
+    let receipts = Receipts::load()?;
+
+    let sem = Arc::new(Semaphore::new(4));
+    let mut tasks = JoinSet::new();
+    for (i, (source, config)) in entries.iter().enumerate() {
+        let installed = receipts
+            .packages
+            .get(&package_name(source, config))
+            .cloned();
+        let (source, config, sem) = (source.clone(), config.clone(), sem.clone());
+        tasks.spawn(async move {
+            let _permit = sem.acquire().await.expect("semaphore closed");
+            let res: Result<Option<Output>> = async {
+                let Some(reason) = install_reason(&source, &config, installed.as_ref()).await?
+                else {
+                    return Ok(None);
+                };
+                if !config.quiet {
+                    println!("Installing {source} ({reason})");
+                }
+                install(&source, &config).await.map(Some)
+            }
+            .await;
+            (i, res)
+        });
+    }
+
+    let mut results: Vec<Option<Result<Option<Output>>>> = entries.iter().map(|_| None).collect();
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok((i, res)) => results[i] = Some(res),
+            Err(e) => eprintln!("Warning: {e}"),
+        }
+    }
+
+    // Receipts are written one package at a time, after all installs finished.
+    let (mut installed, mut current, mut failed) = (0, 0, vec![]);
+    let mut names = HashSet::new();
+    for ((source, config), res) in entries.iter().zip(results) {
+        let name = package_name(source, config);
+        match res.unwrap_or_else(|| Err(anyhow::anyhow!("sync aborted"))) {
+            Ok(Some(output)) => {
+                record_install(source, config, &output);
+                installed += 1;
+            }
+            Ok(None) => current += 1,
+            Err(e) => {
+                if !config.quiet {
+                    eprintln!("Failed to sync {source}: {e:?}");
+                }
+                failed.push(source.clone());
+            }
+        }
+        names.insert(name);
+    }
+
+    // Remember which packages this toolfile owns, so `--prune` never
+    // touches tools installed by hand or by another project.
+    let receipts = {
+        let _lock = Receipts::lock(config.quiet)?;
+        let mut receipts = Receipts::load()?;
+        for (name, receipt) in receipts.packages.iter_mut() {
+            if names.contains(name) {
+                receipt.toolfile = Some(toolfile_path.clone());
+            }
+        }
+        receipts.save()?;
+        receipts
+    };
+
+    let mut removed = 0;
+    if prune {
+        let stale: Vec<String> = receipts
+            .packages
+            .iter()
+            .filter(|(name, r)| {
+                !names.contains(*name) && r.toolfile.as_deref() == Some(toolfile_path.as_str())
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            match uninstall(&name, false, config.quiet) {
+                Ok(()) => removed += 1,
+                Err(e) => {
+                    if !config.quiet {
+                        eprintln!("Failed to remove {name}: {e:?}");
+                    }
+                    failed.push(name);
+                }
+            }
+        }
+    }
+
+    if !config.quiet {
+        println!("{installed} installed, {current} up to date, {removed} removed");
+    }
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to sync: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Toolfile;
+
+    #[test]
+    fn test_toolfile_source() {
+        let toolfile: Toolfile = toml::from_str(
+            r#"
+            [packages."BurntSushi/ripgrep"]
+            tag = "14.1.0"
+
+            [packages."quickjs-ng/quickjs"]
+            name = ["qjs"]
+            strip = true
+
+            [packages."https://example.com/tool.tar.gz"]
+            tag = "v1"
+            "#,
+        )
+        .unwrap();
+        let source = |key: &str| toolfile.packages[key].source(key);
+        assert_eq!(
+            source("BurntSushi/ripgrep").unwrap(),
+            "BurntSushi/ripgrep@14.1.0"
+        );
+        assert_eq!(source("quickjs-ng/quickjs").unwrap(), "quickjs-ng/quickjs");
+        assert!(source("https://example.com/tool.tar.gz").is_err());
+
+        assert!(toml::from_str::<Toolfile>("[packages.fd]\nversion = \"1\"").is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::InstallConfig;
-use crate::artifact::{GhArtifact, GhArtifacts};
+use crate::artifact::{ArtifactUrl, GhArtifact, GhArtifacts};
 use crate::download::{download, download_dist_manfiest, download_json};
 use crate::manfiest::DistManifest;
 use crate::tool::get_artifact_url;
@@ -61,6 +61,16 @@ static RE_NIGHTLY_ASSETS: LazyLock<Regex> = LazyLock::new(|| {
     .unwrap()
 });
 
+/// A link entry of an archive, recreated as a link instead of written from
+/// content.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub(crate) enum Link {
+    /// Target as stored in the archive, usually relative to the link.
+    Symlink(String),
+    /// Entry path of the linked file in the same archive.
+    Hardlink(String),
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Default)]
 pub(crate) struct OutputFile {
     pub(crate) install_path: String,
@@ -68,12 +78,16 @@ pub(crate) struct OutputFile {
     pub(crate) size: u64,
     pub(crate) origin_path: String,
     pub(crate) is_dir: bool,
-    pub(crate) buffer: Vec<u8>,
+    pub(crate) link: Option<Link>,
 }
 #[derive(Debug, Clone, PartialEq, Default)]
 pub(crate) struct OutputItem {
     pub(crate) install_dir: String,
     pub(crate) files: Vec<OutputFile>,
+    /// Release tag the files were installed from, when known.
+    pub(crate) tag: Option<String>,
+    /// Version from the metadata of a distribution package.
+    pub(crate) version: Option<String>,
 }
 
 pub(crate) type Output = HashMap<String, OutputItem>;
@@ -239,6 +253,27 @@ impl Repo {
         }
     }
 
+    /// Tag of the latest release, preferring the releases API and falling
+    /// back to the releases page.
+    pub(crate) async fn get_latest_release_tag(&self, config: &InstallConfig) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Release {
+            tag_name: String,
+        }
+        let api = Repo {
+            tag: None,
+            ..self.clone()
+        }
+        .get_artifact_api();
+        match download_json::<Release>(&api, config).await {
+            Ok(release) => Ok(release.tag_name),
+            Err(e) => {
+                trace!("latest release api failed for {}: {}", self, e);
+                self.get_latest_tag(config.retry, config.timeout).await
+            }
+        }
+    }
+
     fn parse_latest_tag(html: &str) -> Result<String> {
         if let Some(cap) = RE_LATEST_TAG.captures(html) {
             let tag = cap[1].to_string();
@@ -279,22 +314,25 @@ impl Repo {
         ))
     }
 
-    async fn get_release_page_url(&self, retry: usize, timeout: u64) -> Result<String> {
+    async fn get_release_tag(&self, retry: usize, timeout: u64) -> Result<String> {
         match &self.tag {
-            Some(t) => Ok(format!(
-                "https://github.com/{}/{}/releases/expanded_assets/{}",
-                self.owner, self.name, t
-            )),
-            None => {
-                let tag = self.get_latest_tag(retry, timeout).await?;
-                Ok(format!(
-                    "https://github.com/{}/{}/releases/expanded_assets/{}",
-                    self.owner, self.name, tag
-                ))
-            }
+            Some(t) => Ok(t.clone()),
+            None => self.get_latest_tag(retry, timeout).await,
         }
     }
 
+    fn get_expanded_assets_url(&self, tag: &str) -> String {
+        format!(
+            "https://github.com/{}/{}/releases/expanded_assets/{}",
+            self.owner, self.name, tag
+        )
+    }
+
+    async fn get_release_page_url(&self, retry: usize, timeout: u64) -> Result<String> {
+        let tag = self.get_release_tag(retry, timeout).await?;
+        Ok(self.get_expanded_assets_url(&tag))
+    }
+
     fn parse_release_html(html: &str) -> Result<GhArtifacts> {
         let mut assets = HashSet::new();
 
@@ -312,6 +350,7 @@ impl Repo {
                 name,
                 browser_download_url,
                 url: None,
+                digest: None,
             });
         }
 
@@ -319,28 +358,21 @@ impl Repo {
             return Err(anyhow::anyhow!("No assets found in release page HTML"));
         }
 
-        Ok(GhArtifacts { assets })
+        Ok(GhArtifacts {
+            assets,
+            tag_name: None,
+        })
     }
 
-    pub(crate) async fn get_manfiest(
-        &self,
-        retry: usize,
-        proxy: Proxy,
-        timeout: u64,
-    ) -> Result<DistManifest> {
-        download_dist_manfiest(
-            &self.get_manfiest_url(proxy, retry, timeout).await?,
-            retry,
-            timeout,
-        )
-        .await
+    pub(crate) async fn get_manfiest(&self, config: &InstallConfig) -> Result<DistManifest> {
+        let url = self
+            .get_manfiest_url(config.proxy, config.retry, config.timeout)
+            .await?;
+        download_dist_manfiest(&url, config).await
     }
 
     #[allow(dead_code)]
-    async fn get_artifact_url_from_html(
-        &self,
-        config: &InstallConfig,
-    ) -> Result<Vec<(String, String)>> {
+    async fn get_artifact_url_from_html(&self, config: &InstallConfig) -> Result<Vec<ArtifactUrl>> {
         let page_url = self
             .get_release_page_url(config.retry, config.timeout)
             .await?;
@@ -357,22 +389,19 @@ impl Repo {
     pub(crate) async fn get_artifact_url(
         &self,
         config: &InstallConfig,
-    ) -> Result<Vec<(String, String)>> {
-        let artifacts = self.get_raw_artifacts(config.retry, config.timeout).await?;
+    ) -> Result<Vec<ArtifactUrl>> {
+        let artifacts = self.get_raw_artifacts(config).await?;
         get_artifact_url(artifacts, config)
     }
 
     /// Fetch raw (unfiltered) release artifacts from the GitHub API, with
     /// HTML fallback. Useful for diagnostics when no platform match is found.
-    pub(crate) async fn get_raw_artifacts(
-        &self,
-        retry: usize,
-        timeout: u64,
-    ) -> Result<GhArtifacts> {
+    pub(crate) async fn get_raw_artifacts(&self, config: &InstallConfig) -> Result<GhArtifacts> {
+        let (retry, timeout) = (config.retry, config.timeout);
         let api = self.get_artifact_api();
         trace!("get_raw_artifacts api {}", api);
 
-        match download_json::<GhArtifacts>(&api, retry, timeout).await {
+        match download_json::<GhArtifacts>(&api, config).await {
             Ok(artifacts) => {
                 trace!(
                     "Successfully retrieved artifacts from API for {}/{}",
@@ -386,15 +415,18 @@ impl Repo {
                     self.owner, self.name, api_error
                 );
 
-                let page_url = self.get_release_page_url(retry, timeout).await?;
+                let tag = self.get_release_tag(retry, timeout).await?;
+                let page_url = self.get_expanded_assets_url(&tag);
                 let response = download(&page_url, retry, timeout).await?;
                 let html = response.text().await?;
-                Self::parse_release_html(&html).map_err(|html_error| {
+                let mut artifacts = Self::parse_release_html(&html).map_err(|html_error| {
                     anyhow::anyhow!(
                         "Failed to retrieve artifacts for {}/{}. API error: {}. HTML parsing error: {}",
                         self.owner, self.name, api_error, html_error
                     )
-                })
+                })?;
+                artifacts.tag_name = Some(tag);
+                Ok(artifacts)
             }
         }
     }
@@ -433,16 +465,20 @@ impl Nightly {
                 name,
                 browser_download_url,
                 url: None,
+                digest: None,
             });
         }
 
-        Ok(GhArtifacts { assets })
+        Ok(GhArtifacts {
+            assets,
+            tag_name: None,
+        })
     }
     #[allow(dead_code)]
     pub(crate) async fn get_artifact_url(
         &self,
         config: &InstallConfig,
-    ) -> Result<Vec<(String, String)>> {
+    ) -> Result<Vec<ArtifactUrl>> {
         let artifacts = self.get_raw_artifacts(config.retry, config.timeout).await?;
         get_artifact_url(artifacts, config)
     }
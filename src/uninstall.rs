@@ -0,0 +1,173 @@
+use crate::env::{get_install_dir, remove_from_path};
+use crate::receipt::{Receipt, Receipts};
+use crate::types::OutputFile;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Remove the files an installation created and the PATH entries no other
+/// installed package still needs.
+pub(crate) fn uninstall(name: &str, force: bool, quiet: bool) -> Result<()> {
+    let _lock = Receipts::lock(quiet)?;
+    let mut receipts = Receipts::load()?;
+    let key = receipts
+        .find(name)
+        .with_context(|| format!("{name} is not installed"))?;
+    let receipt = receipts
+        .packages
+        .remove(&key)
+        .context("receipt disappeared")?;
+
+    if !force {
+        let modified: Vec<&str> = receipt
+            .files()
+            .filter(|f| f.is_modified())
+            .map(|f| f.path.as_str())
+            .collect();
+        if !modified.is_empty() {
+            anyhow::bail!(
+                "Refusing to uninstall {key}: files were modified after installation\n  {}\n  Use --force to remove them anyway.",
+                modified.join("\n  ")
+            );
+        }
+    }
+
+    // A later installation may have written to the same path; leave those
+    // files to the package that owns them now.
+    let shared: HashSet<&str> = receipts
+        .packages
+        .values()
+        .flat_map(|r| r.files())
+        .map(|f| f.path.as_str())
+        .collect();
+
+    let home = get_install_dir()?;
+    let mut removed = 0;
+    for asset in &receipt.assets {
+        let install_dir = Path::new(&asset.install_dir);
+        for f in &asset.files {
+            if shared.contains(f.path.as_str()) {
+                continue;
+            }
+            match std::fs::remove_file(&f.path) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Failed to remove {}", f.path)),
+            }
+            prune_empty_dirs(Path::new(&f.path), install_dir, &home);
+        }
+    }
+
+    for entry in &receipt.path {
+        let other = receipts
+            .packages
+            .values_mut()
+            .flat_map(|r| r.path.iter_mut())
+            .find(|e| e.dir == entry.dir);
+        match other {
+            // Still needed: hand over responsibility for removing it later.
+            Some(other) if entry.added && !other.added => {
+                other.added = true;
+                other.shell = entry.shell.clone();
+            }
+            Some(_) => {}
+            None if entry.added => {
+                if let Err(e) = remove_from_path(&entry.dir, entry.shell.as_deref(), quiet)
+                    && !quiet
+                {
+                    eprintln!("Warning: failed to remove {} from $PATH: {e:?}", entry.dir);
+                }
+            }
+            None => {}
+        }
+    }
+
+    receipts.save()?;
+    if !quiet {
+        println!("Uninstalled {key} ({removed} files removed)");
+    }
+    Ok(())
+}
+
+/// Remove the files of `old`, an earlier receipt of a reinstalled package,
+/// that no current receipt lists, e.g. files an update no longer ships.
+/// Files changed since they were installed are kept.
+pub(crate) fn remove_stale_files(old: &Receipt, quiet: bool) -> Result<usize> {
+    let _lock = Receipts::lock(quiet)?;
+    let receipts = Receipts::load()?;
+    let current: HashSet<&str> = receipts
+        .packages
+        .values()
+        .flat_map(|r| r.files())
+        .map(|f| f.path.as_str())
+        .collect();
+
+    let home = get_install_dir()?;
+    let mut removed = 0;
+    for asset in &old.assets {
+        let install_dir = Path::new(&asset.install_dir);
+        for f in asset
+            .files
+            .iter()
+            .filter(|f| !current.contains(f.path.as_str()))
+        {
+            if f.is_modified() {
+                if !quiet {
+                    eprintln!("Keeping {}: modified after installation", f.path);
+                }
+                continue;
+            }
+            match std::fs::remove_file(&f.path) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Failed to remove {}", f.path)),
+            }
+            prune_empty_dirs(Path::new(&f.path), install_dir, &home);
+        }
+    }
+    Ok(removed)
+}
+
+/// Files under `install_dir` of the earlier installation that `files`
+/// replace, i.e. of the packages whose receipts list any of them, which
+/// `files` no longer include. Files another package lists or that were
+/// modified after installation are left out.
+pub(crate) fn replaced_files(files: &[OutputFile], install_dir: &Path) -> Vec<PathBuf> {
+    let Ok(receipts) = Receipts::load() else {
+        return vec![];
+    };
+    let new: HashSet<&str> = files.iter().map(|f| f.install_path.as_str()).collect();
+    let (old, others): (Vec<&Receipt>, Vec<&Receipt>) = receipts
+        .packages
+        .values()
+        .partition(|r| r.files().any(|f| new.contains(f.path.as_str())));
+    let shared: HashSet<&str> = others
+        .iter()
+        .flat_map(|r| r.files())
+        .map(|f| f.path.as_str())
+        .collect();
+    old.iter()
+        .flat_map(|r| &r.assets)
+        .filter(|a| Path::new(&a.install_dir) == install_dir)
+        .flat_map(|a| &a.files)
+        .filter(|f| !new.contains(f.path.as_str()) && !shared.contains(f.path.as_str()))
+        .filter(|f| Path::new(&f.path).starts_with(install_dir))
+        .filter(|f| Path::new(&f.path).symlink_metadata().is_ok() && !f.is_modified())
+        .map(|f| PathBuf::from(&f.path))
+        .collect()
+}
+
+/// Remove now-empty directories between `file` and `install_dir`. The
+/// install directory itself is only removed when ei created it under its
+/// home directory, never when it is a user-supplied `--dir`.
+fn prune_empty_dirs(file: &Path, install_dir: &Path, home: &Path) {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        let removable = d.starts_with(install_dir)
+            && (d != install_dir || (install_dir.starts_with(home) && install_dir != home));
+        if !removable || std::fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
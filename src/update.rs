@@ -0,0 +1,219 @@
+use crate::config::PersistentConfig;
+use crate::receipt::{Receipt, Receipts};
+use crate::tool::{print_table, replace_version};
+use crate::types::Repo;
+use crate::uninstall::remove_stale_files;
+use crate::{InstallConfig, ei};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// An installed GitHub package and the latest release tag found for it.
+struct Status {
+    name: String,
+    repo: Repo,
+    installed: Option<String>,
+    latest: Result<String>,
+}
+
+impl Status {
+    fn is_outdated(&self) -> bool {
+        matches!(&self.latest, Ok(latest) if self.installed.as_deref() != Some(latest.as_str()))
+    }
+}
+
+/// Installed packages whose source is a GitHub repository. With `names`,
+/// exactly those packages, failing on any that is not from GitHub.
+fn github_packages<'a>(
+    receipts: &'a Receipts,
+    names: &[String],
+) -> Result<Vec<(String, &'a Receipt, Repo)>> {
+    let keys: Vec<String> = if names.is_empty() {
+        receipts.packages.keys().cloned().collect()
+    } else {
+        names
+            .iter()
+            .map(|n| {
+                receipts
+                    .find(n)
+                    .with_context(|| format!("{n} is not installed"))
+            })
+            .collect::<Result<_>>()?
+    };
+
+    let mut v = vec![];
+    for key in keys {
+        let receipt = &receipts.packages[&key];
+        match receipt.repo.as_deref().map(Repo::try_from) {
+            Some(Ok(repo)) => v.push((key, receipt, repo)),
+            _ if !names.is_empty() => {
+                anyhow::bail!("{key} was not installed from a GitHub repository")
+            }
+            _ => {}
+        }
+    }
+    Ok(v)
+}
+
+/// Look up the latest release of every package concurrently.
+async fn check(packages: &[(String, &Receipt, Repo)], config: &InstallConfig) -> Vec<Status> {
+    let sem = Arc::new(Semaphore::new(4));
+    let mut tasks = JoinSet::new();
+    for (i, (_, _, repo)) in packages.iter().enumerate() {
+        let repo = repo.clone();
+        let sem = sem.clone();
+        let config = config.clone();
+        tasks.spawn(async move {
+            let _permit = sem.acquire().await.expect("semaphore closed");
+            (i, repo.get_latest_release_tag(&config).await)
+        });
+    }
+
+    let mut latest: Vec<Option<Result<String>>> = packages.iter().map(|_| None).collect();
+    while let Some(res) = tasks.join_next().await {
+        match res {
+            Ok((i, tag)) => latest[i] = Some(tag),
+            Err(e) => eprintln!("Warning: {e}"),
+        }
+    }
+
+    packages
+        .iter()
+        .zip(latest)
+        .map(|((name, receipt, repo), latest)| Status {
+            name: name.clone(),
+            repo: repo.clone(),
+            installed: receipt.tag.clone(),
+            latest: latest.unwrap_or_else(|| Err(anyhow::anyhow!("check aborted"))),
+        })
+        .collect()
+}
+
+/// Print installed vs latest tags; fails when any package is out of date.
+pub(crate) async fn outdated(config: &InstallConfig) -> Result<()> {
+    let receipts = Receipts::load()?;
+    let packages = github_packages(&receipts, &[])?;
+    if packages.is_empty() {
+        if !config.quiet {
+            println!("No GitHub packages installed");
+        }
+        return Ok(());
+    }
+    let statuses = check(&packages, config).await;
+
+    if !config.quiet {
+        let rows: Vec<[String; 4]> = statuses
+            .iter()
+            .map(|s| {
+                let (latest, state) = match &s.latest {
+                    Ok(t) if s.is_outdated() => (t.clone(), "outdated".to_string()),
+                    Ok(t) => (t.clone(), "up to date".to_string()),
+                    Err(e) => ("-".to_string(), format!("error: {e}")),
+                };
+                [
+                    s.name.clone(),
+                    s.installed.clone().unwrap_or_else(|| "-".to_string()),
+                    latest,
+                    state,
+                ]
+            })
+            .collect();
+        print_table(["NAME", "INSTALLED", "LATEST", "STATUS"], &rows);
+    }
+
+    let count = statuses.iter().filter(|s| s.is_outdated()).count();
+    if count > 0 {
+        anyhow::bail!("{count} package(s) outdated");
+    }
+    Ok(())
+}
+
+/// The source to update `receipt` from. A release asset URL moves to the
+/// same asset of release `latest`; anything else installs the latest
+/// release of `owner/repo`.
+fn update_source(receipt: &Receipt, repo: &Repo, latest: &str) -> String {
+    let prefix = format!(
+        "https://github.com/{}/{}/releases/download/",
+        repo.owner, repo.name
+    );
+    if let Some(rest) = receipt.source.strip_prefix(&prefix)
+        && let Some((tag, filename)) = rest.split_once('/')
+        && !filename.contains('/')
+    {
+        return format!(
+            "{prefix}{latest}/{}",
+            replace_version(filename, tag, latest)
+        );
+    }
+    format!("{}/{}", repo.owner, repo.name)
+}
+
+/// Reinstall outdated packages with the options they were installed with.
+pub(crate) async fn update(names: &[String], config: &InstallConfig) -> Result<()> {
+    let receipts = Receipts::load()?;
+    let packages = github_packages(&receipts, names)?;
+    let statuses = check(&packages, config).await;
+    let presets = PersistentConfig::load();
+
+    let mut failed = vec![];
+    for (status, (_, receipt, _)) in statuses.iter().zip(&packages) {
+        let latest = match &status.latest {
+            Err(e) => {
+                if !config.quiet {
+                    eprintln!("Failed to check {}: {e}", status.name);
+                }
+                failed.push(status.name.clone());
+                continue;
+            }
+            Ok(_) if !status.is_outdated() => {
+                if !config.quiet {
+                    println!("{} is up to date", status.name);
+                }
+                continue;
+            }
+            Ok(latest) => {
+                if !config.quiet {
+                    println!(
+                        "Updating {} {} -> {latest}",
+                        status.name,
+                        status.installed.as_deref().unwrap_or("-")
+                    );
+                }
+                latest
+            }
+        };
+
+        let source = update_source(receipt, &status.repo, latest);
+        let package_config = receipt
+            .options
+            .apply(config.clone())
+            .with_preset(presets.package(&source));
+        if let Err(e) = ei(&source, &package_config).await {
+            if !config.quiet {
+                eprintln!("Failed to update {}: {e:?}", status.name);
+            }
+            failed.push(status.name.clone());
+            continue;
+        }
+        match remove_stale_files(receipt, config.quiet) {
+            Ok(n) if n > 0 && !config.quiet => {
+                println!("Removed {n} files {} no longer ships", status.name)
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if !config.quiet {
+                    eprintln!(
+                        "Warning: failed to remove old files of {}: {e:?}",
+                        status.name
+                    );
+                }
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to update: {}", failed.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,407 @@
+use crate::InstallConfig;
+use crate::artifact::{ArtifactUrl, GhArtifact, GhArtifacts};
+use crate::download::get_bytes;
+use crate::tool::get_filename;
+use anyhow::{Context, Result};
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+use tracing::trace;
+
+/// Suffixes of checksum files published for a single asset.
+const CHECKSUM_SUFFIXES: [&str; 7] = [
+    ".sha256",
+    ".sha256sum",
+    ".sha512",
+    ".sha512sum",
+    ".md5",
+    ".md5sum",
+    ".sum",
+];
+
+/// Suffixes of signatures and keys, which may share a checksum file's name
+/// (`checksums.txt.sig`) but are not checksum lists themselves.
+const SIGNATURE_SUFFIXES: [&str; 5] = [".sig", ".asc", ".minisig", ".pem", ".pub"];
+
+/// Whether `name` is a combined checksum list such as `checksums.txt`,
+/// `SHA256SUMS`, `MD5SUMS` or `tool_1.0.0_checksums.txt`, rather than the
+/// per-file checksum of another of `assets` (`tool-aarch64.tar.gz.sha256sum`).
+fn is_checksum_list(assets: &HashSet<GhArtifact>, name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    if SIGNATURE_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+        return false;
+    }
+    let per_file = CHECKSUM_SUFFIXES.iter().any(|s| {
+        name.strip_suffix(s)
+            .is_some_and(|stem| assets.iter().any(|a| a.name.eq_ignore_ascii_case(stem)))
+    });
+    if per_file {
+        return false;
+    }
+    ["checksum", "sha256sum", "sha512sum", "md5sum"]
+        .iter()
+        .any(|s| name.contains(s))
+}
+
+/// Find the checksum asset covering `filename`: a per-file checksum
+/// (`<filename>.sha256`, ...) if one exists, otherwise a combined list,
+/// preferring SHA-256 lists and using MD5 lists last.
+pub(crate) fn find_checksum_asset<'a>(
+    assets: &'a HashSet<GhArtifact>,
+    filename: &str,
+) -> Option<&'a GhArtifact> {
+    for suffix in CHECKSUM_SUFFIXES {
+        let name = format!("{filename}{suffix}");
+        if let Some(a) = assets.iter().find(|a| a.name.eq_ignore_ascii_case(&name)) {
+            return Some(a);
+        }
+    }
+
+    assets
+        .iter()
+        .filter(|a| is_checksum_list(assets, &a.name))
+        .min_by_key(|a| {
+            let lower = a.name.to_ascii_lowercase();
+            let priority = if lower.contains("sha256") {
+                0
+            } else if lower.contains("md5") {
+                3
+            } else if lower.contains("sha512") {
+                2
+            } else {
+                1
+            };
+            (priority, a.name.clone())
+        })
+}
+
+fn is_hex_digest(s: &str) -> bool {
+    matches!(s.len(), 32 | 64 | 128) && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_same_file(listed: &str, filename: &str) -> bool {
+    get_filename(listed.trim()) == filename
+}
+
+/// Look up the SHA-256/SHA-512/MD5 hash of `filename` in a checksum file.
+///
+/// Understands GNU (`<hex>  name`, `<hex> *name`) and BSD
+/// (`SHA256 (name) = <hex>`) lines, and files holding a single bare hash.
+pub(crate) fn parse_checksum(content: &str, filename: &str) -> Option<String> {
+    let mut bare = None;
+    let mut lines = 0;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        lines += 1;
+
+        if let Some((head, hex)) = line.split_once(") = ")
+            && let Some((_, name)) = head.split_once(" (")
+        {
+            let hex = hex.trim();
+            if is_hex_digest(hex) && is_same_file(name, filename) {
+                return Some(hex.to_ascii_lowercase());
+            }
+            continue;
+        }
+
+        let (hex, name) = match line.split_once(char::is_whitespace) {
+            Some((hex, name)) => (hex, name.trim_start().trim_start_matches('*')),
+            None => (line, ""),
+        };
+        if !is_hex_digest(hex) {
+            continue;
+        }
+        if name.is_empty() {
+            bare = Some(hex.to_ascii_lowercase());
+        } else if is_same_file(name, filename) {
+            return Some(hex.to_ascii_lowercase());
+        }
+    }
+
+    if lines == 1 { bare } else { None }
+}
+
+fn algorithm(hex: &str) -> &'static str {
+    match hex.len() {
+        32 => "md5",
+        128 => "sha512",
+        _ => "sha256",
+    }
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn hash_reader<D: Digest + std::io::Write>(mut reader: impl Read) -> Result<String> {
+    let mut hasher = D::new();
+    std::io::copy(&mut reader, &mut hasher).context("Failed to read content to hash")?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// SHA-256 of a file, read in chunks.
+pub(crate) fn sha256_file(path: &Path) -> Result<String> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    hash_reader::<Sha256>(file)
+}
+
+/// Hash everything `reader` yields with the algorithm matching the length
+/// of `expected` and compare the two.
+pub(crate) fn check_digest(reader: impl Read, expected: &str) -> Result<()> {
+    let actual = match algorithm(expected) {
+        "sha512" => hash_reader::<Sha512>(reader)?,
+        "md5" => hash_reader::<Md5>(reader)?,
+        _ => hash_reader::<Sha256>(reader)?,
+    };
+    if !actual.eq_ignore_ascii_case(expected) {
+        anyhow::bail!(
+            "{} mismatch\n  expected: {}\n  actual:   {}",
+            algorithm(expected),
+            expected.to_ascii_lowercase(),
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Parse a `--sha256` value: 64 hex digits, normalised to lowercase.
+pub(crate) fn parse_sha256(s: &str) -> Result<String, String> {
+    let s = s.trim().trim_start_matches("sha256:");
+    if s.len() == 64 && is_hex_digest(s) {
+        Ok(s.to_ascii_lowercase())
+    } else {
+        Err("expected 64 hexadecimal digits".to_string())
+    }
+}
+
+/// Split a GitHub asset digest (`sha256:<hex>`) into its hex part, if it
+/// uses an algorithm ei can check.
+pub(crate) fn parse_digest(digest: &str) -> Option<&str> {
+    let (algo, hex) = digest.split_once(':')?;
+    let len = match algo.to_ascii_lowercase().as_str() {
+        "sha256" => 64,
+        "sha512" => 128,
+        _ => return None,
+    };
+    (hex.len() == len && is_hex_digest(hex)).then_some(hex)
+}
+
+/// Tell the user when a release listing carries no digests, e.g. because
+/// it came from the HTML fallback or nightly.link.
+pub(crate) fn report_missing_digests(
+    artifacts: &GhArtifacts,
+    source: &str,
+    config: &InstallConfig,
+) {
+    if config.no_verify || config.quiet || artifacts.assets.is_empty() {
+        return;
+    }
+    if artifacts.assets.iter().all(|a| a.digest.is_none()) {
+        eprintln!(
+            "Note: no asset digests available for {source}; only published checksum files can be verified"
+        );
+    }
+}
+
+pub(crate) fn check_file_digest(path: &Path, expected: &str) -> Result<()> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    check_digest(file, expected)
+}
+
+/// Verify a downloaded file against the digest GitHub reports for the asset
+/// and the checksum asset published with it. Artifacts with neither are
+/// accepted as-is, but a checksum file that doesn't cover the artifact is an
+/// error.
+pub(crate) async fn verify_artifact(
+    path: &Path,
+    art: &ArtifactUrl,
+    config: &InstallConfig,
+) -> Result<()> {
+    if config.no_verify {
+        return Ok(());
+    }
+    let filename = get_filename(&art.url);
+
+    if let Some(digest) = &art.digest {
+        match parse_digest(digest) {
+            Some(expected) => {
+                check_file_digest(path, expected).with_context(|| {
+                    format!("Digest verification failed for {filename} (GitHub asset digest)")
+                })?;
+                if !config.quiet {
+                    println!(
+                        "{} verified {filename} (GitHub digest)",
+                        algorithm(expected)
+                    );
+                }
+            }
+            None if !config.quiet => {
+                eprintln!("Warning: unsupported digest {digest} for {filename}, skipping it");
+            }
+            None => {}
+        }
+    }
+
+    let Some(checksum_url) = &art.checksum_url else {
+        trace!("no checksum asset for {}", art.url);
+        return Ok(());
+    };
+    let content = get_bytes(checksum_url, config).await.with_context(|| {
+        format!("Failed to download checksum file {checksum_url} (use --no-verify to skip)")
+    })?;
+    let Some(expected) = parse_checksum(&String::from_utf8_lossy(&content), &filename) else {
+        anyhow::bail!("No checksum for {filename} in {checksum_url} (use --no-verify to skip)");
+    };
+
+    check_file_digest(path, &expected)
+        .with_context(|| format!("Checksum verification failed for {filename} ({checksum_url})"))?;
+    if !config.quiet {
+        println!("{} verified {filename}", algorithm(&expected));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_digest, find_checksum_asset, parse_checksum, parse_digest, parse_sha256};
+    use crate::artifact::GhArtifact;
+    use std::collections::HashSet;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_parse_checksum() {
+        let gnu = format!(
+            "{HELLO_SHA256}  ei-x86_64.tar.gz\n{}  other.zip\n",
+            "0".repeat(64)
+        );
+        assert_eq!(
+            parse_checksum(&gnu, "ei-x86_64.tar.gz").as_deref(),
+            Some(HELLO_SHA256)
+        );
+        assert_eq!(parse_checksum(&gnu, "missing.zip"), None);
+
+        let binary = format!("{} *./dist/ei.zip\n", HELLO_SHA256.to_uppercase());
+        assert_eq!(
+            parse_checksum(&binary, "ei.zip").as_deref(),
+            Some(HELLO_SHA256)
+        );
+
+        let bsd = format!("SHA256 (ei.zip) = {HELLO_SHA256}\n");
+        assert_eq!(
+            parse_checksum(&bsd, "ei.zip").as_deref(),
+            Some(HELLO_SHA256)
+        );
+
+        let bare = format!("{HELLO_SHA256}\n");
+        assert_eq!(
+            parse_checksum(&bare, "ei.zip").as_deref(),
+            Some(HELLO_SHA256)
+        );
+
+        let md5 = "5d41402abc4b2a76b9719d911017c592  ei.zip";
+        assert_eq!(
+            parse_checksum(md5, "ei.zip").as_deref(),
+            Some("5d41402abc4b2a76b9719d911017c592")
+        );
+        assert_eq!(parse_checksum("5d41402abc4b  ei.zip", "ei.zip"), None);
+    }
+
+    #[test]
+    fn test_check_digest() {
+        assert!(check_digest(&b"hello"[..], HELLO_SHA256).is_ok());
+        assert!(check_digest(&b"hello"[..], &HELLO_SHA256.to_uppercase()).is_ok());
+        assert!(check_digest(&b"hello!"[..], HELLO_SHA256).is_err());
+        assert!(check_digest(&b"hello"[..], "5d41402abc4b2a76b9719d911017c592").is_ok());
+        assert!(check_digest(&b"hello!"[..], "5d41402abc4b2a76b9719d911017c592").is_err());
+    }
+
+    #[test]
+    fn test_parse_sha256() {
+        let upper = HELLO_SHA256.to_uppercase();
+        assert_eq!(parse_sha256(&upper).as_deref(), Ok(HELLO_SHA256));
+        let prefixed = format!("sha256:{HELLO_SHA256}");
+        assert_eq!(parse_sha256(&prefixed).as_deref(), Ok(HELLO_SHA256));
+        assert!(parse_sha256("abc").is_err());
+        assert!(parse_sha256(&"g".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let digest = format!("sha256:{HELLO_SHA256}");
+        assert_eq!(parse_digest(&digest), Some(HELLO_SHA256));
+        assert_eq!(parse_digest("sha256:abc"), None);
+        assert_eq!(parse_digest("md5:5d41402abc4b2a76b9719d911017c592"), None);
+        assert_eq!(parse_digest(HELLO_SHA256), None);
+    }
+
+    #[test]
+    fn test_find_checksum_asset() {
+        let assets = |names: &[&str]| -> HashSet<GhArtifact> {
+            names
+                .iter()
+                .map(|n| GhArtifact {
+                    name: n.to_string(),
+                    browser_download_url: format!("https://example.com/{n}"),
+                    url: None,
+                    digest: None,
+                })
+                .collect()
+        };
+        let find = |set: &HashSet<GhArtifact>, f: &str| {
+            find_checksum_asset(set, f).map(|a| a.name.clone())
+        };
+
+        let set = assets(&["ei.tar.gz", "ei.tar.gz.sha256", "checksums.txt"]);
+        assert_eq!(find(&set, "ei.tar.gz").as_deref(), Some("ei.tar.gz.sha256"));
+
+        let set = assets(&[
+            "ei.tar.gz",
+            "checksums.txt",
+            "checksums.txt.sig",
+            "SHA256SUMS",
+        ]);
+        assert_eq!(find(&set, "ei.tar.gz").as_deref(), Some("SHA256SUMS"));
+
+        let set = assets(&["ei.tar.gz", "ei.tar.gz.md5", "SHA256SUMS"]);
+        assert_eq!(find(&set, "ei.tar.gz").as_deref(), Some("ei.tar.gz.md5"));
+
+        let set = assets(&["ei.tar.gz", "ei.tar.gz.sum"]);
+        assert_eq!(find(&set, "ei.tar.gz").as_deref(), Some("ei.tar.gz.sum"));
+
+        let set = assets(&["ei.tar.gz", "MD5SUMS", "checksums.txt"]);
+        assert_eq!(find(&set, "ei.tar.gz").as_deref(), Some("checksums.txt"));
+
+        let set = assets(&["ei.tar.gz", "ei.zip"]);
+        assert_eq!(find(&set, "ei.tar.gz"), None);
+
+        let set = assets(&[
+            "ei-x86_64.tar.gz",
+            "ei-aarch64.tar.gz",
+            "ei-aarch64.tar.gz.sha256sum",
+        ]);
+        assert_eq!(find(&set, "ei-x86_64.tar.gz"), None);
+
+        let set = assets(&[
+            "ei-x86_64.tar.gz",
+            "ei-aarch64.tar.gz",
+            "ei-aarch64.tar.gz.sha256sum",
+            "sha256sums.txt",
+        ]);
+        assert_eq!(
+            find(&set, "ei-x86_64.tar.gz").as_deref(),
+            Some("sha256sums.txt")
+        );
+    }
+}